@@ -2,11 +2,22 @@ use proc_macro::TokenStream;
 use quote::quote;
 use syn::parse::{Parse, ParseStream};
 use syn::punctuated::Punctuated;
-use syn::{bracketed, parenthesized, parse_macro_input, Ident, LitStr, Result, Token};
+use syn::{bracketed, parenthesized, parse_macro_input, Ident, LitStr, Path, Result, Token};
 
+/// A subcommand, written as `(name, "description", options...)`. The options are:
+/// * `feature = "..."`: the cargo feature which the subcommand is compiled with
+/// * `aliases = ["...", ...]`: other names the subcommand can be invoked by
+/// * `hidden`: leave the subcommand out of the help
+/// * `args = Type`: a clap-derive `Subcommand` enum in the subcommand's module, which describes
+///   its arguments instead of a `command_extension` function. The module's `run` function then
+///   receives the parsed enum instead of the `ArgMatches`.
 struct Definition {
     name: Ident,
     description: LitStr,
+    feature: Option<LitStr>,
+    aliases: Vec<LitStr>,
+    hidden: bool,
+    args: Option<Path>,
 }
 
 impl Parse for Definition {
@@ -18,7 +29,46 @@ impl Parse for Definition {
         let _comma_token: Token![,] = content.parse()?;
         let description = content.parse()?;
 
-        Ok(Self { name, description })
+        let mut definition = Self {
+            name,
+            description,
+            feature: None,
+            aliases: Vec::new(),
+            hidden: false,
+            args: None,
+        };
+
+        while content.parse::<Option<Token![,]>>()?.is_some() && !content.is_empty() {
+            let key: Ident = content.parse()?;
+            match key.to_string().as_str() {
+                "hidden" => definition.hidden = true,
+                "feature" => {
+                    let _eq_token: Token![=] = content.parse()?;
+                    definition.feature = Some(content.parse()?);
+                }
+                "aliases" => {
+                    let _eq_token: Token![=] = content.parse()?;
+                    let aliases;
+                    let _bracket_token = bracketed!(aliases in content);
+                    definition.aliases =
+                        Punctuated::<LitStr, Token![,]>::parse_terminated(&aliases)?
+                            .into_iter()
+                            .collect();
+                }
+                "args" => {
+                    let _eq_token: Token![=] = content.parse()?;
+                    definition.args = Some(content.parse()?);
+                }
+                _ => {
+                    return Err(syn::Error::new(
+                        key.span(),
+                        "expected one of `feature`, `aliases`, `hidden` or `args`",
+                    ))
+                }
+            }
+        }
+
+        Ok(definition)
     }
 }
 
@@ -40,19 +90,65 @@ impl Parse for Input {
 #[proc_macro]
 pub fn define_subcommands(tokens: TokenStream) -> TokenStream {
     let input = parse_macro_input!(tokens as Input);
-    let definitions = input.definitions;
 
-    let names = definitions.iter().map(|d| d.name.clone());
-    let descriptions = definitions.iter().map(|d| d.description.clone());
+    let entries = input.definitions.iter().map(|definition| {
+        let Definition {
+            name,
+            description,
+            feature,
+            aliases,
+            hidden,
+            args,
+        } = definition;
+
+        // Subcommands tied to a disabled feature are left out of the list entirely
+        let cfg = feature
+            .as_ref()
+            .map(|feature| quote! { #[cfg(feature = #feature)] });
+
+        let (command_extension, script) = match args {
+            Some(args) => (
+                quote! {
+                    |cmd| {
+                        <subcommands::#name::#args as clap::Subcommand>::augment_subcommands(cmd)
+                            .subcommand_required(true)
+                            .arg_required_else_help(true)
+                    }
+                },
+                quote! {
+                    |sh, matches| {
+                        let args =
+                            <subcommands::#name::#args as clap::FromArgMatches>::from_arg_matches(
+                                matches,
+                            )?;
+                        subcommands::#name::run(sh, args)
+                    }
+                },
+            ),
+            None => (
+                quote! { subcommands::#name::command_extension },
+                quote! { subcommands::#name::run },
+            ),
+        };
+
+        quote! {
+            #cfg
+            (
+                Definition {
+                    name: stringify!(#name),
+                    description: #description,
+                    aliases: &[#(#aliases),*],
+                    hidden: #hidden,
+                    command_extension: #command_extension,
+                },
+                #script
+            ),
+        }
+    });
 
     let expanded = quote! {
         pub const SUBCOMMANDS: &[(Definition, Script)] = &[
-            #(
-                (
-                    (stringify!(#names), #descriptions, subcommands::#names::command_extension),
-                    subcommands::#names::run
-                ),
-            )*
+            #(#entries)*
         ];
     };
 
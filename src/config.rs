@@ -0,0 +1,206 @@
+//! User configuration, read from `$XDG_CONFIG_HOME/plsdo/config.toml`.
+//!
+//! Every key is optional. Missing paths fall back to XDG-derived defaults, while missing device
+//! names only cause an error once a subcommand actually needs them.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::{
+    constants::Constants,
+    subcommands::scene::Scene,
+    system_atlas::{RawSystemAtlas, SystemAtlas},
+    util::{clipboard_history::ClipboardConfig, dmenu::MenuConfig},
+};
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+#[derive(Debug)]
+pub struct Config {
+    pub paths: SystemAtlas,
+    pub devices: Constants,
+    pub menu: MenuConfig,
+    pub clipboard: ClipboardConfig,
+    /// See `plsdo scene`
+    pub scenes: BTreeMap<String, Scene>,
+    /// The arguments of plsdo by key chord, see `plsdo keybinds`
+    pub keybinds: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawConfig {
+    paths: RawSystemAtlas,
+    devices: Constants,
+    menu: MenuConfig,
+    clipboard: ClipboardConfig,
+    scenes: BTreeMap<String, Scene>,
+    keybinds: BTreeMap<String, Vec<String>>,
+}
+
+impl Config {
+    fn parse(contents: &str) -> anyhow::Result<Self> {
+        let raw: RawConfig = toml::from_str(contents)?;
+        Ok(Self {
+            paths: SystemAtlas::resolve(raw.paths)?,
+            devices: raw.devices,
+            menu: raw.menu,
+            clipboard: raw.clipboard,
+            scenes: raw.scenes,
+            keybinds: raw.keybinds,
+        })
+    }
+
+    fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to read config file {}", path.display()))
+            }
+        };
+
+        Self::parse(&contents).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+}
+
+/// Get the path of the config file
+pub fn config_path() -> anyhow::Result<PathBuf> {
+    let mut path = dirs::config_dir()
+        .ok_or_else(|| anyhow::anyhow!("Could not determine the config directory"))?;
+    path.push("plsdo");
+    path.push("config.toml");
+    Ok(path)
+}
+
+/// Read the config file, and make it available through `get`. Must be called once at startup,
+/// before any subcommand runs.
+pub fn init() -> anyhow::Result<()> {
+    if CONFIG.get().is_some() {
+        return Ok(());
+    }
+
+    let config = Config::load(&config_path()?)?;
+    let _ = CONFIG.set(config);
+    Ok(())
+}
+
+pub fn get() -> &'static Config {
+    CONFIG
+        .get()
+        .expect("config::init should be called at startup")
+}
+
+/// Make a fixed config available through `get`, for tests of code which reads it. The user's
+/// config file is not read.
+#[cfg(test)]
+pub fn init_for_tests() {
+    const TEST_CONFIG: &str = r#"
+[paths]
+main_dotfiles = "/dotfiles/main"
+canary_dotfiles = "/dotfiles/canary"
+
+[devices]
+headphones_controller_name = "Headphones"
+tv_controller_name = "TV"
+earbuds_name = "Earbuds"
+
+[menu]
+backend = "dmenu"
+args = []
+"#;
+
+    CONFIG.get_or_init(|| Config::parse(TEST_CONFIG).expect("the test config is valid"));
+}
+
+/// Expand a leading `~` in the given path to the home directory.
+pub fn expand_tilde(path: &str) -> anyhow::Result<String> {
+    let rest = match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => rest,
+        _ => return Ok(path.to_owned()),
+    };
+
+    let home = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Could not expand '~': home directory is unknown"))?;
+    Ok(format!("{}{}", home.display(), rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::dmenu::MenuBackendKind;
+
+    use super::*;
+
+    #[test]
+    fn empty_config_uses_defaults() {
+        let config = Config::parse("").unwrap();
+        let data_dir = dirs::data_dir().unwrap();
+
+        assert_eq!(
+            config.paths.eww_audio,
+            data_dir.join("eww-audio").to_str().unwrap()
+        );
+        assert!(config.devices.earbuds_name.is_none());
+    }
+
+    #[test]
+    fn paths_are_tilde_expanded() {
+        let config = Config::parse("[paths]\nalacritty = \"~/alacritty.toml\"").unwrap();
+        let home = dirs::home_dir().unwrap();
+
+        assert_eq!(
+            config.paths.alacritty,
+            home.join("alacritty.toml").to_str().unwrap()
+        );
+    }
+
+    #[test]
+    fn tilde_in_the_middle_is_kept() {
+        assert_eq!(expand_tilde("/a/~/b").unwrap(), "/a/~/b");
+        assert_eq!(expand_tilde("~user/b").unwrap(), "~user/b");
+    }
+
+    #[test]
+    fn missing_device_name_is_named_in_error() {
+        let config = Config::parse("[devices]\ntv_controller_name = \"TV\"").unwrap();
+
+        assert_eq!(config.devices.tv_controller_name.as_deref(), Some("TV"));
+        let err = config.devices.earbuds_name().unwrap_err();
+        assert!(err.to_string().contains("devices.earbuds_name"));
+    }
+
+    #[test]
+    fn menu_backend_is_parsed() {
+        let config = Config::parse("[menu]\nbackend = \"fuzzel\"").unwrap();
+        assert_eq!(config.menu.backend, Some(MenuBackendKind::Fuzzel));
+        assert!(config.menu.args.is_none());
+    }
+
+    #[test]
+    fn scenes_are_parsed() {
+        let config = Config::parse(
+            "[scenes.movie]\naudio_output = \"tv\"\nbrightness = 100\nfont_size = 14",
+        )
+        .unwrap();
+
+        let movie = &config.scenes["movie"];
+        assert_eq!(movie.audio_output.as_deref(), Some("tv"));
+        assert_eq!(movie.brightness, Some(100));
+        assert_eq!(movie.font_size, Some(14.0));
+        assert!(movie.colortemp.is_none());
+    }
+
+    #[test]
+    fn unknown_key_is_rejected() {
+        let err = Config::parse("[paths]\nalacrity = \"/a\"").unwrap_err();
+        assert!(format!("{err:#}").contains("alacrity"));
+    }
+}
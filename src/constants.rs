@@ -1,3 +1,28 @@
-pub const EARBUDS_NAME: &str = "JBL WAVE200TWS";
-pub const TV_CONTROLLER_NAME: &str = "Navi 21/23 HDMI/DP Audio Controller";
-pub const HEADPHONES_CONTROLLER_NAME: &str = "Ryzen HD Audio Controller";
+use std::sync::LazyLock;
+
+use serde::Deserialize;
+
+use crate::config;
+
+/// Names of the devices which plsdo treats specially, read from the `devices` section of the
+/// config file.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Constants {
+    pub earbuds_name: Option<String>,
+    pub tv_controller_name: Option<String>,
+    pub headphones_controller_name: Option<String>,
+}
+
+pub static CONSTANTS: LazyLock<&Constants> = LazyLock::new(|| &config::get().devices);
+
+impl Constants {
+    /// The name of the earbuds is needed to react to them being disconnected, so unlike the
+    /// controller names, it must be configured.
+    #[cfg_attr(not(feature = "udev"), allow(dead_code))]
+    pub fn earbuds_name(&self) -> anyhow::Result<&str> {
+        self.earbuds_name
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("Missing config key `devices.earbuds_name`"))
+    }
+}
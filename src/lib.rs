@@ -1,63 +1,270 @@
-use clap::{command, ArgMatches, Command};
+use std::{
+    ffi::{OsStr, OsString},
+    io::IsTerminal,
+};
+
+use clap::{
+    arg,
+    builder::{PossibleValuesParser, TypedValueParser},
+    command, value_parser, Arg, ArgMatches, Command,
+};
 use define_subcommands_macro::define_subcommands;
+use log::LevelFilter;
 use xshell::Shell;
 
+use util::notify::{Notification, Urgency};
+
+mod config;
 mod constants;
+mod output;
+pub mod plugin;
 mod subcommands;
 mod system_atlas;
 mod util;
 
-pub type Definition = (
-    &'static str,           // name
-    &'static str,           // description
-    fn(Command) -> Command, // command extension
-);
-pub type Script = fn(&Shell, &ArgMatches) -> anyhow::Result<Option<String>>;
+/// How a subcommand is presented on the command line. Generated by `define_subcommands!`.
+pub struct Definition {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub aliases: &'static [&'static str],
+    /// Left out of the help, e.g. for commands which only other plsdo processes run
+    pub hidden: bool,
+    pub command_extension: fn(Command) -> Command,
+}
+
+impl Definition {
+    fn is_called(&self, name: &str) -> bool {
+        self.name == name || self.aliases.contains(&name)
+    }
+}
+
+pub type Script = fn(&Shell, &ArgMatches) -> anyhow::Result<Option<ScriptOutput>>;
+
+pub use output::{OutputFormat, ScriptOutput};
+pub use subcommands::completions::COMPLETE_VAR;
 
 // Each plsdo subcommand can be invoked as a subcommand on the plsdo command. Subcommands are
 // expected to live under the `subcommands` folder, and must provide implementations for the `run`
-// and `command_extension` functions.
+// and `command_extension` functions, unless their arguments are described by a clap-derive enum
+// given as `args`. A subcommand which needs an optional dependency names the cargo feature which
+// enables it, and its module declaration must be gated on the same feature. See the macro for all
+// the options.
 define_subcommands!([
-    (power, "Shut down, reboot or suspend the machine"),
-    (keyboard, "Change the keyboard layout"),
+    (menu, "Choose any plsdo action from a menu"),
+    (
+        power,
+        "Shut down, suspend, lock or log out through logind",
+        feature = "dbus"
+    ),
+    (keyboard, "Change the keyboard layout", feature = "hyprland"),
     (font_size, "Change the font size"),
     (font_family, "Change the font family"),
+    (
+        config_history,
+        "List or roll back the config edits made by plsdo",
+        aliases = ["config-history"]
+    ),
     (playerctl, "Control media players"),
     (game, "Launch a game through Lutris"),
-    (workspace, "Manage desktop workspaces"),
-    (brightness, "Adjust the screen brightness"),
-    (colortemp, "Adjust the screen color temperature"),
+    (
+        workspace,
+        "Manage desktop workspaces",
+        feature = "hyprland",
+        aliases = ["ws"],
+        args = WorkspaceCommand
+    ),
+    (brightness, "Adjust the screen brightness", feature = "dbus"),
+    (
+        colortemp,
+        "Adjust the screen color temperature",
+        feature = "dbus"
+    ),
     (audio, "Adjust the audio volume or output"),
+    (
+        scene,
+        "Apply or save a named bundle of audio, screen and font settings"
+    ),
+    (
+        keybinds,
+        "Generate Hyprland and sxhkd keybinds from the config, or list them"
+    ),
+    (undo, "Revert the last change of a setting made by plsdo"),
+    (redo, "Reapply the last change which was undone"),
     (ytdl, "Download videos using yt-dlp"),
-    (torrent, "Manage torrents"),
-    (screenshot, "Take screenshots")
+    (torrent, "Manage torrents", feature = "transmission"),
+    (screenshot, "Take screenshots", feature = "hyprland"),
+    (daemon, "Run and manage the background listeners"),
+    (
+        subscribe,
+        "Follow changes to a piece of state through the daemon"
+    ),
+    (
+        serve,
+        "Run the commands which other plsdo processes send to it",
+        hidden
+    ),
+    (completions, "Print the shell completion script"),
+    (manpage, "Print the man page")
 ]);
 
-pub fn run_subcommand(shell: &Shell, matches: &ArgMatches) -> anyhow::Result<Option<String>> {
+/// Load the user configuration. Must be called before running any subcommand.
+pub fn init() -> anyhow::Result<()> {
+    config::init()
+}
+
+/// Install the logger, which drops messages less severe than the level
+pub fn init_logging(level: LevelFilter) {
+    util::logging::init(level)
+}
+
+/// Run the subcommand of the parsed command line. `args` is the command line it was parsed from,
+/// including the executable.
+pub fn run_subcommand<S: AsRef<OsStr>>(
+    shell: &Shell,
+    args: &[S],
+    matches: &ArgMatches,
+) -> anyhow::Result<Option<ScriptOutput>> {
+    let command_line = args
+        .iter()
+        .skip(1)
+        .map(|arg| arg.as_ref().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ");
+
     let (subcmd_name, subcmd_args) = matches.subcommand().expect(
         "A subcommand is always received;
 otherwise clap exits before getting this far",
     );
 
-    let subcommand = SUBCOMMANDS
+    if let Some(&(_, subcommand)) = SUBCOMMANDS
         .iter()
-        .find(|&&((name, _, _), _)| name == subcmd_name)
-        .map(|found| found.1)
-        .expect("A valid subcommand name is supplied; otherwise clap exists");
+        .find(|(definition, _)| definition.name == subcmd_name)
+    {
+        return util::config_edit::for_command(&command_line, || subcommand(shell, subcmd_args));
+    }
+
+    let Some(plugin) = plugin::discover()
+        .into_iter()
+        .find(|plugin| plugin.name == subcmd_name)
+    else {
+        // clap only accepts the name if the plugin was found while parsing, but it may have been
+        // removed from `$PATH` since
+        anyhow::bail!(
+            "No built-in subcommand or plugin is called '{}'",
+            subcmd_name
+        );
+    };
+    let args = subcmd_args
+        .get_many::<OsString>("ARGS")
+        .unwrap_or_default()
+        .cloned()
+        .collect::<Vec<_>>();
+    let format = subcmd_args
+        .get_one::<OutputFormat>("output-format")
+        .copied()
+        .unwrap_or_default();
 
-    subcommand(shell, subcmd_args)
+    Err(plugin::exec(&plugin, &args, format))
 }
 
+/// Run the command in the server (see `plsdo serve`), and return its output. Returns `None` if
+/// the command should run in this process instead, e.g. because no server is running.
+pub fn forward_to_server(args: &[OsString]) -> Option<anyhow::Result<Option<String>>> {
+    subcommands::serve::forward(args)
+}
+
+/// Print the error which ended the command. Most commands run from keybinds, where nobody reads
+/// stderr, so without a terminal the error is also shown as a notification. A closed menu means
+/// that the user changed their mind, which isn't reported at all.
+pub fn report_error(err: &anyhow::Error) {
+    if util::dmenu::is_aborted(err) {
+        return;
+    }
+
+    eprintln!("Error: {:?}", err);
+
+    if !std::io::stderr().is_terminal() {
+        let causes = err
+            .chain()
+            .map(|cause| cause.to_string())
+            .collect::<Vec<_>>();
+        Notification::new("plsdo failed")
+            .body(causes.join("\n"))
+            .icon("dialog-error")
+            .urgency(Urgency::Critical)
+            .send();
+    }
+}
+
+/// The subcommands of the plugins found on `$PATH`, except those shadowed by a built-in one
+fn plugin_subcommands() -> Vec<Command> {
+    plugin::discover()
+        .into_iter()
+        .filter(|plugin| {
+            !SUBCOMMANDS
+                .iter()
+                .any(|(definition, _)| definition.is_called(&plugin.name))
+        })
+        .map(|plugin| {
+            Command::new(plugin.name)
+                .about(format!("Run {}", plugin.path.display()))
+                .disable_help_flag(true)
+                .arg(
+                    Arg::new("ARGS")
+                        .num_args(..)
+                        .trailing_var_arg(true)
+                        .allow_hyphen_values(true)
+                        .value_parser(value_parser!(OsString)),
+                )
+        })
+        .collect()
+}
+
+/// The command with every subcommand, including those of the plugins
 pub fn get_command() -> Command {
+    get_builtin_command().subcommands(plugin_subcommands())
+}
+
+/// Parse the command line. Finding the plugins means listing every directory on `$PATH`, so it's
+/// only done when the built-in subcommands don't do, e.g. for a plugin, a mistyped subcommand or
+/// the help.
+pub fn get_matches(args: &[OsString]) -> ArgMatches {
+    get_builtin_command()
+        .try_get_matches_from(args)
+        .unwrap_or_else(|_| get_command().get_matches_from(args))
+}
+
+fn get_builtin_command() -> Command {
     command!()
         .subcommand_required(true)
         .arg_required_else_help(true)
-        .subcommands(
-            SUBCOMMANDS
-                .iter()
-                .map(|&((name, about, command_extension), _)| {
-                    let base_command = Command::new(name).about(about);
-                    command_extension(base_command)
-                }),
+        .arg(
+            // Not `--format`: global arguments are propagated into the subcommands, where they
+            // would clash with `ytdl download --format`
+            arg!(--"output-format" <FORMAT> "How the output of the command should be printed")
+                .value_parser(value_parser!(OutputFormat))
+                .default_value("text")
+                .global(true),
+        )
+        .arg(
+            arg!(--"log-level" <LEVEL> "Which messages should be logged")
+                .value_parser(
+                    PossibleValuesParser::new(["off", "error", "warn", "info", "debug", "trace"])
+                        .map(|level| {
+                            level
+                                .parse::<LevelFilter>()
+                                .expect("only valid levels are possible")
+                        }),
+                )
+                .env("PLSDO_LOG")
+                .default_value("info")
+                .global(true),
         )
+        .subcommands(SUBCOMMANDS.iter().map(|(definition, _)| {
+            let base_command = Command::new(definition.name)
+                .about(definition.description)
+                .visible_aliases(definition.aliases.iter().copied())
+                .hide(definition.hidden);
+            (definition.command_extension)(base_command)
+        }))
 }
@@ -1,12 +1,55 @@
-use plsdo::{get_command, run_subcommand};
+use std::{ffi::OsString, process::ExitCode};
+
+use clap_complete::CompleteEnv;
+use log::LevelFilter;
+use plsdo::{
+    forward_to_server, get_command, get_matches, init, init_logging, report_error, run_subcommand,
+    OutputFormat, COMPLETE_VAR,
+};
 use xshell::Shell;
 
-fn main() -> anyhow::Result<()> {
+fn main() -> ExitCode {
+    // When invoked by a completion script, print the completions and exit
+    CompleteEnv::with_factory(get_command)
+        .var(COMPLETE_VAR)
+        .complete();
+
+    let args = std::env::args_os().collect::<Vec<_>>();
+    let result = forward_to_server(&args).unwrap_or_else(|| run(&args));
+
+    match result {
+        Ok(output) => {
+            if let Some(output) = output {
+                println!("{}", output);
+            }
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            report_error(&err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Run the command in this process, and return what it should print
+fn run(args: &[OsString]) -> anyhow::Result<Option<String>> {
     let shell = Shell::new()?;
 
-    let command = get_command();
-    let matches = command.get_matches();
-    run_subcommand(&shell, &matches)?;
+    let matches = get_matches(args);
+    init_logging(
+        matches
+            .get_one::<LevelFilter>("log-level")
+            .copied()
+            .unwrap_or(LevelFilter::Info),
+    );
+    init()?;
+
+    let format = matches
+        .get_one::<OutputFormat>("output-format")
+        .copied()
+        .unwrap_or_default();
 
-    Ok(())
+    run_subcommand(&shell, args, &matches)?
+        .map(|output| output.render(format))
+        .transpose()
 }
@@ -0,0 +1,94 @@
+//! The value a script returns, and how it gets printed.
+//!
+//! Scripts don't print their results themselves; instead they return a `ScriptOutput`, which is
+//! rendered by `main` according to the `--output-format` flag. This way every subcommand can be consumed
+//! as JSON by other scripts and eww widgets.
+
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptOutput {
+    value: Value,
+    text: Option<String>,
+}
+
+impl ScriptOutput {
+    /// Create an output from a serializable value. Unless `with_text` is used, the text
+    /// representation is the value itself if it's a string, or pretty-printed JSON otherwise.
+    pub fn new(value: &impl Serialize) -> anyhow::Result<Self> {
+        Ok(Self {
+            value: serde_json::to_value(value)?,
+            text: None,
+        })
+    }
+
+    /// Use the given string as the text representation of the output.
+    pub fn with_text(mut self, text: impl ToString) -> Self {
+        self.text = Some(text.to_string());
+        self
+    }
+
+    pub fn render(&self, format: OutputFormat) -> anyhow::Result<String> {
+        let rendered = match format {
+            OutputFormat::Json => serde_json::to_string(&self.value)?,
+            OutputFormat::Text => match (&self.text, &self.value) {
+                (Some(text), _) => text.clone(),
+                (None, Value::String(s)) => s.clone(),
+                (None, value) => serde_json::to_string_pretty(value)?,
+            },
+        };
+
+        Ok(rendered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn text_defaults_to_pretty_json() {
+        let output = ScriptOutput::new(&json!({ "volume": 42 })).unwrap();
+
+        assert_eq!(
+            output.render(OutputFormat::Text).unwrap(),
+            "{\n  \"volume\": 42\n}"
+        );
+        assert_eq!(
+            output.render(OutputFormat::Json).unwrap(),
+            "{\"volume\":42}"
+        );
+    }
+
+    #[test]
+    fn strings_are_not_quoted_in_text_mode() {
+        let output = ScriptOutput::new(&"up").unwrap();
+
+        assert_eq!(output.render(OutputFormat::Text).unwrap(), "up");
+        assert_eq!(output.render(OutputFormat::Json).unwrap(), "\"up\"");
+    }
+
+    #[test]
+    fn explicit_text_is_preferred() {
+        let output = ScriptOutput::new(&json!({ "minutes": 3 }))
+            .unwrap()
+            .with_text("3m");
+
+        assert_eq!(output.render(OutputFormat::Text).unwrap(), "3m");
+        assert_eq!(
+            output.render(OutputFormat::Json).unwrap(),
+            "{\"minutes\":3}"
+        );
+    }
+}
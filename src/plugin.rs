@@ -0,0 +1,144 @@
+//! External subcommands. Like git, plsdo runs an executable called `plsdo-<name>` found on
+//! `$PATH` when invoked as `plsdo <name>`, passing it the remaining arguments.
+//!
+//! Plugins can be written in any language. The ones written in Rust can depend on this crate, and
+//! use the items re-exported here to prompt with the configured menu, read the clipboard, and use
+//! the state bus the same way the built-in subcommands do. `init` must be called first:
+//!
+//! ```no_run
+//! use plsdo::plugin::{Dmenu, Shell};
+//!
+//! fn main() -> anyhow::Result<()> {
+//!     plsdo::init()?;
+//!     let sh = Shell::new()?;
+//!     let greeting = Dmenu::new(&sh).choose_one_str("Greeting", &["hello", "hi"])?;
+//!     println!("{}", greeting);
+//!     Ok(())
+//! }
+//! ```
+
+use std::{
+    collections::HashSet,
+    ffi::{OsStr, OsString},
+    os::unix::process::CommandExt,
+    path::PathBuf,
+};
+
+use clap::ValueEnum;
+
+use crate::OutputFormat;
+
+pub use xshell::Shell;
+
+pub use crate::util::{
+    bus::{publish, subscribe, Topic},
+    determine_wm,
+    dmenu::Dmenu,
+    Clipboard, RealClipboard, WM,
+};
+
+const PREFIX: &str = "plsdo-";
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Plugin {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+/// Find the plugins in the directories of the `PATH`-like list. As with any other command, when
+/// several directories contain a plugin of the same name, the first one wins.
+fn discover_in(path: &OsStr) -> Vec<Plugin> {
+    let mut seen = HashSet::new();
+    let mut plugins = Vec::new();
+
+    for dir in std::env::split_paths(path) {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+
+        let mut found = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                let name = path.file_name()?.to_str()?.strip_prefix(PREFIX)?.to_owned();
+                (!name.is_empty() && crate::util::is_executable(&path))
+                    .then_some(Plugin { name, path })
+            })
+            .collect::<Vec<_>>();
+        found.sort_by(|a, b| a.name.cmp(&b.name));
+
+        plugins.extend(
+            found
+                .into_iter()
+                .filter(|plugin| seen.insert(plugin.name.clone())),
+        );
+    }
+
+    plugins
+}
+
+/// Find the plugins on `$PATH`
+pub(crate) fn discover() -> Vec<Plugin> {
+    std::env::var_os("PATH")
+        .map(|path| discover_in(&path))
+        .unwrap_or_default()
+}
+
+/// Replace the current process with the plugin. Only returns if the plugin could not be started.
+///
+/// `--output-format` is parsed by plsdo wherever it appears, so its value is passed on through the
+/// `PLSDO_FORMAT` environment variable instead.
+pub(crate) fn exec(plugin: &Plugin, args: &[OsString], format: OutputFormat) -> anyhow::Error {
+    let format = format
+        .to_possible_value()
+        .expect("output formats are not skipped");
+    let err = std::process::Command::new(&plugin.path)
+        .args(args)
+        .env("PLSDO_FORMAT", format.get_name())
+        .exec();
+    anyhow::Error::new(err).context(format!("Failed to run {}", plugin.path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::fs::PermissionsExt, path::Path};
+
+    use super::*;
+
+    fn create_file(path: &Path, mode: u32) {
+        fs::write(path, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    #[test]
+    fn the_first_executable_of_each_name_is_found() {
+        let root = std::env::temp_dir().join(format!("plsdo-test-plugins-{}", std::process::id()));
+        let (first, second) = (root.join("first"), root.join("second"));
+        fs::create_dir_all(&first).unwrap();
+        fs::create_dir_all(&second).unwrap();
+
+        create_file(&first.join("plsdo-wallpaper"), 0o755);
+        create_file(&first.join("plsdo-notes"), 0o644);
+        create_file(&first.join("plsdo-"), 0o755);
+        create_file(&second.join("plsdo-wallpaper"), 0o755);
+        create_file(&second.join("plsdo-vpn"), 0o755);
+        create_file(&second.join("other"), 0o755);
+
+        let path = std::env::join_paths([&first, &root.join("missing"), &second]).unwrap();
+        let plugins = discover_in(&path);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            plugins,
+            vec![
+                Plugin {
+                    name: "wallpaper".to_owned(),
+                    path: first.join("plsdo-wallpaper"),
+                },
+                Plugin {
+                    name: "vpn".to_owned(),
+                    path: second.join("plsdo-vpn"),
+                },
+            ]
+        );
+    }
+}
@@ -8,8 +8,12 @@ use udev::Device;
 use xshell::Shell;
 
 use crate::{
-    constants::EARBUDS_NAME,
-    util::listener::{get_pidfile_lock, write_pid},
+    constants::CONSTANTS,
+    system_atlas::SYSTEM_ATLAS,
+    util::{
+        listener::{get_pidfile_lock, write_pid},
+        logging,
+    },
 };
 
 use super::state::{
@@ -17,8 +21,6 @@ use super::state::{
     write_to_backing_file,
 };
 
-const PIDFILE: &str = "/tmp/plsdo-audio-device-listener.pid";
-
 fn get_property_value(device: &Device, property: impl AsRef<OsStr>) -> Option<&str> {
     device.property_value(property).and_then(|s| s.to_str())
 }
@@ -26,19 +28,26 @@ fn get_property_value(device: &Device, property: impl AsRef<OsStr>) -> Option<&s
 fn handle_udev_event(event: udev::Event) -> anyhow::Result<()> {
     let device = event.device();
 
-    let action = get_property_value(&device, "ACTION");
-    let Some("remove") = action else {
-        anyhow::bail!("Not a remove event")
+    // Only removed devices are interesting
+    let Some("remove") = get_property_value(&device, "ACTION") else {
+        return Ok(());
     };
 
-    let name = get_property_value(&device, "NAME");
-    let is_earbuds = name.is_some_and(|n| n.trim_start_matches('"').starts_with(EARBUDS_NAME));
+    let earbuds_name = CONSTANTS.earbuds_name()?;
+    let name = get_property_value(&device, "NAME").map(|n| n.trim_matches('"'));
+    log::trace!(device = name; "Device removed");
+    let is_earbuds = name.is_some_and(|n| n.starts_with(earbuds_name));
 
     if is_earbuds {
         let sh = Shell::new()?;
         let outputs = get_all_audio_outputs(&sh)?;
         let matching_output = find_matching_output(&outputs, "Headphones")?;
-        set_audio_output(&sh, &matching_output.name)?;
+        log::info!(
+            device = name,
+            output = matching_output.description;
+            "Earbuds were removed, switching to the headphones"
+        );
+        set_audio_output(&sh, matching_output)?;
 
         let audio_state = get_current_audio_state(&sh)?;
         write_to_backing_file(audio_state)?;
@@ -48,11 +57,13 @@ fn handle_udev_event(event: udev::Event) -> anyhow::Result<()> {
 }
 
 pub fn run(_args: &ArgMatches) -> anyhow::Result<()> {
-    let mut lock = get_pidfile_lock(PIDFILE)?;
+    let mut lock = get_pidfile_lock(&SYSTEM_ATLAS.audio_listener_pidfile)?;
     let mut guard = lock
         .try_write()
         .context("The listener is already running")?;
     write_pid(&mut guard)?;
+    logging::log_to_file("audio")?;
+    log::info!("Listening to udev events");
 
     let mut socket = udev::MonitorBuilder::new()?.listen()?;
 
@@ -70,7 +81,7 @@ pub fn run(_args: &ArgMatches) -> anyhow::Result<()> {
             if event.token() == source_token && event.is_readable() {
                 socket.iter().for_each(|ev| {
                     if let Err(err) = handle_udev_event(ev) {
-                        eprintln!("{}", err);
+                        log::error!(error = format!("{err:#}"); "Failed to handle udev event");
                     }
                 });
             }
@@ -1,14 +1,29 @@
 use clap::{arg, value_parser, ArgAction, ArgMatches, Command};
+use clap_complete::engine::CompletionCandidate;
 use state::{
     find_matching_output, get_all_audio_outputs, get_current_audio_output, get_current_audio_state,
     set_audio_output, set_volume, toggle_mute, write_to_backing_file,
 };
 use xshell::Shell;
 
-use crate::util::dmenu::Dmenu;
+use crate::{
+    util::{completion, dmenu::Dmenu, runner::CommandRunner},
+    ScriptOutput,
+};
+
+use super::undo::{self, Snapshot};
 
+#[cfg(feature = "udev")]
 mod listener;
-mod state;
+pub mod state;
+
+fn output_name_candidates(sh: &Shell) -> anyhow::Result<Vec<CompletionCandidate>> {
+    let outputs = get_all_audio_outputs(sh)?;
+    Ok(outputs
+        .into_iter()
+        .map(|output| CompletionCandidate::new(output.name).help(Some(output.description.into())))
+        .collect())
+}
 
 pub fn command_extension(cmd: Command) -> Command {
     let volume_subcommands = [
@@ -36,7 +51,8 @@ pub fn command_extension(cmd: Command) -> Command {
             .about("Set the current audio output")
             .arg(
             arg!([NEEDLE] "A string that matches the friendly name, name, description of the audio output")
-                .required(true),
+                .required(true)
+                .add(completion::dynamic_values(output_name_candidates)),
         ),
         Command::new("choose").about("Choose an audio output from the list of outputs"),
     ];
@@ -53,8 +69,10 @@ pub fn command_extension(cmd: Command) -> Command {
             .arg_required_else_help(true)
             .subcommand_required(true)
             .subcommands(volume_subcommands),
+        #[cfg(feature = "udev")]
         Command::new("run_listener")
-            .about("Launch the listener process, that reacts to audio device added/removed events"),
+            .about("Launch the listener process, that reacts to audio device added/removed events")
+            .hide(true),
     ];
 
     cmd.subcommand_required(true)
@@ -62,38 +80,52 @@ pub fn command_extension(cmd: Command) -> Command {
         .subcommands(inner_subcommands.iter())
 }
 
-fn handle_output_subcommand(sh: &Shell, args: &ArgMatches) -> anyhow::Result<()> {
+/// Set the audio output, recording the change for `plsdo undo`
+fn change_audio_output(
+    runner: &dyn CommandRunner,
+    output: &state::AudioOutput,
+) -> anyhow::Result<()> {
+    undo::record(
+        || {
+            Ok(Snapshot::AudioOutput(
+                get_current_audio_output(runner)?.name,
+            ))
+        },
+        || set_audio_output(runner, output),
+    )
+}
+
+fn handle_output_subcommand(
+    runner: &dyn CommandRunner,
+    args: &ArgMatches,
+) -> anyhow::Result<Option<ScriptOutput>> {
     match args.subcommand() {
         Some(("get", _)) => {
-            let current_output = get_current_audio_output(sh)?;
-            let current_output_json = serde_json::to_string_pretty(&current_output)?;
-            println!("{}", current_output_json);
-            Ok(())
+            let current_output = get_current_audio_output(runner)?;
+            return Ok(Some(ScriptOutput::new(&current_output)?));
         }
         Some(("get-all", _)) => {
-            let outputs = get_all_audio_outputs(sh)?;
-            let outputs_json = serde_json::to_string_pretty(&outputs)?;
-            println!("{}", outputs_json);
-            Ok(())
+            let outputs = get_all_audio_outputs(runner)?;
+            return Ok(Some(ScriptOutput::new(&outputs)?));
         }
         Some(("set", set_args)) => {
-            let outputs = get_all_audio_outputs(sh)?;
+            let outputs = get_all_audio_outputs(runner)?;
             let needle = set_args
                 .get_one::<String>("NEEDLE")
                 .expect("NEEDLE should be a required argument");
 
             let matching_output = find_matching_output(&outputs, needle)?;
 
-            set_audio_output(sh, &matching_output.name)
+            change_audio_output(runner, matching_output)?;
         }
         Some(("choose", _)) => {
-            let outputs = get_all_audio_outputs(sh)?;
+            let outputs = get_all_audio_outputs(runner)?;
             let mut choices = outputs
                 .iter()
                 .map(|o| format!("{:?} | {}", o.friendly_name, o.description))
                 .collect::<Vec<_>>();
             choices.sort();
-            let result = Dmenu::new(sh).numbered().auto_select().choose_one(
+            let result = Dmenu::new(runner).numbered().auto_select().choose_one(
                 "Choose audio output",
                 &choices,
                 String::as_ref,
@@ -106,13 +138,23 @@ fn handle_output_subcommand(sh: &Shell, args: &ArgMatches) -> anyhow::Result<()>
                 .trim();
             let matching_output = find_matching_output(&outputs, result_friendly_name)?;
 
-            set_audio_output(sh, &matching_output.name)
+            change_audio_output(runner, matching_output)?;
         }
-        _ => Ok(()),
+        _ => {}
     }
+
+    Ok(None)
 }
 
-fn handle_volume_subcommand(sh: &Shell, args: &ArgMatches) -> anyhow::Result<()> {
+fn handle_volume_subcommand(runner: &dyn CommandRunner, args: &ArgMatches) -> anyhow::Result<()> {
+    let capture = || {
+        let state = get_current_audio_state(runner)?;
+        Ok(Snapshot::Volume {
+            volume: state.volume,
+            is_muted: state.is_muted,
+        })
+    };
+
     match args.subcommand() {
         Some(("set", set_args)) => {
             let is_relative = set_args
@@ -127,29 +169,33 @@ fn handle_volume_subcommand(sh: &Shell, args: &ArgMatches) -> anyhow::Result<()>
                 anyhow::bail!("Cannot use negative value in non-relative mode!");
             }
 
-            set_volume(sh, value, is_relative)
+            undo::record(capture, || set_volume(runner, value, is_relative))
         }
-        Some(("toggle-mute", _)) => toggle_mute(sh),
+        Some(("toggle-mute", _)) => undo::record(capture, || toggle_mute(runner)),
         _ => Ok(()),
     }
 }
 
-fn initialize(sh: &Shell) -> anyhow::Result<()> {
-    let audio_state = get_current_audio_state(sh)?;
+fn initialize(runner: &dyn CommandRunner) -> anyhow::Result<()> {
+    let audio_state = get_current_audio_state(runner)?;
     write_to_backing_file(audio_state)
 }
 
-pub fn run(sh: &Shell, args: &ArgMatches) -> anyhow::Result<Option<String>> {
-    match args.subcommand() {
-        Some(("init", _)) => initialize(sh),
+pub fn run(sh: &Shell, args: &ArgMatches) -> anyhow::Result<Option<ScriptOutput>> {
+    let output = match args.subcommand() {
+        Some(("init", _)) => initialize(sh).map(|_| None),
         Some(("output", output_args)) => handle_output_subcommand(sh, output_args),
-        Some(("volume", volume_args)) => handle_volume_subcommand(sh, volume_args),
-        Some(("run_listener", run_listener_args)) => listener::run(run_listener_args),
-        _ => Ok(()),
+        Some(("volume", volume_args)) => handle_volume_subcommand(sh, volume_args).map(|_| None),
+        #[cfg(feature = "udev")]
+        Some(("run_listener", run_listener_args)) => listener::run(run_listener_args).map(|_| None),
+        _ => Ok(None),
     }?;
 
     let audio_state = get_current_audio_state(sh)?;
+    if let Some(("volume", _)) = args.subcommand() {
+        audio_state.volume_notification().send();
+    }
     write_to_backing_file(audio_state)?;
 
-    Ok(None)
+    Ok(output)
 }
@@ -1,26 +1,44 @@
-use std::{
-    fs::OpenOptions,
-    io::{LineWriter, Write},
-};
-
 use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
-use xshell::{cmd, Shell};
 
 use crate::{
-    constants::{EARBUDS_NAME, HEADPHONES_CONTROLLER_NAME, TV_CONTROLLER_NAME},
+    constants::CONSTANTS,
     system_atlas::SYSTEM_ATLAS,
+    util::{
+        backing_file,
+        bus::{self, Topic},
+        notify::Notification,
+        runner::{CommandRunner, Invocation},
+    },
 };
 
 const SINK: &str = "@DEFAULT_SINK@";
 
 #[derive(Serialize, Debug, Clone)]
 pub struct AudioState {
-    volume: u32,
-    is_muted: bool,
+    pub volume: u32,
+    pub is_muted: bool,
     output: AudioOutputFriendlyName,
 }
 
+impl AudioState {
+    /// The OSD which is shown when the volume is changed
+    pub fn volume_notification(&self) -> Notification {
+        let (icon, body) = match self.volume {
+            _ if self.is_muted => ("audio-volume-muted", "Muted".to_owned()),
+            0..=33 => ("audio-volume-low", format!("{}%", self.volume)),
+            34..=66 => ("audio-volume-medium", format!("{}%", self.volume)),
+            _ => ("audio-volume-high", format!("{}%", self.volume)),
+        };
+
+        Notification::new("Volume")
+            .body(body)
+            .icon(icon)
+            .progress(if self.is_muted { 0 } else { self.volume })
+            .tag("volume")
+    }
+}
+
 #[derive(ValueEnum, Serialize, Clone, Debug, PartialEq, Eq)]
 pub enum AudioOutputFriendlyName {
     TV,
@@ -59,11 +77,16 @@ impl From<PactlAudioSink> for AudioOutput {
     fn from(sink: PactlAudioSink) -> Self {
         // TODO: this is not very elaborate... maybe there is a way to assign labels to sinks in pipewire?
         // I should look into that
-        let output_type = if sink.description.starts_with(HEADPHONES_CONTROLLER_NAME) {
+        let is_described_by = |name: &Option<String>| {
+            name.as_deref()
+                .is_some_and(|name| sink.description.starts_with(name))
+        };
+
+        let output_type = if is_described_by(&CONSTANTS.headphones_controller_name) {
             AudioOutputFriendlyName::Headphones
-        } else if sink.description.starts_with(TV_CONTROLLER_NAME) {
+        } else if is_described_by(&CONSTANTS.tv_controller_name) {
             AudioOutputFriendlyName::TV
-        } else if sink.description.starts_with(EARBUDS_NAME) {
+        } else if is_described_by(&CONSTANTS.earbuds_name) {
             AudioOutputFriendlyName::Earbuds
         } else {
             AudioOutputFriendlyName::Unrecognized
@@ -78,34 +101,36 @@ impl From<PactlAudioSink> for AudioOutput {
 }
 
 pub fn write_to_backing_file(audio_state: AudioState) -> anyhow::Result<()> {
-    let file = OpenOptions::new()
-        .append(true)
-        .open(SYSTEM_ATLAS.eww_audio)?;
-    let mut writer = LineWriter::new(&file);
+    backing_file::append_json(&SYSTEM_ATLAS.eww_audio, &audio_state)?;
 
-    serde_json::to_writer(&mut writer, &audio_state)?;
-    writer.write_all(b"\n")?;
+    bus::publish(Topic::Audio, &audio_state)
+}
 
-    Ok(())
+fn pactl(args: &[&str]) -> Invocation {
+    Invocation::new("pactl").args(args.iter().copied())
 }
 
-pub fn toggle_mute(sh: &Shell) -> anyhow::Result<()> {
-    Ok(cmd!(sh, "pactl set-sink-mute {SINK} toggle").run()?)
+pub fn toggle_mute(runner: &dyn CommandRunner) -> anyhow::Result<()> {
+    runner.run(&pactl(&["set-sink-mute", SINK, "toggle"]))
 }
 
-pub fn set_volume(sh: &Shell, value: i16, is_relative: bool) -> anyhow::Result<()> {
+pub fn set_mute(runner: &dyn CommandRunner, is_muted: bool) -> anyhow::Result<()> {
+    let value = if is_muted { "1" } else { "0" };
+    runner.run(&pactl(&["set-sink-mute", SINK, value]))
+}
+
+pub fn set_volume(runner: &dyn CommandRunner, value: i16, is_relative: bool) -> anyhow::Result<()> {
     let value_str = if is_relative {
-        format!("{:+}", value)
+        format!("{:+}%", value)
     } else {
-        format!("{}", value)
+        format!("{}%", value)
     };
-    cmd!(sh, "pactl set-sink-volume {SINK} {value_str}%").run()?;
-    Ok(())
+    runner.run(&pactl(&["set-sink-volume", SINK, &value_str]))
 }
 
-pub fn get_current_audio_state(sh: &Shell) -> anyhow::Result<AudioState> {
-    let is_muted = cmd!(sh, "pactl get-sink-mute {SINK}")
-        .read()?
+pub fn get_current_audio_state(runner: &dyn CommandRunner) -> anyhow::Result<AudioState> {
+    let is_muted = runner
+        .read(&pactl(&["get-sink-mute", SINK]))?
         .split_once(' ')
         .ok_or(anyhow::anyhow!(
             "Got unexpected output from `pactl get-sink-mute"
@@ -113,7 +138,7 @@ pub fn get_current_audio_state(sh: &Shell) -> anyhow::Result<AudioState> {
         .1
         == "yes";
 
-    let volume_str = cmd!(sh, "pactl get-sink-volume {SINK}").read()?;
+    let volume_str = runner.read(&pactl(&["get-sink-volume", SINK]))?;
     let volume_percent = volume_str
         .split('/')
         .nth(1)
@@ -123,7 +148,7 @@ pub fn get_current_audio_state(sh: &Shell) -> anyhow::Result<AudioState> {
         .trim();
     let volume = volume_percent[..volume_percent.len() - 1].parse::<u32>()?;
 
-    let output = get_current_audio_output(sh)?;
+    let output = get_current_audio_output(runner)?;
 
     Ok(AudioState {
         volume,
@@ -132,17 +157,17 @@ pub fn get_current_audio_state(sh: &Shell) -> anyhow::Result<AudioState> {
     })
 }
 
-pub fn get_all_audio_outputs(sh: &Shell) -> anyhow::Result<Vec<AudioOutput>> {
-    let sinks_json = cmd!(sh, "pactl -f json list sinks").read()?;
+pub fn get_all_audio_outputs(runner: &dyn CommandRunner) -> anyhow::Result<Vec<AudioOutput>> {
+    let sinks_json = runner.read(&pactl(&["-f", "json", "list", "sinks"]))?;
     let sinks: Vec<PactlAudioSink> = serde_json::from_str(&sinks_json)?;
     let outputs = sinks.into_iter().map(AudioOutput::from).collect::<Vec<_>>();
 
     Ok(outputs)
 }
 
-pub fn get_current_audio_output(sh: &Shell) -> anyhow::Result<AudioOutput> {
-    let outputs = get_all_audio_outputs(sh)?;
-    let current_output_name = cmd!(sh, "pactl get-default-sink").read()?;
+pub fn get_current_audio_output(runner: &dyn CommandRunner) -> anyhow::Result<AudioOutput> {
+    let outputs = get_all_audio_outputs(runner)?;
+    let current_output_name = runner.read(&pactl(&["get-default-sink"]))?;
 
     outputs
         .into_iter()
@@ -152,8 +177,14 @@ pub fn get_current_audio_output(sh: &Shell) -> anyhow::Result<AudioOutput> {
         ))
 }
 
-pub fn set_audio_output(sh: &Shell, name: &str) -> anyhow::Result<()> {
-    cmd!(sh, "pactl set-default-sink {name}").run()?;
+pub fn set_audio_output(runner: &dyn CommandRunner, output: &AudioOutput) -> anyhow::Result<()> {
+    runner.run(&pactl(&["set-default-sink", &output.name]))?;
+
+    Notification::new("Audio output")
+        .body(&output.description)
+        .icon("audio-speakers")
+        .tag("audio-output")
+        .send();
     Ok(())
 }
 
@@ -181,3 +212,52 @@ pub fn find_matching_output<'a>(
 
     Ok(matching_outputs[0])
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{config, util::runner::FakeRunner};
+
+    use super::*;
+
+    const SINKS: &str = r#"[
+        {"name": "alsa_output.hdmi", "description": "TV Speakers"},
+        {"name": "bluez_output.1", "description": "Headphones WH-1000"}
+    ]"#;
+
+    #[test]
+    fn state_is_read_from_pactl() {
+        config::init_for_tests();
+        let fake = FakeRunner::new()
+            .respond("pactl get-sink-mute @DEFAULT_SINK@", "Mute: no\n", 0)
+            .respond(
+                "pactl get-sink-volume @DEFAULT_SINK@",
+                "Volume: front-left: 45875 /  70% / -9.29 dB,   front-right: 45875 /  70% / -9.29 dB\n",
+                0,
+            )
+            .respond("pactl -f json list sinks", SINKS, 0)
+            .respond("pactl get-default-sink", "bluez_output.1\n", 0);
+
+        let state = get_current_audio_state(&fake).unwrap();
+        assert_eq!(state.volume, 70);
+        assert!(!state.is_muted);
+        assert_eq!(state.output, AudioOutputFriendlyName::Headphones);
+    }
+
+    #[test]
+    fn relative_volume_is_signed() {
+        let fake = FakeRunner::new();
+
+        set_volume(&fake, 5, true).unwrap();
+        set_volume(&fake, -5, true).unwrap();
+        set_volume(&fake, 30, false).unwrap();
+
+        assert_eq!(
+            fake.command_lines(),
+            [
+                "pactl set-sink-volume @DEFAULT_SINK@ +5%",
+                "pactl set-sink-volume @DEFAULT_SINK@ -5%",
+                "pactl set-sink-volume @DEFAULT_SINK@ 30%"
+            ]
+        );
+    }
+}
@@ -1,12 +1,24 @@
-use std::fs::OpenOptions;
-use std::io::{LineWriter, Write};
 use std::time::Duration;
 
 use clap::{arg, value_parser, ArgMatches, Command, ValueEnum};
-use dbus::{arg, blocking::Connection};
+use dbus::{
+    arg,
+    blocking::{stdintf::org_freedesktop_dbus::Properties, Proxy, SyncConnection},
+};
 use xshell::Shell;
 
-use crate::system_atlas::SYSTEM_ATLAS;
+use crate::{
+    system_atlas::SYSTEM_ATLAS,
+    util::{
+        backing_file,
+        bus::{self, Topic},
+        notify::Notification,
+        session_bus,
+    },
+    ScriptOutput,
+};
+
+use super::undo::{self, Snapshot};
 
 #[derive(ValueEnum, Clone, Debug)]
 enum Direction {
@@ -44,31 +56,67 @@ fn determine_delta(args: &ArgMatches) -> anyhow::Result<f64> {
 }
 
 fn write_brightness_to_backing_file(brightness: f64) -> anyhow::Result<()> {
-    let file = OpenOptions::new()
-        .append(true)
-        .open(SYSTEM_ATLAS.eww_brightness)?;
-    let mut writer = LineWriter::new(&file);
-    writeln!(writer, "{}", brightness.round())?;
+    backing_file::append_line(
+        &SYSTEM_ATLAS.eww_brightness,
+        &brightness.round().to_string(),
+    )?;
 
-    Ok(())
+    bus::publish(Topic::Brightness, &(brightness.round() as u32))
 }
 
-pub fn run(_: &Shell, args: &ArgMatches) -> anyhow::Result<Option<String>> {
-    let signed_delta = determine_delta(args)?;
-
-    let connection = Connection::new_session()?;
-    let proxy = connection.with_proxy("rs.wl-gammarelay", "/", Duration::from_secs(1));
-
-    proxy.method_call::<(), _, _, _>("rs.wl.gammarelay", "UpdateBrightness", (signed_delta,))?;
-    use dbus::blocking::stdintf::org_freedesktop_dbus::Properties;
-
+/// Read the brightness from gammarelay, in percent
+fn read_brightness(proxy: &Proxy<'_, &SyncConnection>) -> anyhow::Result<f64> {
     let brightness_refarg: Box<dyn arg::RefArg> = proxy.get("rs.wl.gammarelay", "Brightness")?;
     let brightness = 100.0
         * brightness_refarg.as_f64().ok_or(anyhow::anyhow!(
             "rs.wl.gammarelay.Brightness is not an f64 value"
         ))?;
 
+    Ok(brightness)
+}
+
+/// The current brightness, in percent
+pub fn get_brightness() -> anyhow::Result<u32> {
+    let connection = session_bus()?;
+    let proxy = connection.with_proxy("rs.wl-gammarelay", "/", Duration::from_secs(1));
+
+    Ok(read_brightness(&proxy)?.round() as u32)
+}
+
+/// Set the brightness, in percent
+pub fn set_brightness(brightness: u32) -> anyhow::Result<()> {
+    let connection = session_bus()?;
+    let proxy = connection.with_proxy("rs.wl-gammarelay", "/", Duration::from_secs(1));
+
+    proxy.set("rs.wl.gammarelay", "Brightness", brightness as f64 / 100.0)?;
+    write_brightness_to_backing_file(brightness as f64)
+}
+
+pub fn run(_: &Shell, args: &ArgMatches) -> anyhow::Result<Option<ScriptOutput>> {
+    let signed_delta = determine_delta(args)?;
+
+    let connection = session_bus()?;
+    let proxy = connection.with_proxy("rs.wl-gammarelay", "/", Duration::from_secs(1));
+
+    let brightness = undo::record(
+        || Ok(Snapshot::Brightness(read_brightness(&proxy)?.round() as u32)),
+        || {
+            proxy.method_call::<(), _, _, _>(
+                "rs.wl.gammarelay",
+                "UpdateBrightness",
+                (signed_delta,),
+            )?;
+            read_brightness(&proxy)
+        },
+    )?;
+
     write_brightness_to_backing_file(brightness)?;
+    Notification::new("Brightness")
+        .body(format!("{}%", brightness.round()))
+        .icon("display-brightness")
+        .progress(brightness.round() as u32)
+        .tag("brightness")
+        .send();
 
-    Ok(None)
+    Ok(Some(ScriptOutput::new(&(brightness.round() as u32))?))
 }
@@ -1,11 +1,23 @@
 use clap::{arg, value_parser, ArgMatches, Command, ValueEnum};
-use dbus::{arg, blocking::Connection};
-use std::fs::OpenOptions;
-use std::io::{LineWriter, Write};
+use dbus::{
+    arg,
+    blocking::{stdintf::org_freedesktop_dbus::Properties, Proxy, SyncConnection},
+};
 use std::time::Duration;
 use xshell::Shell;
 
-use crate::system_atlas::SYSTEM_ATLAS;
+use crate::{
+    system_atlas::SYSTEM_ATLAS,
+    util::{
+        backing_file,
+        bus::{self, Topic},
+        notify::Notification,
+        session_bus,
+    },
+    ScriptOutput,
+};
+
+use super::undo::{self, Snapshot};
 
 #[derive(ValueEnum, Clone, Debug)]
 enum Direction {
@@ -43,30 +55,57 @@ fn determine_delta(args: &ArgMatches) -> anyhow::Result<i16> {
 }
 
 fn write_colortemp_to_backing_file(colortemp: u16) -> anyhow::Result<()> {
-    let file = OpenOptions::new()
-        .append(true)
-        .open(SYSTEM_ATLAS.eww_colortemp)?;
-    let mut writer = LineWriter::new(&file);
-    writeln!(writer, "{}", colortemp)?;
+    backing_file::append_line(&SYSTEM_ATLAS.eww_colortemp, &colortemp.to_string())?;
 
-    Ok(())
+    bus::publish(Topic::Colortemp, &colortemp)
 }
 
-pub fn run(_: &Shell, args: &ArgMatches) -> anyhow::Result<Option<String>> {
-    let delta = determine_delta(args)?;
-
-    let connection = Connection::new_session()?;
-    let proxy = connection.with_proxy("rs.wl-gammarelay", "/", Duration::from_secs(1));
-
-    proxy.method_call::<(), _, _, _>("rs.wl.gammarelay", "UpdateTemperature", (delta,))?;
-    use dbus::blocking::stdintf::org_freedesktop_dbus::Properties;
-
+fn read_colortemp(proxy: &Proxy<'_, &SyncConnection>) -> anyhow::Result<u16> {
     let colortemp_refarg: Box<dyn arg::RefArg> = proxy.get("rs.wl.gammarelay", "Temperature")?;
     let colortemp = colortemp_refarg.as_u64().ok_or(anyhow::anyhow!(
         "rs.wl.gammarelay.Temperature is not an unsigned value"
     ))?;
 
-    write_colortemp_to_backing_file(colortemp as u16)?;
+    Ok(colortemp as u16)
+}
+
+/// The current color temperature, in Kelvin
+pub fn get_colortemp() -> anyhow::Result<u16> {
+    let connection = session_bus()?;
+    let proxy = connection.with_proxy("rs.wl-gammarelay", "/", Duration::from_secs(1));
+
+    read_colortemp(&proxy)
+}
+
+/// Set the color temperature, in Kelvin
+pub fn set_colortemp(colortemp: u16) -> anyhow::Result<()> {
+    let connection = session_bus()?;
+    let proxy = connection.with_proxy("rs.wl-gammarelay", "/", Duration::from_secs(1));
+
+    proxy.set("rs.wl.gammarelay", "Temperature", colortemp)?;
+    write_colortemp_to_backing_file(colortemp)
+}
+
+pub fn run(_: &Shell, args: &ArgMatches) -> anyhow::Result<Option<ScriptOutput>> {
+    let delta = determine_delta(args)?;
+
+    let connection = session_bus()?;
+    let proxy = connection.with_proxy("rs.wl-gammarelay", "/", Duration::from_secs(1));
+
+    let colortemp = undo::record(
+        || Ok(Snapshot::Colortemp(read_colortemp(&proxy)?)),
+        || {
+            proxy.method_call::<(), _, _, _>("rs.wl.gammarelay", "UpdateTemperature", (delta,))?;
+            read_colortemp(&proxy)
+        },
+    )?;
+
+    write_colortemp_to_backing_file(colortemp)?;
+    Notification::new("Color temperature")
+        .body(format!("{}K", colortemp))
+        .icon("weather-clear-night")
+        .tag("colortemp")
+        .send();
 
-    Ok(None)
+    Ok(Some(ScriptOutput::new(&colortemp)?))
 }
@@ -0,0 +1,69 @@
+use std::path::PathBuf;
+
+use clap::{arg, value_parser, ArgMatches, Command, ValueEnum};
+use clap_complete::env::{Bash, EnvCompleter, Fish, Zsh};
+use xshell::Shell;
+
+use crate::ScriptOutput;
+
+/// The variable which makes plsdo print completions instead of running a subcommand
+pub const COMPLETE_VAR: &str = "COMPLETE";
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl CompletionShell {
+    fn completer(self) -> &'static dyn EnvCompleter {
+        match self {
+            Self::Bash => &Bash,
+            Self::Zsh => &Zsh,
+            Self::Fish => &Fish,
+        }
+    }
+}
+
+pub fn command_extension(cmd: Command) -> Command {
+    cmd.long_about(
+        "Print the completion script for the given shell. The script calls back into plsdo, so \
+that monitor names, audio outputs, pinned programs and keyboard layouts are completed too. \
+Source it from the shell's startup file, e.g. `source <(plsdo completions bash)`.",
+    )
+    .arg(
+        arg!([SHELL] "The shell to generate completions for")
+            .value_parser(value_parser!(CompletionShell))
+            .required(true),
+    )
+}
+
+/// The path the completion script should call plsdo with: the one plsdo was invoked with, made
+/// absolute if it's relative to the current directory
+fn completer_path() -> anyhow::Result<String> {
+    let mut path = PathBuf::from(std::env::args_os().next().unwrap_or("plsdo".into()));
+    if path.components().count() > 1 && path.is_relative() {
+        path = std::env::current_dir()?.join(path);
+    }
+    Ok(path.to_string_lossy().into_owned())
+}
+
+pub fn run(_: &Shell, args: &ArgMatches) -> anyhow::Result<Option<ScriptOutput>> {
+    let shell = *args
+        .get_one::<CompletionShell>("SHELL")
+        .expect("SHELL should be a required argument");
+
+    let name = crate::get_command().get_name().to_owned();
+    let mut script = Vec::new();
+    shell.completer().write_registration(
+        COMPLETE_VAR,
+        &name,
+        &name,
+        &completer_path()?,
+        &mut script,
+    )?;
+
+    let script = String::from_utf8(script)?;
+    Ok(Some(ScriptOutput::new(&script)?))
+}
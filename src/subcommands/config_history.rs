@@ -0,0 +1,86 @@
+use std::{
+    fmt::Display,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use clap::{arg, value_parser, ArgMatches, Command};
+use xshell::Shell;
+
+use crate::{
+    system_atlas::SYSTEM_ATLAS,
+    util::config_edit::{self, backups::Backup},
+    ScriptOutput,
+};
+
+pub fn command_extension(cmd: Command) -> Command {
+    let inner_subcommands = [
+        Command::new("list").about("List the snapshots taken before config edits, newest first"),
+        Command::new("restore")
+            .about("Roll a config file back to a snapshot")
+            .arg(
+                arg!([ID] "The id of the snapshot, as shown by `list`")
+                    .value_parser(value_parser!(u64))
+                    .required(true),
+            ),
+    ];
+
+    cmd.subcommand_required(true)
+        .subcommands(inner_subcommands.iter())
+}
+
+/// How long ago the timestamp was, e.g. "5m ago"
+fn format_age(timestamp: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(timestamp, |now| now.as_secs());
+    let seconds = now.saturating_sub(timestamp);
+
+    match seconds {
+        0..=59 => format!("{}s ago", seconds),
+        60..=3599 => format!("{}m ago", seconds / 60),
+        3600..=86399 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    }
+}
+
+struct BackupLine<'a>(&'a Backup);
+
+impl Display for BackupLine<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let backup = self.0;
+        write!(
+            f,
+            "{:>4}  {:>8}  {}  (plsdo {})",
+            backup.id,
+            format_age(backup.created),
+            backup.path,
+            backup.command
+        )
+    }
+}
+
+pub fn run(_: &Shell, args: &ArgMatches) -> anyhow::Result<Option<ScriptOutput>> {
+    match args.subcommand() {
+        Some(("list", _)) => {
+            let all_backups =
+                config_edit::backups::list(std::path::Path::new(&SYSTEM_ATLAS.config_backups))?;
+            let text = all_backups
+                .iter()
+                .map(|backup| BackupLine(backup).to_string())
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            Ok(Some(ScriptOutput::new(&all_backups)?.with_text(text)))
+        }
+        Some(("restore", restore_args)) => {
+            let id = *restore_args
+                .get_one::<u64>("ID")
+                .expect("ID should be a required argument");
+            let backup = config_edit::restore(id)?;
+
+            let text = format!("Restored {} from snapshot {}", backup.path, backup.id);
+            Ok(Some(ScriptOutput::new(&backup)?.with_text(text)))
+        }
+        _ => Ok(None),
+    }
+}
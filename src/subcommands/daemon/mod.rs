@@ -0,0 +1,180 @@
+//! The daemon supervises the long-running components of plsdo (the listeners, the ytdl aggregator
+//! and the server which runs commands for other plsdo processes), so that only a single process
+//! has to be started with the desktop session. It also hosts the state bus (see `util::bus`).
+//!
+//! Components run as child processes of the daemon: the listeners block inside library code which
+//! offers no way to be interrupted, so killing the process is the only reliable way to stop or
+//! restart them individually.
+
+use std::{fmt::Display, os::unix::net::UnixDatagram, time::Duration};
+
+use anyhow::Context;
+use clap::{arg, value_parser, ArgMatches, Command, ValueEnum};
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+use strum_macros::{Display, EnumIter};
+use xshell::Shell;
+
+use crate::{system_atlas::SYSTEM_ATLAS, util::listener::get_running_pid, ScriptOutput};
+
+mod supervisor;
+
+#[derive(
+    ValueEnum, Serialize, Deserialize, Display, EnumIter, Clone, Copy, Debug, PartialEq, Eq, Hash,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Component {
+    #[cfg(feature = "udev")]
+    Audio,
+    #[cfg(feature = "hyprland")]
+    Workspace,
+    Ytdl,
+    Server,
+}
+
+impl Component {
+    /// The plsdo arguments which launch the component
+    fn args(&self) -> &'static [&'static str] {
+        match self {
+            #[cfg(feature = "udev")]
+            Self::Audio => &["audio", "run_listener"],
+            #[cfg(feature = "hyprland")]
+            Self::Workspace => &["workspace", "run_listener"],
+            Self::Ytdl => &["ytdl", "run_aggregator"],
+            Self::Server => &["serve"],
+        }
+    }
+
+    fn pidfile(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "udev")]
+            Self::Audio => &SYSTEM_ATLAS.audio_listener_pidfile,
+            #[cfg(feature = "hyprland")]
+            Self::Workspace => &SYSTEM_ATLAS.workspace_listener_pidfile,
+            Self::Ytdl => &SYSTEM_ATLAS.ytdl_aggregator_pidfile,
+            Self::Server => &SYSTEM_ATLAS.server_pidfile,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+enum Request {
+    Start(Component),
+    Stop(Option<Component>),
+    Restart(Option<Component>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+enum Response {
+    Done,
+    Failed(String),
+}
+
+#[derive(Serialize, Debug)]
+struct ProcessStatus {
+    name: String,
+    pid: Option<u32>,
+}
+
+impl Display for ProcessStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.pid {
+            Some(pid) => write!(f, "{:<10} running (pid {})", self.name, pid),
+            None => write!(f, "{:<10} stopped", self.name),
+        }
+    }
+}
+
+pub fn command_extension(cmd: Command) -> Command {
+    let inner_subcommands = [
+        Command::new("status").about("Show which components are running"),
+        Command::new("start")
+            .about("Start a component which was stopped, or is waiting to be restarted")
+            .arg(
+                arg!([COMPONENT] "The component to start")
+                    .value_parser(value_parser!(Component))
+                    .required(true),
+            ),
+        Command::new("stop")
+            .about("Stop a component, or the daemon itself if no component is given")
+            .arg(arg!([COMPONENT] "The component to stop").value_parser(value_parser!(Component))),
+        Command::new("restart")
+            .about("Restart a component, or all of them if no component is given")
+            .arg(
+                arg!([COMPONENT] "The component to restart").value_parser(value_parser!(Component)),
+            ),
+    ];
+
+    cmd.subcommands(inner_subcommands.iter())
+}
+
+fn get_status() -> anyhow::Result<ScriptOutput> {
+    let mut statuses = vec![ProcessStatus {
+        name: "daemon".to_owned(),
+        pid: get_running_pid(&SYSTEM_ATLAS.daemon_pidfile)?,
+    }];
+
+    for component in Component::iter() {
+        statuses.push(ProcessStatus {
+            name: component.to_string(),
+            pid: get_running_pid(component.pidfile())?,
+        });
+    }
+
+    let text = statuses
+        .iter()
+        .map(|s| s.to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Ok(ScriptOutput::new(&statuses)?.with_text(text))
+}
+
+/// How long a client waits for the daemon to answer a request
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn send_request(request: &Request) -> anyhow::Result<()> {
+    let client_path = format!(
+        "{}.client-{}",
+        SYSTEM_ATLAS.daemon_socket,
+        std::process::id()
+    );
+    let _ = std::fs::remove_file(&client_path);
+    let socket = UnixDatagram::bind(&client_path)?;
+    socket.set_read_timeout(Some(REPLY_TIMEOUT))?;
+
+    let result = (|| {
+        socket
+            .connect(&SYSTEM_ATLAS.daemon_socket)
+            .context("Cannot connect to the daemon; is it running?")?;
+        socket.send(serde_json::to_string(request)?.as_bytes())?;
+
+        let mut buf = vec![0; 1024];
+        let n = socket.recv(&mut buf).context("The daemon did not answer")?;
+        match serde_json::from_slice::<Response>(&buf[..n])? {
+            Response::Done => Ok(()),
+            Response::Failed(reason) => Err(anyhow::anyhow!(reason)),
+        }
+    })();
+
+    let _ = std::fs::remove_file(&client_path);
+    result
+}
+
+pub fn run(_: &Shell, args: &ArgMatches) -> anyhow::Result<Option<ScriptOutput>> {
+    let component = |args: &ArgMatches| args.get_one::<Component>("COMPONENT").copied();
+
+    match args.subcommand() {
+        None => supervisor::run(),
+        Some(("status", _)) => return get_status().map(Some),
+        Some(("start", start_args)) => send_request(&Request::Start(
+            component(start_args).expect("COMPONENT should be a required argument"),
+        )),
+        Some(("stop", stop_args)) => send_request(&Request::Stop(component(stop_args))),
+        Some(("restart", restart_args)) => send_request(&Request::Restart(component(restart_args))),
+        _ => Ok(()),
+    }?;
+
+    Ok(None)
+}
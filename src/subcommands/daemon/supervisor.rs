@@ -0,0 +1,251 @@
+use std::{
+    io::ErrorKind,
+    os::unix::net::{SocketAddr, UnixDatagram},
+    process::{Child, Command as StdCommand},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use strum::IntoEnumIterator;
+
+use crate::{
+    system_atlas::SYSTEM_ATLAS,
+    util::{
+        bus,
+        listener::{get_pidfile_lock, get_running_pid, write_pid},
+        logging,
+    },
+};
+
+use super::{Component, Request, Response};
+
+/// How often the children are checked, if no requests are coming in
+const TICK: Duration = Duration::from_millis(250);
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// If a component has been running for at least this long before exiting, it is considered to
+/// have been healthy, and is restarted without waiting
+const HEALTHY_UPTIME: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+enum State {
+    Running {
+        child: Child,
+        since: Instant,
+    },
+    /// Waiting to be (re)started
+    Waiting {
+        until: Instant,
+    },
+    /// Stopped on request; the component is only started again on request
+    Stopped,
+}
+
+struct Supervised {
+    component: Component,
+    state: State,
+    backoff: Duration,
+}
+
+impl Supervised {
+    fn new(component: Component) -> Self {
+        Self {
+            component,
+            state: State::Waiting {
+                until: Instant::now(),
+            },
+            backoff: INITIAL_BACKOFF,
+        }
+    }
+
+    fn spawn(&mut self) {
+        // Components log at the same level as the daemon
+        let log_level = log::max_level().as_str().to_lowercase();
+        let child = std::env::current_exe().and_then(|exe| {
+            StdCommand::new(exe)
+                .args(["--log-level", &log_level])
+                .args(self.component.args())
+                .spawn()
+        });
+
+        self.state = match child {
+            Ok(child) => {
+                log::info!(component:% = self.component, pid = child.id(); "Started component");
+                State::Running {
+                    child,
+                    since: Instant::now(),
+                }
+            }
+            Err(err) => {
+                log::error!(component:% = self.component, error:% = err; "Failed to start component");
+                self.wait_before_restarting()
+            }
+        };
+    }
+
+    fn kill(&mut self) {
+        if let State::Running { child, .. } = &mut self.state {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+
+    fn wait_before_restarting(&mut self) -> State {
+        let until = Instant::now() + self.backoff;
+        log::debug!(component:% = self.component, backoff_secs = self.backoff.as_secs(); "Waiting before restarting component");
+        self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+        State::Waiting { until }
+    }
+
+    /// Check up on the component, and (re)start it if it's time to
+    fn tick(&mut self) {
+        match &mut self.state {
+            State::Running { child, since } => {
+                let status = match child.try_wait() {
+                    Ok(Some(status)) => status,
+                    Ok(None) => return,
+                    Err(err) => {
+                        log::error!(component:% = self.component, error:% = err; "Failed to check on component");
+                        return;
+                    }
+                };
+
+                log::warn!(
+                    component:% = self.component,
+                    status:% = status,
+                    uptime_secs = since.elapsed().as_secs();
+                    "Component exited"
+                );
+                // A component which exits because another instance holds its pidfile would
+                // only exit again when restarted
+                if let Ok(Some(pid)) = get_running_pid(self.component.pidfile()) {
+                    log::warn!(
+                        component:% = self.component,
+                        pid = pid;
+                        "Component is already running outside the daemon; not restarting it"
+                    );
+                    self.state = State::Stopped;
+                    return;
+                }
+
+                if since.elapsed() >= HEALTHY_UPTIME {
+                    self.backoff = INITIAL_BACKOFF;
+                }
+                self.state = self.wait_before_restarting();
+            }
+            State::Waiting { until } if Instant::now() >= *until => self.spawn(),
+            State::Waiting { .. } | State::Stopped => {}
+        }
+    }
+
+    fn stop(&mut self) {
+        self.kill();
+        self.state = State::Stopped;
+    }
+
+    fn restart(&mut self) {
+        self.kill();
+        self.backoff = INITIAL_BACKOFF;
+        self.spawn();
+    }
+}
+
+fn start_socket() -> anyhow::Result<UnixDatagram> {
+    let path = &SYSTEM_ATLAS.daemon_socket;
+    let _ = std::fs::remove_file(path);
+    let socket = UnixDatagram::bind(path)?;
+    socket.set_read_timeout(Some(TICK))?;
+    Ok(socket)
+}
+
+fn reply(socket: &UnixDatagram, addr: &SocketAddr, response: &Response) -> anyhow::Result<()> {
+    let path = addr
+        .as_pathname()
+        .ok_or_else(|| anyhow::anyhow!("Client socket is unbound; can't reply to it"))?;
+    socket.send_to(serde_json::to_string(response)?.as_bytes(), path)?;
+    Ok(())
+}
+
+/// Handle a request from a client. Returns false if the daemon should exit.
+fn handle_request(components: &mut [Supervised], request: Request) -> bool {
+    log::info!(request:? = request; "Received request");
+    match request {
+        Request::Start(component) => components
+            .iter_mut()
+            .filter(|s| {
+                s.component == component
+                    && matches!(s.state, State::Stopped | State::Waiting { .. })
+            })
+            .for_each(Supervised::restart),
+        Request::Stop(None) => {
+            components.iter_mut().for_each(Supervised::kill);
+            return false;
+        }
+        Request::Stop(Some(component)) => components
+            .iter_mut()
+            .filter(|s| s.component == component)
+            .for_each(Supervised::stop),
+        Request::Restart(component) => components
+            .iter_mut()
+            .filter(|s| component.is_none_or(|c| s.component == c))
+            .for_each(Supervised::restart),
+    }
+
+    true
+}
+
+/// Launch the daemon, which hosts the state bus, starts every component and keeps them running
+/// until it is told to stop
+pub fn run() -> anyhow::Result<()> {
+    let mut lock = get_pidfile_lock(&SYSTEM_ATLAS.daemon_pidfile)?;
+    let mut guard = lock.try_write().context("The daemon is already running")?;
+    write_pid(&mut guard)?;
+    logging::log_to_file("daemon")?;
+    log::info!("Daemon started");
+
+    std::thread::spawn(|| {
+        if let Err(err) = bus::serve() {
+            log::error!(error = format!("{err:#}"); "State bus stopped");
+        }
+    });
+
+    let socket = start_socket()?;
+    let mut components = Component::iter().map(Supervised::new).collect::<Vec<_>>();
+    let mut buf = vec![0; 1024];
+
+    loop {
+        match socket.recv_from(&mut buf) {
+            Ok((n, addr)) => {
+                let (keep_running, response) = match serde_json::from_slice::<Request>(&buf[..n]) {
+                    Ok(request) => (handle_request(&mut components, request), Response::Done),
+                    Err(err) => (true, Response::Failed(err.to_string())),
+                };
+
+                if let Err(err) = reply(&socket, &addr, &response) {
+                    log::warn!(error = format!("{err:#}"); "Failed to reply to client");
+                }
+
+                if !keep_running {
+                    log::info!("Daemon stopped");
+                    let _ = std::fs::remove_file(&SYSTEM_ATLAS.daemon_socket);
+                    let _ = std::fs::remove_file(&SYSTEM_ATLAS.state_bus_socket);
+                    return Ok(());
+                }
+            }
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted
+                ) => {}
+            Err(err) => {
+                // Children left running would hold their pidfiles, and the next daemon would
+                // take them for instances which run outside of it
+                log::error!(error:% = err; "Failed to receive requests; stopping the components");
+                components.iter_mut().for_each(Supervised::kill);
+                return Err(err.into());
+            }
+        }
+
+        components.iter_mut().for_each(Supervised::tick);
+    }
+}
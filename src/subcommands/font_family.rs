@@ -1,8 +1,11 @@
 use crate::{
     system_atlas::SYSTEM_ATLAS,
-    util::{self, dmenu::Dmenu},
+    util::{
+        config_edit::{self, FontconfigDocument, Value},
+        dmenu::Dmenu,
+    },
+    ScriptOutput,
 };
-use std::io::Write;
 
 use clap::{ArgMatches, Command};
 use xshell::Shell;
@@ -11,61 +14,58 @@ pub fn command_extension(cmd: Command) -> Command {
     cmd
 }
 
-pub fn run(sh: &Shell, _: &ArgMatches) -> anyhow::Result<Option<String>> {
-    util::modify_file(
-        SYSTEM_ATLAS.fontconfig,
-        "<family>monospace</family>\n",
-        |lines, writer| {
-            let mut font_families = Vec::new();
+/// Move the family to the front of the preferred monospace families
+fn prefer_family(fontconfig: &mut FontconfigDocument, family: &str) -> anyhow::Result<()> {
+    let font_families = fontconfig.preferred_families("monospace")?;
 
-            for line in lines.by_ref() {
-                if line.trim().starts_with("</prefer>") {
-                    break;
-                }
+    let reordered = std::iter::once(family.to_owned())
+        .chain(font_families.iter().filter(|&f| f != family).cloned())
+        .collect::<Vec<_>>();
+    fontconfig.set_preferred_families("monospace", &reordered)
+}
 
-                if let Some(family) = line.trim().strip_prefix("<family>") {
-                    if let Some(family) = family.strip_suffix("</family>") {
-                        // Ignore icons fonts. Selecting an icon font as the main font in fontconfig works
-                        // fine, but setting it as default in alacritty would break it
-                        if !family.to_lowercase().contains("icon") {
-                            font_families.push(family);
-                        }
-                    }
-                }
-            }
+fn set_alacritty_family(family: &str) -> anyhow::Result<()> {
+    config_edit::edit(&SYSTEM_ATLAS.alacritty, |config| {
+        config.set("font.normal.family", Value::String(family.to_owned()))
+    })
+}
 
-            // unwrap: we don't want to continue if the string is empty
-            let chosen = Dmenu::new(sh)
-                .choose_one_str("Choose font family", &font_families)
-                .unwrap();
+/// The font family in the alacritty config, if it sets one
+pub fn get_font_family() -> anyhow::Result<Option<String>> {
+    config_edit::read(&SYSTEM_ATLAS.alacritty, |config| {
+        Ok(config
+            .get("font.normal.family")?
+            .and_then(|value| value.as_str().map(str::to_owned)))
+    })
+}
 
-            util::modify_file(SYSTEM_ATLAS.alacritty, "font:\n", |lines, writer| {
-                for line in lines.by_ref() {
-                    if line.trim().starts_with("family:") {
-                        let _ = writer.write(b"    family: ")?;
-                        _ = writer.write(chosen.as_bytes())?;
-                        _ = writer.write(b"\n");
-                        break;
-                    }
+/// Make the family the preferred monospace font, in fontconfig and alacritty
+pub fn set_font_family(family: &str) -> anyhow::Result<()> {
+    config_edit::edit_fontconfig(&SYSTEM_ATLAS.fontconfig, |fontconfig| {
+        prefer_family(fontconfig, family)
+    })?;
+    set_alacritty_family(family)
+}
 
-                    writer.write_all(line.as_bytes())?;
-                    let _ = writer.write(b"\n")?;
-                }
+pub fn run(sh: &Shell, _: &ArgMatches) -> anyhow::Result<Option<ScriptOutput>> {
+    let chosen = config_edit::edit_fontconfig(&SYSTEM_ATLAS.fontconfig, |fontconfig| {
+        let font_families = fontconfig.preferred_families("monospace")?;
 
-                Ok(())
-            })?;
+        // Ignore icons fonts. Selecting an icon font as the main font in fontconfig works fine,
+        // but setting it as default in alacritty would break it
+        let choices = font_families
+            .iter()
+            .map(String::as_str)
+            .filter(|family| !family.to_lowercase().contains("icon"))
+            .collect::<Vec<_>>();
 
-            writer.write_all(b"    <prefer>\n")?;
-            writer.write_all(format!("      <family>{}</family>\n", chosen).as_bytes())?;
+        let chosen = Dmenu::new(sh).choose_one_str("Choose font family", &choices)?;
+        prefer_family(fontconfig, &chosen)?;
 
-            for family in font_families.iter().filter(|&&f| f != chosen) {
-                writer.write_all(format!("      <family>{}</family>\n", family).as_bytes())?;
-            }
-            writer.write_all(b"    </prefer>\n")?;
+        Ok(chosen)
+    })?;
 
-            Ok(())
-        },
-    )?;
+    set_alacritty_family(&chosen)?;
 
-    Ok(None)
+    Ok(Some(ScriptOutput::new(&chosen)?))
 }
@@ -1,8 +1,13 @@
-use crate::{system_atlas::SYSTEM_ATLAS, util};
+use crate::{
+    system_atlas::SYSTEM_ATLAS,
+    util::config_edit::{self, Value},
+    ScriptOutput,
+};
 use clap::{arg, value_parser, ArgMatches, Command, ValueEnum};
-use std::io::Write;
 use xshell::Shell;
 
+use super::undo::{self, Snapshot};
+
 #[derive(ValueEnum, Clone, Debug)]
 enum Direction {
     Up,
@@ -22,37 +27,67 @@ pub fn command_extension(cmd: Command) -> Command {
     )
 }
 
-pub fn run(_sh: &Shell, args: &ArgMatches) -> anyhow::Result<Option<String>> {
+/// The font size in the alacritty config, if it sets one
+pub fn get_font_size() -> anyhow::Result<Option<f64>> {
+    config_edit::read(&SYSTEM_ATLAS.alacritty, |config| {
+        match config.get("font.size")? {
+            None => Ok(None),
+            Some(value) => value
+                .as_f64()
+                .map(Some)
+                .ok_or(anyhow::anyhow!("The current font size is not a number")),
+        }
+    })
+}
+
+/// Set the font size in the alacritty config. Whole sizes are written as integers.
+pub fn set_font_size(size: f64) -> anyhow::Result<()> {
+    let value = if size.fract() == 0.0 {
+        Value::Integer(size as i64)
+    } else {
+        Value::Float(size)
+    };
+
+    config_edit::edit(&SYSTEM_ATLAS.alacritty, |config| {
+        config.set("font.size", value)
+    })
+}
+
+pub fn run(_sh: &Shell, args: &ArgMatches) -> anyhow::Result<Option<ScriptOutput>> {
     let dir = args.get_one::<Direction>("direction");
 
     // unwrap: argument is required
-    let delta = args.get_one::<i32>("DELTA").unwrap();
-
-    util::modify_file(SYSTEM_ATLAS.alacritty, "# Point size\n", |lines, writer| {
-        let previous_value_line = lines
-            .next()
-            .ok_or_else(|| anyhow::anyhow!("Line containing previous value not found"))?;
-
-        let new_value = if let Some(dir) = dir {
-            let previous_value = previous_value_line
-                .trim()
-                .split_once(' ')
-                .ok_or_else(|| anyhow::anyhow!("Could not split line containing previous value"))?
-                .1
-                .parse::<i32>()?;
-
-            match dir {
-                Direction::Up => previous_value + delta,
-                Direction::Down => previous_value - delta,
-            }
-        } else {
-            *delta
-        };
-
-        writer.write_all(format!("  size: {}\n", new_value).as_bytes())?;
-
-        Ok(())
+    let delta = *args.get_one::<i32>("DELTA").unwrap() as i64;
+
+    let capture = || {
+        let size =
+            get_font_size()?.ok_or(anyhow::anyhow!("The alacritty config sets no font size"))?;
+        Ok(Snapshot::FontSize(size))
+    };
+
+    let new_size = undo::record(capture, || {
+        config_edit::edit(&SYSTEM_ATLAS.alacritty, |config| {
+            let previous_value = config.get("font.size")?;
+
+            let new_value = match (dir, previous_value) {
+                (None, _) => Value::Integer(delta),
+                (Some(dir), Some(Value::Integer(previous))) => Value::Integer(match dir {
+                    Direction::Up => previous + delta,
+                    Direction::Down => previous - delta,
+                }),
+                (Some(dir), Some(Value::Float(previous))) => Value::Float(match dir {
+                    Direction::Up => previous + delta as f64,
+                    Direction::Down => previous - delta as f64,
+                }),
+                (Some(_), _) => anyhow::bail!("The current font size is not a number"),
+            };
+
+            config.set("font.size", new_value.clone())?;
+            Ok(new_value)
+        })
     })?;
 
-    Ok(None)
+    Ok(Some(
+        ScriptOutput::new(&new_size.as_f64())?.with_text(new_size),
+    ))
 }
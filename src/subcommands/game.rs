@@ -1,15 +1,27 @@
 use clap::{arg, ArgMatches, Command};
-use xshell::{cmd, Shell};
+use xshell::Shell;
 
-use crate::util::dmenu::Dmenu;
+use crate::{
+    util::{
+        dmenu::Dmenu,
+        runner::{CommandRunner, Invocation},
+    },
+    ScriptOutput,
+};
 
 pub fn command_extension(cmd: Command) -> Command {
     cmd.arg(arg!([GAME]))
 }
 
-pub fn run(sh: &Shell, args: &ArgMatches) -> anyhow::Result<Option<String>> {
-    let dmenu = Dmenu::new(sh);
-    let list_output = cmd!(sh, "lutris -l").ignore_stderr().read()?;
+pub fn run(sh: &Shell, args: &ArgMatches) -> anyhow::Result<Option<ScriptOutput>> {
+    launch(sh, args.get_one::<String>("GAME").map(String::as_str))?;
+    Ok(None)
+}
+
+/// Launch the game matching the search, letting the user choose if there isn't exactly one
+fn launch(runner: &dyn CommandRunner, game: Option<&str>) -> anyhow::Result<()> {
+    let dmenu = Dmenu::new(runner);
+    let list_output = runner.read(&Invocation::new("lutris").arg("-l").ignore_stderr())?;
     let mut choices = list_output
         .split('\n')
         .map(|s| s.split('|').take(2).collect::<Vec<_>>().join("|"))
@@ -18,7 +30,7 @@ pub fn run(sh: &Shell, args: &ArgMatches) -> anyhow::Result<Option<String>> {
 
     let mut filtered_choices = choices.clone();
     let search = "";
-    if let Some(search) = args.get_one::<String>("GAME") {
+    if let Some(search) = game {
         filtered_choices.retain(|name| name.contains(search));
     }
 
@@ -27,23 +39,61 @@ pub fn run(sh: &Shell, args: &ArgMatches) -> anyhow::Result<Option<String>> {
     let result = if filtered_choices.len() == 1 {
         filtered_choices[0].as_str()
     } else if filtered_choices.is_empty() {
-        dmenu
-            .choose_one(
-                &format!("Choose game (no matches found for '{search}')"),
-                &choices,
-                String::as_ref,
-            )
-            .unwrap()
+        dmenu.choose_one(
+            &format!("Choose game (no matches found for '{search}')"),
+            &choices,
+            String::as_ref,
+        )?
     } else {
-        // unwrap: we don't want to continue if result is empty
-        dmenu
-            .choose_one("Choose game", &filtered_choices, String::as_ref)
-            .unwrap()
+        dmenu.choose_one("Choose game", &filtered_choices, String::as_ref)?
     };
 
     // unwrap: result always contains a pipe, and the first element is always a number
     let num = result.split('|').next().unwrap().trim();
 
-    let _ = cmd!(sh, "lutris lutris:rungameid/{num}").run();
-    Ok(None)
+    let _ = runner.run(&Invocation::new("lutris").arg(format!("lutris:rungameid/{}", num)));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config,
+        util::{dmenu::is_aborted, runner::FakeRunner},
+    };
+
+    use super::*;
+
+    #[test]
+    fn single_match_is_launched_without_a_menu() {
+        let fake = FakeRunner::new().respond(
+            "lutris -l",
+            "12 | Celeste | celeste\n7 | Hades | hades\n",
+            0,
+        );
+
+        launch(&fake, Some("Hades")).unwrap();
+
+        assert_eq!(
+            fake.command_lines(),
+            ["lutris -l", "lutris lutris:rungameid/7"]
+        );
+    }
+
+    #[test]
+    fn closing_the_menu_launches_nothing() {
+        config::init_for_tests();
+        let fake = FakeRunner::new()
+            .respond(
+                "lutris -l",
+                "12 | Celeste | celeste\n7 | Hades | hades\n",
+                0,
+            )
+            .respond("dmenu -p Choose game -i -l 2", "", 1);
+
+        let err = launch(&fake, None).unwrap_err();
+
+        assert!(is_aborted(&err));
+        assert_eq!(fake.command_lines().len(), 2);
+    }
 }
@@ -0,0 +1,408 @@
+//! Keybinds which run plsdo, declared once in the `keybinds` section of the config and turned
+//! into config fragments for Hyprland and sxhkd. Each key is a chord, and each value the
+//! arguments of plsdo:
+//!
+//! ```toml
+//! [keybinds]
+//! "SUPER+SHIFT+S" = ["screenshot", "area"]
+//! "XF86AudioRaiseVolume" = ["audio", "volume", "set", "--relative", "--", "5"]
+//! ```
+//!
+//! The arguments are checked against the command line of plsdo before anything is written, so
+//! that a renamed subcommand breaks `keybinds generate` instead of the keybind.
+
+use std::{collections::BTreeMap, fmt::Display, fs, path::Path};
+
+use anyhow::Context;
+use clap::{ArgMatches, Command};
+use serde::Serialize;
+use xshell::Shell;
+
+use crate::{config, system_atlas::SYSTEM_ATLAS, util::dmenu::Dmenu, ScriptOutput};
+
+const HEADER: &str =
+    "# Generated by `plsdo keybinds generate` from the plsdo config; changes will be overwritten";
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Modifier {
+    Super,
+    Shift,
+    Ctrl,
+    Alt,
+}
+
+impl Modifier {
+    fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "super" | "mod4" => Some(Self::Super),
+            "shift" => Some(Self::Shift),
+            "ctrl" | "control" => Some(Self::Ctrl),
+            "alt" | "mod1" => Some(Self::Alt),
+            _ => None,
+        }
+    }
+
+    fn hyprland(self) -> &'static str {
+        match self {
+            Self::Super => "SUPER",
+            Self::Shift => "SHIFT",
+            Self::Ctrl => "CTRL",
+            Self::Alt => "ALT",
+        }
+    }
+
+    fn sxhkd(self) -> &'static str {
+        match self {
+            Self::Super => "super",
+            Self::Shift => "shift",
+            Self::Ctrl => "ctrl",
+            Self::Alt => "alt",
+        }
+    }
+}
+
+/// The modifiers and the key of a keybind, e.g. "SUPER+SHIFT+S"
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+struct Chord {
+    modifiers: Vec<Modifier>,
+    /// The name of the key as an X keysym, e.g. "S" or "XF86AudioMute"
+    key: String,
+}
+
+impl Chord {
+    fn parse(chord: &str) -> anyhow::Result<Self> {
+        let mut parts = chord.split('+').map(str::trim).collect::<Vec<_>>();
+        let key = parts.pop().filter(|key| !key.is_empty());
+        let key = key.ok_or_else(|| anyhow::anyhow!("The chord has no key"))?;
+
+        let modifiers = parts
+            .into_iter()
+            .map(|name| {
+                Modifier::parse(name).ok_or_else(|| anyhow::anyhow!("Unknown modifier '{}'", name))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self {
+            modifiers,
+            key: key.to_owned(),
+        })
+    }
+
+    /// The modifiers and the key as the first two fields of a `bind` line
+    fn hyprland(&self) -> String {
+        let modifiers = self
+            .modifiers
+            .iter()
+            .map(|modifier| modifier.hyprland())
+            .collect::<Vec<_>>();
+        format!("{}, {}", modifiers.join(" "), self.key)
+    }
+
+    fn sxhkd(&self) -> String {
+        // The keysyms of letters are lower case; an upper case one means that shift is held
+        let key = if self.key.len() == 1 {
+            self.key.to_lowercase()
+        } else {
+            self.key.clone()
+        };
+        self.modifiers
+            .iter()
+            .map(|modifier| modifier.sxhkd())
+            .chain(std::iter::once(key.as_str()))
+            .collect::<Vec<_>>()
+            .join(" + ")
+    }
+}
+
+impl Display for Chord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for modifier in &self.modifiers {
+            write!(f, "{}+", modifier.hyprland())?;
+        }
+        write!(f, "{}", self.key)
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct Keybind {
+    chord: Chord,
+    args: Vec<String>,
+    /// The about text of the subcommand which the keybind runs
+    about: String,
+}
+
+impl Keybind {
+    /// The shell command which runs plsdo with the arguments
+    fn command_line(&self) -> String {
+        std::iter::once("plsdo")
+            .chain(self.args.iter().map(String::as_str))
+            .map(shell_quote)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Quote the word for `sh`, unless it only consists of characters which are safe without
+fn shell_quote(word: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "_-+=%@:,./".contains(c);
+    if !word.is_empty() && word.chars().all(is_safe) {
+        word.to_owned()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
+
+/// Check that plsdo accepts the arguments, and find the about text of the subcommand they run
+fn check_args(command: &Command, args: &[String]) -> anyhow::Result<String> {
+    if args.is_empty() {
+        anyhow::bail!("No subcommand is given");
+    }
+
+    let command_line = std::iter::once("plsdo").chain(args.iter().map(String::as_str));
+    let mut matches = command
+        .clone()
+        .try_get_matches_from(command_line)
+        .map_err(|err| {
+            let message = err.to_string();
+            let first_line = message.lines().next().unwrap_or_default();
+            anyhow::anyhow!(first_line.trim_start_matches("error: ").to_owned())
+        })?;
+
+    let mut subcommand = command;
+    while let Some((name, sub_matches)) = matches.subcommand() {
+        subcommand = subcommand
+            .find_subcommand(name)
+            .expect("clap only matches existing subcommands");
+        matches = sub_matches.clone();
+    }
+    Ok(subcommand
+        .get_about()
+        .map(|about| about.to_string())
+        .unwrap_or_default())
+}
+
+/// Parse and check all the keybinds; the ones which are invalid are all named in the error
+fn parse_keymap(
+    command: &Command,
+    keymap: &BTreeMap<String, Vec<String>>,
+) -> anyhow::Result<Vec<Keybind>> {
+    let mut keybinds = Vec::new();
+    let mut errors = Vec::new();
+
+    for (chord, args) in keymap {
+        let keybind = Chord::parse(chord).and_then(|parsed| {
+            Ok(Keybind {
+                chord: parsed,
+                about: check_args(command, args)?,
+                args: args.clone(),
+            })
+        });
+        match keybind {
+            Ok(keybind) => keybinds.push(keybind),
+            Err(err) => errors.push(format!("{}: {:#}", chord, err)),
+        }
+    }
+
+    if !errors.is_empty() {
+        anyhow::bail!("Invalid keybinds:\n{}", errors.join("\n"));
+    }
+    Ok(keybinds)
+}
+
+fn hyprland_fragment(keybinds: &[Keybind]) -> String {
+    let mut fragment = format!("{}\n\n", HEADER);
+    for keybind in keybinds {
+        // A '#' would start a comment, unless it is doubled
+        let command_line = keybind.command_line().replace('#', "##");
+        fragment += &format!(
+            "bind = {}, exec, {}\n",
+            keybind.chord.hyprland(),
+            command_line
+        );
+    }
+    fragment
+}
+
+fn sxhkd_fragment(keybinds: &[Keybind]) -> String {
+    let mut fragment = format!("{}\n", HEADER);
+    for keybind in keybinds {
+        fragment += &format!(
+            "\n{}\n\t{}\n",
+            keybind.chord.sxhkd(),
+            keybind.command_line()
+        );
+    }
+    fragment
+}
+
+fn write_fragment(path: &str, contents: &str) -> anyhow::Result<()> {
+    if let Some(dir) = Path::new(path).parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, contents).with_context(|| format!("Failed to write {}", path))
+}
+
+#[derive(Serialize, Debug)]
+struct Generated<'a> {
+    keybinds: usize,
+    hyprland: &'a str,
+    sxhkd: &'a str,
+}
+
+fn generate() -> anyhow::Result<ScriptOutput> {
+    let keybinds = parse_keymap(&crate::get_command(), &config::get().keybinds)?;
+
+    write_fragment(
+        &SYSTEM_ATLAS.hyprland_keybinds,
+        &hyprland_fragment(&keybinds),
+    )?;
+    write_fragment(&SYSTEM_ATLAS.sxhkd_keybinds, &sxhkd_fragment(&keybinds))?;
+
+    let generated = Generated {
+        keybinds: keybinds.len(),
+        hyprland: &SYSTEM_ATLAS.hyprland_keybinds,
+        sxhkd: &SYSTEM_ATLAS.sxhkd_keybinds,
+    };
+    let text = format!(
+        "Wrote {} keybinds to {} and {}",
+        generated.keybinds, generated.hyprland, generated.sxhkd
+    );
+    Ok(ScriptOutput::new(&generated)?.with_text(text))
+}
+
+/// Show the keybinds as a cheat sheet, with what they do next to them
+fn list(sh: &Shell) -> anyhow::Result<()> {
+    let keybinds = parse_keymap(&crate::get_command(), &config::get().keybinds)?;
+
+    let width = keybinds
+        .iter()
+        .map(|keybind| keybind.chord.to_string().chars().count())
+        .max()
+        .unwrap_or_default();
+    let lines = keybinds
+        .iter()
+        .map(|keybind| {
+            format!(
+                "{:<width$}  {}  ({})",
+                keybind.chord.to_string(),
+                keybind.args.join(" "),
+                keybind.about
+            )
+        })
+        .collect::<Vec<_>>();
+
+    Dmenu::new(sh).choose_one_str(
+        "Keybinds",
+        &lines.iter().map(String::as_str).collect::<Vec<_>>(),
+    )?;
+    Ok(())
+}
+
+pub fn command_extension(cmd: Command) -> Command {
+    let inner_subcommands = [
+        Command::new("generate").about(
+            "Write the keybinds of the config as Hyprland and sxhkd config files, after checking them",
+        ),
+        Command::new("list").about("Show the keybinds of the config in a menu"),
+    ];
+
+    cmd.subcommand_required(true)
+        .arg_required_else_help(true)
+        .subcommands(inner_subcommands.iter())
+}
+
+pub fn run(sh: &Shell, args: &ArgMatches) -> anyhow::Result<Option<ScriptOutput>> {
+    match args.subcommand() {
+        Some(("generate", _)) => generate().map(Some),
+        Some(("list", _)) => list(sh).map(|_| None),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keymap(binds: &[(&str, &[&str])]) -> BTreeMap<String, Vec<String>> {
+        binds
+            .iter()
+            .map(|(chord, args)| {
+                (
+                    chord.to_string(),
+                    args.iter().map(|arg| arg.to_string()).collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fragments_are_generated_for_both_formats() {
+        let keybinds = parse_keymap(
+            &crate::get_command(),
+            &keymap(&[
+                (
+                    "super + shift + S",
+                    &["font_size", "--direction", "up", "2"],
+                ),
+                (
+                    "XF86AudioLowerVolume",
+                    &["audio", "volume", "set", "-r", "--", "-5"],
+                ),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(
+            hyprland_fragment(&keybinds)
+                .lines()
+                .skip(2)
+                .collect::<Vec<_>>(),
+            [
+                "bind = , XF86AudioLowerVolume, exec, plsdo audio volume set -r -- -5",
+                "bind = SUPER SHIFT, S, exec, plsdo font_size --direction up 2",
+            ]
+        );
+        assert_eq!(
+            sxhkd_fragment(&keybinds)
+                .lines()
+                .skip(2)
+                .collect::<Vec<_>>(),
+            [
+                "XF86AudioLowerVolume",
+                "\tplsdo audio volume set -r -- -5",
+                "",
+                "super + shift + s",
+                "\tplsdo font_size --direction up 2",
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_keybinds_are_all_named() {
+        let err = parse_keymap(
+            &crate::get_command(),
+            &keymap(&[
+                ("SUPER+A", &["audio", "volume", "louder"]),
+                ("HYPER+B", &["undo"]),
+                ("SUPER+C", &["undo"]),
+                ("SUPER+D", &[]),
+            ]),
+        )
+        .unwrap_err()
+        .to_string();
+
+        assert!(err.contains("SUPER+A: unrecognized subcommand 'louder'"));
+        assert!(err.contains("HYPER+B: Unknown modifier 'HYPER'"));
+        assert!(err.contains("SUPER+D: No subcommand is given"));
+        assert!(!err.contains("SUPER+C"));
+    }
+
+    #[test]
+    fn words_are_quoted_for_the_shell() {
+        assert_eq!(shell_quote("-5"), "-5");
+        assert_eq!(shell_quote("Fira Code"), "'Fira Code'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert_eq!(shell_quote(""), "''");
+    }
+}
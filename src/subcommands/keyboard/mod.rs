@@ -1,25 +1,32 @@
-use std::{
-    fmt::Display,
-    fs::{File, OpenOptions},
-    io::{BufRead, BufReader, LineWriter, Write},
-    path::PathBuf,
-};
+use std::{fmt::Display, path::PathBuf};
 
 use anyhow::{anyhow, Context};
 use clap::{arg, value_parser, ArgMatches, Command};
+use clap_complete::engine::CompletionCandidate;
 use hyprland::{ctl::switch_xkb_layout::SwitchXKBLayoutCmdTypes, data::Devices, shared::HyprData};
 
 mod xkb;
 
 use serde::{Deserialize, Serialize};
 use xkb::{get_xkb_layouts, XkbLayout};
-use xshell::{cmd, Shell};
+use xshell::Shell;
 
 use crate::{
     system_atlas::SYSTEM_ATLAS,
-    util::{determine_wm, dmenu::Dmenu, WM},
+    util::{
+        backing_file,
+        bus::{self, Topic},
+        completion, determine_wm,
+        dmenu::Dmenu,
+        notify::Notification,
+        runner::{CommandRunner, Invocation},
+        WM,
+    },
+    ScriptOutput,
 };
 
+use super::undo::{self, Snapshot};
+
 // TODO: do these really need to be Strings?
 #[derive(Debug, Clone)]
 struct AlternativeLayout {
@@ -31,6 +38,7 @@ struct AlternativeLayout {
 #[derive(Debug, Clone)]
 enum KeyboardLayout {
     Xkb(XkbLayout),
+    #[allow(dead_code)] // see `collect_all_layouts`
     Alternative(AlternativeLayout),
 }
 
@@ -49,7 +57,7 @@ impl KeyboardLayout {
     }
     fn dotfiles_path(&self) -> &str {
         match self {
-            Self::Xkb(_) => SYSTEM_ATLAS.main_dotfiles,
+            Self::Xkb(_) => SYSTEM_ATLAS.main_dotfiles.as_str(),
             Self::Alternative(layout) => layout.dotfiles_path.as_str(),
         }
     }
@@ -75,6 +83,15 @@ impl Display for KeyboardLayout {
     }
 }
 
+/// What `keyboard get` reports about the current layout.
+/// * `index`: the identifier which can be passed to `keyboard set`
+#[derive(Debug, Serialize)]
+struct LayoutSummary<'a> {
+    index: usize,
+    id: &'a str,
+    name: &'a str,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct PersistedData {
     layout_id: String,
@@ -82,28 +99,15 @@ struct PersistedData {
 
 impl PersistedData {
     fn read() -> anyhow::Result<Option<Self>> {
-        let file =
-            File::open(SYSTEM_ATLAS.keyboard_layout).context("The backing file does not exist")?;
-        let bufreader = BufReader::new(file);
-        let last_line = bufreader.lines().last();
-
-        match last_line {
-            Some(line) => {
-                let line = line.context("Failed to read lines from the backing file")?;
-                Ok(Some(serde_json::from_str::<Self>(&line)?))
-            }
+        match backing_file::read_last_line(&SYSTEM_ATLAS.keyboard_layout)? {
+            Some(line) => Ok(Some(serde_json::from_str::<Self>(&line)?)),
             None => Ok(None),
         }
     }
 
     fn write(&self) -> anyhow::Result<()> {
-        let file = OpenOptions::new()
-            .append(true)
-            .open(SYSTEM_ATLAS.keyboard_layout)?;
-        let mut writer = LineWriter::new(&file);
-        serde_json::to_writer(&mut writer, self)?;
-        writer.write_all(b"\n")?;
-        Ok(())
+        backing_file::append_json(&SYSTEM_ATLAS.keyboard_layout, self)?;
+        bus::publish(Topic::Keyboard, self)
     }
 }
 
@@ -121,7 +125,7 @@ fn get_current_layout(all_layouts: &[KeyboardLayout]) -> anyhow::Result<&Keyboar
             }),
         None => {
             // some heuristics to try figure out the keyboard layout in use
-            std::fs::read_link("/home/rg/.config/dotfiles_id")
+            std::fs::read_link(&SYSTEM_ATLAS.dotfiles_id)
                 .context("Example file does not exist, or is not a symlink")
                 .and_then(|path| {
                     path.iter()
@@ -158,8 +162,10 @@ fn set_hyprland_layout_by_id(id: u8) -> anyhow::Result<()> {
     Ok(())
 }
 
-fn collect_all_layouts(sh: &Shell) -> anyhow::Result<Vec<KeyboardLayout>> {
-    let xkb_layouts = get_xkb_layouts(sh)?;
+// The kyria layout is left out of the list for now, see below
+#[allow(unused_variables, unused_mut)]
+fn collect_all_layouts(runner: &dyn CommandRunner) -> anyhow::Result<Vec<KeyboardLayout>> {
+    let xkb_layouts = get_xkb_layouts(runner)?;
     let kyria_layout = AlternativeLayout {
         id: "ky".to_owned(),
         name: "kyria".to_owned(),
@@ -177,20 +183,35 @@ fn collect_all_layouts(sh: &Shell) -> anyhow::Result<Vec<KeyboardLayout>> {
     Ok(all_layouts)
 }
 
-fn switch_dotfiles(sh: &Shell, from_path: &str, to_path: &str) -> anyhow::Result<()> {
-    let dotter_local_path = "/home/rg/.dotfiles/.dotter/local.toml";
-
-    sh.change_dir(from_path);
-    cmd!(sh, "dotter undeploy -y -l {dotter_local_path}").run()?;
+fn layout_index_candidates(sh: &Shell) -> anyhow::Result<Vec<CompletionCandidate>> {
+    let layouts = collect_all_layouts(sh)?;
+    Ok(layouts
+        .iter()
+        .enumerate()
+        .map(|(index, layout)| {
+            CompletionCandidate::new(index.to_string()).help(Some(layout.to_string().into()))
+        })
+        .collect())
+}
 
-    sh.change_dir(to_path);
-    cmd!(sh, "dotter deploy -y -l {dotter_local_path}").run()?;
+fn switch_dotfiles(
+    runner: &dyn CommandRunner,
+    from_path: &str,
+    to_path: &str,
+) -> anyhow::Result<()> {
+    let dotter_local_path = format!("{}/.dotter/local.toml", SYSTEM_ATLAS.main_dotfiles);
+    let dotter = |action: &str, dir: &str| {
+        Invocation::new("dotter")
+            .args([action, "-y", "-l", &dotter_local_path])
+            .current_dir(dir)
+    };
 
-    Ok(())
+    runner.run(&dotter("undeploy", from_path))?;
+    runner.run(&dotter("deploy", to_path))
 }
 
 fn set_layout(
-    sh: &Shell,
+    runner: &dyn CommandRunner,
     current_layout: &KeyboardLayout,
     new_layout: &KeyboardLayout,
 ) -> anyhow::Result<()> {
@@ -198,7 +219,7 @@ fn set_layout(
         KeyboardLayout::Xkb(xkb_layout) => {
             if let KeyboardLayout::Alternative(_) = current_layout {
                 switch_dotfiles(
-                    sh,
+                    runner,
                     current_layout.dotfiles_path(),
                     new_layout.dotfiles_path(),
                 )?;
@@ -212,7 +233,7 @@ fn set_layout(
             }
 
             switch_dotfiles(
-                sh,
+                runner,
                 current_layout.dotfiles_path(),
                 new_layout.dotfiles_path(),
             )?;
@@ -221,11 +242,46 @@ fn set_layout(
         }
     }
 
+    Notification::new("Keyboard layout")
+        .body(new_layout.name())
+        .icon("input-keyboard")
+        .tag("keyboard-layout")
+        .send();
     Ok(())
 }
 
-fn run_hyprland(sh: &Shell, args: &ArgMatches) -> anyhow::Result<Option<String>> {
-    let layouts = collect_all_layouts(sh)?;
+/// Switch to the layout, recording the change for `plsdo undo`
+fn change_layout(
+    runner: &dyn CommandRunner,
+    layouts: &[KeyboardLayout],
+    new_layout: &KeyboardLayout,
+) -> anyhow::Result<()> {
+    undo::record(
+        || {
+            let current_layout = get_current_layout(layouts)?;
+            Ok(Snapshot::KeyboardLayout(current_layout.id().to_owned()))
+        },
+        || set_layout(runner, get_current_layout(layouts)?, new_layout),
+    )
+}
+
+/// Switch to the layout with the given id, e.g. "us"
+pub fn set_layout_by_id(runner: &dyn CommandRunner, id: &str) -> anyhow::Result<()> {
+    let layouts = collect_all_layouts(runner)?;
+    let current_layout = get_current_layout(&layouts)?;
+    let layout = layouts
+        .iter()
+        .find(|layout| layout.id() == id)
+        .ok_or_else(|| anyhow::anyhow!("There is no keyboard layout with the id '{}'", id))?;
+
+    set_layout(runner, current_layout, layout)
+}
+
+fn run_hyprland(
+    runner: &dyn CommandRunner,
+    args: &ArgMatches,
+) -> anyhow::Result<Option<ScriptOutput>> {
+    let layouts = collect_all_layouts(runner)?;
     let current_layout = get_current_layout(&layouts)?;
 
     match args.subcommand() {
@@ -241,14 +297,24 @@ fn run_hyprland(sh: &Shell, args: &ArgMatches) -> anyhow::Result<Option<String>>
             unimplemented!()
         }
         Some(("choose", _)) => {
-            let chosen_layout = Dmenu::new(sh).numbered().auto_select().choose_one(
+            let chosen_layout = Dmenu::new(runner).numbered().auto_select().choose_one(
                 "Choose keyboard layout",
                 &layouts,
                 |layout| layout.name(),
             )?;
-            set_layout(sh, current_layout, chosen_layout)?
+            change_layout(runner, &layouts, chosen_layout)?
+        }
+        Some(("get", _)) => {
+            let summary = LayoutSummary {
+                index: layouts
+                    .iter()
+                    .position(|l| l == current_layout)
+                    .expect("the current layout to be one of the layouts"),
+                id: current_layout.id(),
+                name: current_layout.name(),
+            };
+            return Ok(Some(ScriptOutput::new(&summary)?.with_text(current_layout)));
         }
-        Some(("get", _)) => println!("{}", current_layout),
         Some(("set", set_args)) => {
             let id = set_args
                 .get_one::<usize>("ID")
@@ -259,7 +325,7 @@ fn run_hyprland(sh: &Shell, args: &ArgMatches) -> anyhow::Result<Option<String>>
                     layouts.len()
                 )
             })?;
-            set_layout(sh, current_layout, layout)?;
+            change_layout(runner, &layouts, layout)?;
         }
         _ => {}
     };
@@ -275,11 +341,11 @@ fn initialize(current_layout: &KeyboardLayout) -> anyhow::Result<()> {
     }
 }
 
-fn run_x11(_sh: &Shell, _args: &ArgMatches) -> anyhow::Result<Option<String>> {
+fn run_x11(_sh: &Shell, _args: &ArgMatches) -> anyhow::Result<Option<ScriptOutput>> {
     unimplemented!()
 }
 
-pub fn run(sh: &Shell, args: &ArgMatches) -> anyhow::Result<Option<String>> {
+pub fn run(sh: &Shell, args: &ArgMatches) -> anyhow::Result<Option<ScriptOutput>> {
     let wm = determine_wm();
 
     match wm {
@@ -300,9 +366,42 @@ pub fn command_extension(cmd: Command) -> Command {
             .arg(
                 arg!([ID] "Identifier (index) of the keyboard layout")
                     .value_parser(value_parser!(usize))
-                    .required(true),
+                    .required(true)
+                    .add(completion::dynamic_values(layout_index_candidates)),
             ),
     ];
     cmd.subcommand_required(true)
         .subcommands(inner_subcommands.iter())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{config, util::runner::FakeRunner};
+
+    use super::*;
+
+    #[test]
+    fn dotfiles_are_switched_from_their_own_directories() {
+        config::init_for_tests();
+        let fake = FakeRunner::new();
+
+        switch_dotfiles(&fake, "/dotfiles/canary", "/dotfiles/main").unwrap();
+
+        let invocations = fake.invocations();
+        assert_eq!(
+            fake.command_lines(),
+            [
+                "dotter undeploy -y -l /dotfiles/main/.dotter/local.toml",
+                "dotter deploy -y -l /dotfiles/main/.dotter/local.toml"
+            ]
+        );
+        assert_eq!(
+            invocations[0].current_dir,
+            Some(PathBuf::from("/dotfiles/canary"))
+        );
+        assert_eq!(
+            invocations[1].current_dir,
+            Some(PathBuf::from("/dotfiles/main"))
+        );
+    }
+}
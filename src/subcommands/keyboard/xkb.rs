@@ -2,7 +2,8 @@ use std::fs::read_to_string;
 
 use anyhow::Context;
 use serde::{de::Visitor, Deserialize, Deserializer};
-use xshell::{cmd, Shell};
+
+use crate::util::runner::{CommandRunner, Invocation};
 
 #[derive(Debug, Clone)]
 pub struct XkbLayoutData {
@@ -52,8 +53,8 @@ where
     deserializer.deserialize_string(StringVisitor)
 }
 
-fn get_hypr_option(sh: &Shell, option: &str) -> anyhow::Result<HyprctlOption> {
-    let json_string = cmd!(sh, "hyprctl getoption {option} -j").read()?;
+fn get_hypr_option(runner: &dyn CommandRunner, option: &str) -> anyhow::Result<HyprctlOption> {
+    let json_string = runner.read(&Invocation::new("hyprctl").args(["getoption", option, "-j"]))?;
     serde_json::from_str::<HyprctlOption>(&json_string)
         .with_context(|| format!("Failed to read Hyprland option '{}'", option))
 }
@@ -74,9 +75,9 @@ fn read_xkb_variants_lines() -> anyhow::Result<Vec<String>> {
     Ok(variants)
 }
 
-pub fn get_xkb_layouts(sh: &Shell) -> anyhow::Result<Vec<XkbLayout>> {
+pub fn get_xkb_layouts(runner: &dyn CommandRunner) -> anyhow::Result<Vec<XkbLayout>> {
     let xkb_variants_lines = read_xkb_variants_lines()?;
-    let layout_data = get_hyprland_xkb_config(sh)?;
+    let layout_data = get_hyprland_xkb_config(runner)?;
 
     // NOTE: we could iterate only once, and check for all layout data on each line,
     // but that would be more complicated
@@ -105,10 +106,10 @@ pub fn get_xkb_layouts(sh: &Shell) -> anyhow::Result<Vec<XkbLayout>> {
         .collect()
 }
 
-pub fn get_hyprland_xkb_config(sh: &Shell) -> anyhow::Result<Vec<XkbLayoutData>> {
-    let layouts = get_hypr_option(sh, "input:kb_layout")?;
-    let variants = get_hypr_option(sh, "input:kb_variant")?;
-    let options = get_hypr_option(sh, "input:kb_options")?;
+pub fn get_hyprland_xkb_config(runner: &dyn CommandRunner) -> anyhow::Result<Vec<XkbLayoutData>> {
+    let layouts = get_hypr_option(runner, "input:kb_layout")?;
+    let variants = get_hypr_option(runner, "input:kb_variant")?;
+    let options = get_hypr_option(runner, "input:kb_options")?;
 
     if layouts.values.len() != variants.values.len() {
         anyhow::bail!(
@@ -130,3 +131,62 @@ pub fn get_hyprland_xkb_config(sh: &Shell) -> anyhow::Result<Vec<XkbLayoutData>>
         })
         .collect())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::util::runner::FakeRunner;
+
+    use super::*;
+
+    #[test]
+    fn layouts_are_paired_with_their_variants() {
+        let fake = FakeRunner::new()
+            .respond(
+                "hyprctl getoption input:kb_layout -j",
+                r#"{"option": "input:kb_layout", "str": "us,hu", "set": true}"#,
+                0,
+            )
+            .respond(
+                "hyprctl getoption input:kb_variant -j",
+                r#"{"option": "input:kb_variant", "str": "intl,", "set": true}"#,
+                0,
+            )
+            .respond(
+                "hyprctl getoption input:kb_options -j",
+                r#"{"option": "input:kb_options", "str": "caps:escape", "set": true}"#,
+                0,
+            );
+
+        let config = get_hyprland_xkb_config(&fake).unwrap();
+        assert_eq!(config.len(), 2);
+        assert_eq!(
+            (config[1].hyprland_id, config[1].layout.as_str()),
+            (1, "hu")
+        );
+        assert_eq!(config[0].variant, "intl");
+        assert_eq!(config[1].options, ["caps:escape"]);
+    }
+
+    #[test]
+    fn mismatched_variants_are_rejected() {
+        let fake = FakeRunner::new()
+            .respond(
+                "hyprctl getoption input:kb_layout -j",
+                r#"{"option": "input:kb_layout", "str": "us,hu"}"#,
+                0,
+            )
+            .respond(
+                "hyprctl getoption input:kb_variant -j",
+                r#"{"option": "input:kb_variant", "str": "intl"}"#,
+                0,
+            )
+            .respond(
+                "hyprctl getoption input:kb_options -j",
+                r#"{"option": "input:kb_options", "str": ""}"#,
+                0,
+            );
+
+        let err = get_hyprland_xkb_config(&fake).unwrap_err();
+        assert!(err.to_string().contains("don't match"));
+    }
+}
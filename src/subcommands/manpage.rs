@@ -0,0 +1,31 @@
+use std::path::PathBuf;
+
+use clap::{arg, value_parser, ArgMatches, Command};
+use clap_mangen::Man;
+use xshell::Shell;
+
+use crate::ScriptOutput;
+
+pub fn command_extension(cmd: Command) -> Command {
+    cmd.arg(
+        arg!(-o --"out-dir" <DIR> "Write a page for every subcommand into this directory, instead of printing the main page")
+            .value_parser(value_parser!(PathBuf)),
+    )
+}
+
+pub fn run(_: &Shell, args: &ArgMatches) -> anyhow::Result<Option<ScriptOutput>> {
+    let command = crate::get_command();
+
+    match args.get_one::<PathBuf>("out-dir") {
+        Some(out_dir) => {
+            std::fs::create_dir_all(out_dir)?;
+            clap_mangen::generate_to(command, out_dir)?;
+            Ok(None)
+        }
+        None => {
+            let mut page = Vec::new();
+            Man::new(command).render(&mut page)?;
+            Ok(Some(ScriptOutput::new(&String::from_utf8(page)?)?))
+        }
+    }
+}
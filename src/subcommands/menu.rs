@@ -0,0 +1,329 @@
+//! A command palette: every action of plsdo in one menu, e.g. "audio › output › choose", with
+//! the actions which were chosen often and recently at the top. The arguments which an action
+//! requires are asked for through further menus, after which the action runs like it would from
+//! the command line.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::Context;
+use clap::{Arg, ArgMatches, Command};
+use clap_complete::engine::ArgValueCandidates;
+use serde::{Deserialize, Serialize};
+use xshell::Shell;
+
+use crate::{
+    system_atlas::SYSTEM_ATLAS,
+    util::{
+        dmenu::{Dmenu, MenuAborted},
+        runner::CommandRunner,
+    },
+    ScriptOutput,
+};
+
+/// Top-level subcommands which are not offered, because they make no sense from a menu
+const NOT_ACTIONS: &[&str] = &["menu", "completions", "manpage", "subscribe", "help"];
+
+#[derive(Debug, Clone, PartialEq)]
+struct Action {
+    /// The subcommands which lead to the action, e.g. `["audio", "output", "choose"]`
+    path: Vec<String>,
+    about: String,
+}
+
+impl Action {
+    fn key(&self) -> String {
+        self.path.join(" ")
+    }
+
+    fn title(&self) -> String {
+        self.path.join(" › ")
+    }
+}
+
+/// Collect the subcommands without subcommands of their own, in the order of the help
+fn collect_actions(command: &Command, path: &mut Vec<String>, actions: &mut Vec<Action>) {
+    for subcommand in command.get_subcommands() {
+        let name = subcommand.get_name();
+        if subcommand.is_hide_set() || (path.is_empty() && NOT_ACTIONS.contains(&name)) {
+            continue;
+        }
+
+        path.push(name.to_owned());
+        if subcommand.has_subcommands() {
+            collect_actions(subcommand, path, actions);
+        } else {
+            actions.push(Action {
+                path: path.clone(),
+                about: subcommand
+                    .get_about()
+                    .map(|about| about.to_string())
+                    .unwrap_or_default(),
+            });
+        }
+        path.pop();
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy)]
+struct Usage {
+    count: u32,
+    /// Seconds since the epoch
+    last_used: u64,
+}
+
+impl Usage {
+    /// How likely the action is to be chosen again: often used actions score higher, and the
+    /// score fades as the last use gets older
+    fn score(&self, now: u64) -> f64 {
+        let age_days = now.saturating_sub(self.last_used) / 86400;
+        let recency = match age_days {
+            0 => 4.0,
+            1..=6 => 2.0,
+            7..=29 => 1.0,
+            _ => 0.5,
+        };
+        self.count as f64 * recency
+    }
+}
+
+/// The usage of the actions, by `Action::key`
+type Frecency = BTreeMap<String, Usage>;
+
+fn read_frecency(path: &Path) -> anyhow::Result<Frecency> {
+    match fs::read_to_string(path) {
+        Ok(contents) => serde_json::from_str(&contents)
+            .with_context(|| format!("Invalid menu frecency file {}", path.display())),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Frecency::default()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn record_use(path: &Path, action: &Action, now: u64) -> anyhow::Result<()> {
+    let mut frecency = read_frecency(path)?;
+    let usage = frecency.entry(action.key()).or_default();
+    usage.count += 1;
+    usage.last_used = now;
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_string(&frecency)?)?;
+    Ok(())
+}
+
+/// Put the actions with the highest score first. Actions with the same score (e.g. the ones
+/// which were never used) keep their order.
+fn sort_by_frecency(actions: &mut [Action], frecency: &Frecency, now: u64) {
+    let score = |action: &Action| {
+        frecency
+            .get(&action.key())
+            .map_or(0.0, |usage| usage.score(now))
+    };
+    actions.sort_by(|a, b| score(b).total_cmp(&score(a)));
+}
+
+/// The menu lines of the actions: the title, and the about text in a column of its own
+fn format_actions(actions: &[Action]) -> Vec<String> {
+    let width = actions
+        .iter()
+        .map(|action| action.title().chars().count())
+        .max()
+        .unwrap_or_default();
+
+    actions
+        .iter()
+        .map(|action| format!("{:<width$}  {}", action.title(), action.about))
+        .collect()
+}
+
+/// Ask for the value of an argument: from its possible values or completion candidates, if it
+/// has any, and as free text otherwise
+fn prompt_value(runner: &dyn CommandRunner, arg: &Arg, prompt: &str) -> anyhow::Result<String> {
+    let possible_values = arg
+        .get_possible_values()
+        .iter()
+        .filter(|value| !value.is_hide_set())
+        .map(|value| value.get_name().to_owned())
+        .collect::<Vec<_>>();
+    let candidates = match arg.get::<ArgValueCandidates>() {
+        Some(candidates) => candidates
+            .candidates()
+            .into_iter()
+            .filter(|candidate| !candidate.is_hide_set())
+            .map(|candidate| {
+                let value = candidate.get_value().to_string_lossy().into_owned();
+                match candidate.get_help() {
+                    Some(help) => (value.clone(), format!("{}  ({})", value, help)),
+                    None => (value.clone(), value),
+                }
+            })
+            .collect(),
+        None => possible_values
+            .into_iter()
+            .map(|value| (value.clone(), value))
+            .collect::<Vec<_>>(),
+    };
+
+    if !candidates.is_empty() {
+        let (value, _) =
+            Dmenu::new(runner).choose_one(prompt, &candidates, |(_, line)| line.as_str())?;
+        return Ok(value.clone());
+    }
+
+    let value = Dmenu::new(runner)
+        .allow_invalid()
+        .choose_one_str(prompt, &[])?;
+    if value.is_empty() {
+        return Err(MenuAborted.into());
+    }
+    Ok(value)
+}
+
+/// Ask for the arguments which the action requires, as they would be given on the command line
+fn prompt_args(
+    runner: &dyn CommandRunner,
+    action: &Action,
+    command: &Command,
+) -> anyhow::Result<Vec<String>> {
+    let mut options = Vec::new();
+    let mut positionals = Vec::new();
+
+    for arg in command.get_arguments().filter(|arg| arg.is_required_set()) {
+        let name = arg
+            .get_value_names()
+            .and_then(|names| names.first())
+            .map_or_else(|| arg.get_id().to_string(), |name| name.to_string());
+        let prompt = match arg.get_help() {
+            Some(help) => format!("{}: {} ({})", action.title(), name, help),
+            None => format!("{}: {}", action.title(), name),
+        };
+        let value = prompt_value(runner, arg, &prompt)?;
+
+        match (arg.get_long(), arg.get_short()) {
+            (Some(long), _) => options.extend([format!("--{}", long), value]),
+            (None, Some(short)) => options.extend([format!("-{}", short), value]),
+            (None, None) => positionals.push(value),
+        }
+    }
+
+    // Values such as a negative volume change must not be taken for options
+    if !positionals.is_empty() {
+        options.push("--".to_owned());
+    }
+    options.extend(positionals);
+    Ok(options)
+}
+
+fn find_subcommand<'c>(command: &'c Command, path: &[String]) -> Option<&'c Command> {
+    path.iter()
+        .try_fold(command, |command, name| command.find_subcommand(name))
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs())
+}
+
+pub fn command_extension(cmd: Command) -> Command {
+    cmd
+}
+
+pub fn run(sh: &Shell, _: &ArgMatches) -> anyhow::Result<Option<ScriptOutput>> {
+    let command = crate::get_command();
+    let mut actions = Vec::new();
+    collect_actions(&command, &mut Vec::new(), &mut actions);
+
+    let frecency_path = Path::new(&SYSTEM_ATLAS.menu_frecency);
+    let now = now();
+    match read_frecency(frecency_path) {
+        Ok(frecency) => sort_by_frecency(&mut actions, &frecency, now),
+        Err(err) => log::warn!(error = format!("{err:#}"); "Failed to read the menu frecency"),
+    }
+
+    let lines = format_actions(&actions);
+    let chosen = Dmenu::new(sh)
+        .choose_one_str("Run", &lines.iter().map(String::as_str).collect::<Vec<_>>())?;
+    let action = lines
+        .iter()
+        .position(|line| *line == chosen)
+        .map(|i| &actions[i])
+        .expect("the menu only returns one of the choices");
+
+    let subcommand =
+        find_subcommand(&command, &action.path).expect("actions are collected from the command");
+    let args = prompt_args(sh, action, subcommand)?;
+
+    if let Err(err) = record_use(frecency_path, action, now) {
+        log::warn!(error = format!("{err:#}"); "Failed to record the use of a menu action");
+    }
+
+    let command_line = std::iter::once("plsdo".to_owned())
+        .chain(action.path.iter().cloned())
+        .chain(args)
+        .collect::<Vec<_>>();
+    log::debug!(args = command_line.join(" "); "Running menu action");
+    let matches = command.try_get_matches_from(&command_line)?;
+
+    crate::run_subcommand(sh, &command_line, &matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn titles(actions: &[Action]) -> Vec<String> {
+        actions.iter().map(Action::title).collect()
+    }
+
+    #[test]
+    fn only_visible_leaf_commands_are_actions() {
+        let mut actions = Vec::new();
+        collect_actions(&crate::get_command(), &mut Vec::new(), &mut actions);
+        let titles = titles(&actions);
+
+        assert!(titles.contains(&"audio › output › choose".to_owned()));
+        assert!(titles.contains(&"font_size".to_owned()));
+        assert!(!titles.contains(&"audio".to_owned()));
+        assert!(!titles.iter().any(|title| title.contains("run_listener")));
+        assert!(!titles.iter().any(|title| title.starts_with("completions")));
+    }
+
+    #[test]
+    fn frequent_and_recent_actions_come_first() {
+        let action = |name: &str| Action {
+            path: vec![name.to_owned()],
+            about: String::new(),
+        };
+        let mut actions = vec![action("a"), action("b"), action("c"), action("d")];
+        let now = 100 * 86400;
+        let frecency = Frecency::from([
+            // Used a lot, but a while ago
+            (
+                "b".to_owned(),
+                Usage {
+                    count: 6,
+                    last_used: now - 40 * 86400,
+                },
+            ),
+            (
+                "c".to_owned(),
+                Usage {
+                    count: 2,
+                    last_used: now - 60,
+                },
+            ),
+            (
+                "d".to_owned(),
+                Usage {
+                    count: 1,
+                    last_used: now - 3 * 86400,
+                },
+            ),
+        ]);
+
+        sort_by_frecency(&mut actions, &frecency, now);
+
+        assert_eq!(titles(&actions), ["c", "b", "d", "a"]);
+    }
+}
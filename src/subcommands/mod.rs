@@ -1,13 +1,31 @@
 pub mod audio;
+#[cfg(feature = "dbus")]
 pub mod brightness;
+#[cfg(feature = "dbus")]
 pub mod colortemp;
+pub mod completions;
+pub mod config_history;
+pub mod daemon;
 pub mod font_family;
 pub mod font_size;
 pub mod game;
+pub mod keybinds;
+#[cfg(feature = "hyprland")]
 pub mod keyboard;
+pub mod manpage;
+pub mod menu;
 pub mod playerctl;
+#[cfg(feature = "dbus")]
 pub mod power;
+pub mod redo;
+pub mod scene;
+#[cfg(feature = "hyprland")]
 pub mod screenshot;
+pub mod serve;
+pub mod subscribe;
+#[cfg(feature = "transmission")]
 pub mod torrent;
+pub mod undo;
+#[cfg(feature = "hyprland")]
 pub mod workspace;
 pub mod ytdl;
@@ -5,9 +5,15 @@ use std::{
 };
 
 use clap::{ArgMatches, Command, FromArgMatches, Parser, Subcommand};
-use xshell::{cmd, Shell};
-
-use crate::util::dmenu::Dmenu;
+use xshell::Shell;
+
+use crate::{
+    util::{
+        dmenu::Dmenu,
+        runner::{CommandRunner, Invocation},
+    },
+    ScriptOutput,
+};
 
 // TODO:
 // - show-status:
@@ -86,30 +92,32 @@ fn get_selected_player_from_file() -> anyhow::Result<String> {
     Ok(contents)
 }
 
-fn invoke_player_command(sh: &Shell, player: &str, command: PlayerCommand) -> anyhow::Result<()> {
+fn invoke_player_command(
+    runner: &dyn CommandRunner,
+    player: &str,
+    command: PlayerCommand,
+) -> anyhow::Result<()> {
     let playerctl_subcommand = get_playerctl_subcommand(&command)?;
 
-    let mut cmd = cmd!(sh, "playerctl")
+    let mut invocation = Invocation::new("playerctl")
         .arg(playerctl_subcommand)
         .arg("-p")
         .arg(player);
 
     if let PlayerCommand::Skip { delta } = command {
-        cmd = cmd.arg(delta.to_string());
+        invocation = invocation.arg(delta.to_string());
     }
 
-    cmd.run()?;
-
-    Ok(())
+    runner.run(&invocation)
 }
 
-fn show_status(player: &str) {
+fn show_status(player: &str) -> &'static str {
     if player == "spotify" {
-        println!("spotify playing some shit");
+        "spotify playing some shit"
     } else if player == "firefox" {
-        println!("firefox playing some shit");
+        "firefox playing some shit"
     } else {
-        println!("mpd or something else playing some shit");
+        "mpd or something else playing some shit"
     }
 }
 
@@ -117,14 +125,11 @@ pub fn command_extension(cmd: Command) -> Command {
     PlayerCommand::augment_subcommands(cmd)
 }
 
-pub fn run(sh: &Shell, args: &ArgMatches) -> anyhow::Result<Option<String>> {
-    // TODO: unwrap
-    let subcmd = PlayerCommand::from_arg_matches(args)
-        .map_err(|err| err.exit())
-        .unwrap();
+pub fn run(sh: &Shell, args: &ArgMatches) -> anyhow::Result<Option<ScriptOutput>> {
+    let subcmd = PlayerCommand::from_arg_matches(args)?;
 
     if let PlayerCommand::SelectPlayer { player } = subcmd {
-        let players = cmd!(sh, "playerctl -l").read()?;
+        let players = sh.read(&Invocation::new("playerctl").arg("-l"))?;
         let players: Vec<_> = players
             .split('\n')
             .map(|player| {
@@ -139,19 +144,40 @@ pub fn run(sh: &Shell, args: &ArgMatches) -> anyhow::Result<Option<String>> {
         let selected_player = if let Some(player) = player {
             player
         } else {
-            // unwrap: we don't want to continue if the resulting string is empty
-            Dmenu::new(sh)
-                .choose_one_str("Choose media player to control", &players)
-                .unwrap()
+            Dmenu::new(sh).choose_one_str("Choose media player to control", &players)?
         };
 
         write_selected_player_to_file(&selected_player)?;
     } else if PlayerCommand::ShowStatus == subcmd {
         let player = get_selected_player_from_file()?;
-        show_status(&player);
+        return Ok(Some(ScriptOutput::new(&show_status(&player))?));
     } else {
         let player = get_selected_player_from_file()?;
         invoke_player_command(sh, &player, subcmd)?;
     }
     Ok(None)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::util::runner::FakeRunner;
+
+    use super::*;
+
+    #[test]
+    fn commands_are_sent_to_the_player() {
+        let fake = FakeRunner::new();
+
+        invoke_player_command(&fake, "spotify", PlayerCommand::Toggle).unwrap();
+        invoke_player_command(&fake, "mpd", PlayerCommand::Skip { delta: -10 }).unwrap();
+
+        assert_eq!(
+            fake.command_lines(),
+            [
+                "playerctl play-pause -p spotify",
+                "playerctl position -p mpd -10"
+            ]
+        );
+        assert!(invoke_player_command(&fake, "mpd", PlayerCommand::ShowStatus).is_err());
+    }
+}
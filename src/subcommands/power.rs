@@ -1,31 +1,156 @@
+//! Power actions through logind (`org.freedesktop.login1.Manager` on the system bus), which
+//! respects inhibitors, asks polkit when the user needs to authenticate, and reports why an
+//! action was refused.
+
+use std::time::Duration;
+
+use anyhow::Context;
 use clap::{ArgMatches, Command};
-use std::str::FromStr;
-use std::string::ToString;
+use dbus::blocking::{Connection, Proxy};
 use strum::IntoEnumIterator;
-use strum_macros::{Display, EnumIter, EnumString};
-use xshell::{cmd, Shell};
+use strum_macros::{Display, EnumIter};
+use xshell::Shell;
+
+use crate::{util::dmenu::Dmenu, ScriptOutput};
 
-use crate::util::dmenu::Dmenu;
+const DESTINATION: &str = "org.freedesktop.login1";
+const PATH: &str = "/org/freedesktop/login1";
+const INTERFACE: &str = "org.freedesktop.login1.Manager";
+/// Long enough for the user to type their password into a polkit prompt
+const TIMEOUT: Duration = Duration::from_secs(60);
+/// The session of the caller, or the graphical session of its user when the caller is not part
+/// of one (e.g. when it's started by a systemd user service)
+const SESSION: &str = "auto";
 
-#[derive(Debug, Display, EnumString, EnumIter)]
-#[strum(serialize_all = "lowercase")]
+#[derive(Debug, Display, EnumIter, Clone, Copy, PartialEq, Eq)]
+#[strum(serialize_all = "kebab-case")]
 enum PowerMenuOption {
     Shutdown,
-    Suspend,
     Reboot,
+    Suspend,
+    Hibernate,
+    HybridSleep,
+    Lock,
+    Logout,
 }
 
 impl PowerMenuOption {
-    fn execute(&self, sh: &Shell) -> anyhow::Result<()> {
-        let cmd = match *self {
-            PowerMenuOption::Shutdown => cmd!(sh, "shutdown now"),
-            PowerMenuOption::Suspend => cmd!(sh, "systemctl suspend"),
-            PowerMenuOption::Reboot => cmd!(sh, "reboot"),
+    /// The method of the manager which tells whether the action is possible, for the actions
+    /// which depend on the machine or on the permissions of the user
+    fn can_method(self) -> Option<&'static str> {
+        match self {
+            Self::Shutdown => Some("CanPowerOff"),
+            Self::Reboot => Some("CanReboot"),
+            Self::Suspend => Some("CanSuspend"),
+            Self::Hibernate => Some("CanHibernate"),
+            Self::HybridSleep => Some("CanHybridSleep"),
+            Self::Lock | Self::Logout => None,
+        }
+    }
+
+    /// What an inhibitor must inhibit to hold up the action
+    fn inhibited_by(self) -> Option<&'static str> {
+        match self {
+            Self::Shutdown | Self::Reboot => Some("shutdown"),
+            Self::Suspend | Self::Hibernate | Self::HybridSleep => Some("sleep"),
+            Self::Lock | Self::Logout => None,
+        }
+    }
+}
+
+/// A lock which a program took to delay or block an action, see `systemd-inhibit(1)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Inhibitor {
+    /// The inhibited actions, separated by ':', e.g. "sleep:shutdown"
+    what: String,
+    who: String,
+    why: String,
+    /// "block" or "delay"
+    mode: String,
+}
+
+impl Inhibitor {
+    fn inhibits(&self, option: PowerMenuOption) -> bool {
+        option
+            .inhibited_by()
+            .is_some_and(|action| self.what.split(':').any(|what| what == action))
+    }
+}
+
+struct Logind {
+    connection: Connection,
+}
+
+impl Logind {
+    fn connect() -> anyhow::Result<Self> {
+        let connection = Connection::new_system().context("Failed to connect to the system bus")?;
+        Ok(Self { connection })
+    }
+
+    fn proxy(&self) -> Proxy<'_, &Connection> {
+        self.connection.with_proxy(DESTINATION, PATH, TIMEOUT)
+    }
+
+    /// Whether the action can be offered: logind answers "yes", "challenge" (after authenticating),
+    /// "no" (not permitted) or "na" (not supported, e.g. hibernating without swap)
+    fn can(&self, option: PowerMenuOption) -> anyhow::Result<bool> {
+        let Some(method) = option.can_method() else {
+            return Ok(true);
         };
+        let (answer,): (String,) = self
+            .proxy()
+            .method_call(INTERFACE, method, ())
+            .with_context(|| format!("Failed to call {}", method))?;
+        Ok(matches!(answer.as_str(), "yes" | "challenge"))
+    }
+
+    fn inhibitors(&self) -> anyhow::Result<Vec<Inhibitor>> {
+        #[allow(clippy::type_complexity)]
+        let (inhibitors,): (Vec<(String, String, String, String, u32, u32)>,) = self
+            .proxy()
+            .method_call(INTERFACE, "ListInhibitors", ())
+            .context("Failed to list the inhibitors")?;
+
+        Ok(inhibitors
+            .into_iter()
+            .map(|(what, who, why, mode, _uid, _pid)| Inhibitor {
+                what,
+                who,
+                why,
+                mode,
+            })
+            .collect())
+    }
+
+    fn execute(&self, option: PowerMenuOption) -> anyhow::Result<()> {
+        let proxy = self.proxy();
+        // Interactive, so that polkit may ask for a password instead of refusing outright
+        let result: Result<(), _> = match option {
+            PowerMenuOption::Shutdown => proxy.method_call(INTERFACE, "PowerOff", (true,)),
+            PowerMenuOption::Reboot => proxy.method_call(INTERFACE, "Reboot", (true,)),
+            PowerMenuOption::Suspend => proxy.method_call(INTERFACE, "Suspend", (true,)),
+            PowerMenuOption::Hibernate => proxy.method_call(INTERFACE, "Hibernate", (true,)),
+            PowerMenuOption::HybridSleep => proxy.method_call(INTERFACE, "HybridSleep", (true,)),
+            PowerMenuOption::Lock => proxy.method_call(INTERFACE, "LockSession", (SESSION,)),
+            PowerMenuOption::Logout => proxy.method_call(INTERFACE, "TerminateSession", (SESSION,)),
+        };
+
+        result.with_context(|| format!("Failed to {}", option))
+    }
+}
 
-        cmd.run()?;
+/// The menu line of the action, naming the inhibitors which hold it up
+fn menu_line(option: PowerMenuOption, inhibitors: &[Inhibitor]) -> String {
+    let holding_up = inhibitors
+        .iter()
+        .filter(|inhibitor| inhibitor.inhibits(option))
+        .map(|inhibitor| format!("{}: {} ({})", inhibitor.who, inhibitor.why, inhibitor.mode))
+        .collect::<Vec<_>>();
 
-        Ok(())
+    if holding_up.is_empty() {
+        option.to_string()
+    } else {
+        format!("{}  [inhibited by {}]", option, holding_up.join(", "))
     }
 }
 
@@ -33,14 +158,56 @@ pub fn command_extension(cmd: Command) -> Command {
     cmd
 }
 
-pub fn run(sh: &Shell, _: &ArgMatches) -> anyhow::Result<Option<String>> {
-    let opts: Vec<_> = PowerMenuOption::iter().map(|opt| opt.to_string()).collect();
+pub fn run(sh: &Shell, _: &ArgMatches) -> anyhow::Result<Option<ScriptOutput>> {
+    let logind = Logind::connect()?;
+    let inhibitors = logind.inhibitors()?;
 
-    let result = Dmenu::new(sh)
-        .numbered()
-        .choose_one("Choose operation", &opts, String::as_ref)?;
+    let mut choices = Vec::new();
+    for option in PowerMenuOption::iter() {
+        if logind.can(option)? {
+            choices.push((option, menu_line(option, &inhibitors)));
+        }
+    }
 
-    PowerMenuOption::from_str(result)?.execute(sh)?;
+    let (option, _) =
+        Dmenu::new(sh)
+            .numbered()
+            .choose_one("Choose operation", &choices, |(_, line)| line.as_str())?;
+
+    logind.execute(*option)?;
 
     Ok(None)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_matching_inhibitors_are_named() {
+        let inhibitors = [
+            Inhibitor {
+                what: "sleep:idle".to_owned(),
+                who: "Firefox".to_owned(),
+                why: "Playing video".to_owned(),
+                mode: "block".to_owned(),
+            },
+            Inhibitor {
+                what: "shutdown".to_owned(),
+                who: "apt".to_owned(),
+                why: "Upgrading".to_owned(),
+                mode: "delay".to_owned(),
+            },
+        ];
+
+        assert_eq!(
+            menu_line(PowerMenuOption::HybridSleep, &inhibitors),
+            "hybrid-sleep  [inhibited by Firefox: Playing video (block)]"
+        );
+        assert_eq!(
+            menu_line(PowerMenuOption::Reboot, &inhibitors),
+            "reboot  [inhibited by apt: Upgrading (delay)]"
+        );
+        assert_eq!(menu_line(PowerMenuOption::Lock, &inhibitors), "lock");
+    }
+}
@@ -0,0 +1,16 @@
+//! Redo the last change which was undone; see `undo`
+
+use clap::{ArgMatches, Command};
+use xshell::Shell;
+
+use crate::ScriptOutput;
+
+use super::undo::{self, Direction};
+
+pub fn command_extension(cmd: Command) -> Command {
+    cmd
+}
+
+pub fn run(sh: &Shell, _: &ArgMatches) -> anyhow::Result<Option<ScriptOutput>> {
+    undo::step(sh, Direction::Redo).map(Some)
+}
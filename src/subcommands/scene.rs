@@ -0,0 +1,437 @@
+//! Scenes are named bundles of settings which are applied together, e.g. a "movie" scene which
+//! switches the audio output to the TV and turns the brightness up. They are stored in the
+//! `scenes` section of the config:
+//!
+//! ```toml
+//! [scenes.movie]
+//! audio_output = "tv"
+//! brightness = 100
+//! colortemp = 6500
+//! ```
+//!
+//! Every setting is optional, and is applied through the same code as the subcommand which
+//! changes it on its own. A setting which fails to apply doesn't stop the others.
+
+use std::{fmt::Display, fs, path::Path};
+
+use anyhow::Context;
+use clap::{arg, value_parser, ArgMatches, Command, ValueEnum};
+use clap_complete::engine::CompletionCandidate;
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+use strum_macros::{Display, EnumIter};
+use xshell::Shell;
+
+use crate::{
+    config,
+    util::{
+        completion,
+        config_edit::{self, Value},
+        notify::Notification,
+        runner::CommandRunner,
+    },
+    ScriptOutput,
+};
+
+use super::{
+    audio::state::{self as audio, AudioOutputFriendlyName},
+    font_family, font_size,
+};
+#[cfg(feature = "dbus")]
+use super::{brightness, colortemp};
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Scene {
+    /// Matched against the audio outputs like `audio output set`, e.g. "tv" or "headphones"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audio_output: Option<String>,
+    /// In percent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub brightness: Option<u32>,
+    /// In Kelvin
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub colortemp: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font_size: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font_family: Option<String>,
+}
+
+/// A setting of a scene. Settings are applied in the order in which they are declared.
+#[derive(ValueEnum, Serialize, Display, EnumIter, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+enum Setting {
+    AudioOutput,
+    Brightness,
+    Colortemp,
+    FontSize,
+    FontFamily,
+}
+
+#[derive(Serialize, Debug)]
+struct Failure {
+    setting: Setting,
+    error: String,
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.setting, self.error)
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct Report {
+    scene: String,
+    /// The settings which were applied or saved
+    done: Vec<Setting>,
+    failed: Vec<Failure>,
+}
+
+impl Report {
+    fn new(scene: &str) -> Self {
+        Self {
+            scene: scene.to_owned(),
+            done: Vec::new(),
+            failed: Vec::new(),
+        }
+    }
+
+    fn record(&mut self, setting: Setting, result: anyhow::Result<()>) {
+        match result {
+            Ok(()) => self.done.push(setting),
+            Err(err) => self.failed.push(Failure {
+                setting,
+                error: format!("{:#}", err),
+            }),
+        }
+    }
+
+    /// The report as output of the command, or an error which lists the failed settings
+    fn into_output(self, verb: &str) -> anyhow::Result<ScriptOutput> {
+        if !self.failed.is_empty() {
+            let failures = self
+                .failed
+                .iter()
+                .map(|failure| format!("  {}", failure))
+                .collect::<Vec<_>>()
+                .join("\n");
+            anyhow::bail!(
+                "{} of {} settings of scene '{}' could not be {}:\n{}",
+                self.failed.len(),
+                self.failed.len() + self.done.len(),
+                self.scene,
+                verb,
+                failures
+            );
+        }
+
+        let settings = self
+            .done
+            .iter()
+            .map(|setting| setting.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let text = format!("Scene '{}' was {}: {}", self.scene, verb, settings);
+        Ok(ScriptOutput::new(&self)?.with_text(text))
+    }
+}
+
+fn set_audio_output(runner: &dyn CommandRunner, needle: &str) -> anyhow::Result<()> {
+    let outputs = audio::get_all_audio_outputs(runner)?;
+    let output = audio::find_matching_output(&outputs, needle)?;
+    audio::set_audio_output(runner, output)?;
+
+    audio::write_to_backing_file(audio::get_current_audio_state(runner)?)
+}
+
+/// The needle which finds the current audio output again: its friendly name if it has one
+fn get_audio_output(runner: &dyn CommandRunner) -> anyhow::Result<String> {
+    let output = audio::get_current_audio_output(runner)?;
+
+    Ok(match output.friendly_name {
+        AudioOutputFriendlyName::Unrecognized => output.name,
+        friendly_name => friendly_name
+            .to_possible_value()
+            .expect("no variant is skipped")
+            .get_name()
+            .to_owned(),
+    })
+}
+
+/// Apply the setting, or return `None` if the scene leaves it alone
+fn apply_setting(
+    runner: &dyn CommandRunner,
+    scene: &Scene,
+    setting: Setting,
+) -> Option<anyhow::Result<()>> {
+    Some(match setting {
+        Setting::AudioOutput => set_audio_output(runner, scene.audio_output.as_ref()?),
+        #[cfg(feature = "dbus")]
+        Setting::Brightness => brightness::set_brightness(scene.brightness?),
+        #[cfg(feature = "dbus")]
+        Setting::Colortemp => colortemp::set_colortemp(scene.colortemp?),
+        #[cfg(not(feature = "dbus"))]
+        Setting::Brightness => {
+            scene.brightness?;
+            Err(anyhow::anyhow!(
+                "plsdo was built without the `dbus` feature"
+            ))
+        }
+        #[cfg(not(feature = "dbus"))]
+        Setting::Colortemp => {
+            scene.colortemp?;
+            Err(anyhow::anyhow!(
+                "plsdo was built without the `dbus` feature"
+            ))
+        }
+        Setting::FontSize => font_size::set_font_size(scene.font_size?),
+        Setting::FontFamily => font_family::set_font_family(scene.font_family.as_ref()?),
+    })
+}
+
+/// Read the current value of the setting into the scene
+fn capture_setting(
+    runner: &dyn CommandRunner,
+    scene: &mut Scene,
+    setting: Setting,
+) -> anyhow::Result<()> {
+    match setting {
+        Setting::AudioOutput => scene.audio_output = Some(get_audio_output(runner)?),
+        #[cfg(feature = "dbus")]
+        Setting::Brightness => scene.brightness = Some(brightness::get_brightness()?),
+        #[cfg(feature = "dbus")]
+        Setting::Colortemp => scene.colortemp = Some(colortemp::get_colortemp()?),
+        #[cfg(not(feature = "dbus"))]
+        Setting::Brightness | Setting::Colortemp => {
+            anyhow::bail!("plsdo was built without the `dbus` feature")
+        }
+        Setting::FontSize => {
+            scene.font_size = Some(
+                font_size::get_font_size()?
+                    .ok_or(anyhow::anyhow!("The alacritty config sets no font size"))?,
+            )
+        }
+        Setting::FontFamily => {
+            scene.font_family = Some(
+                font_family::get_font_family()?
+                    .ok_or(anyhow::anyhow!("The alacritty config sets no font family"))?,
+            )
+        }
+    }
+    Ok(())
+}
+
+fn get_scene(name: &str) -> anyhow::Result<&'static Scene> {
+    config::get().scenes.get(name).ok_or_else(|| {
+        anyhow::anyhow!(
+            "There is no scene called '{}'; see `plsdo scene list`",
+            name
+        )
+    })
+}
+
+fn apply(runner: &dyn CommandRunner, name: &str) -> anyhow::Result<ScriptOutput> {
+    let scene = get_scene(name)?;
+
+    let mut report = Report::new(name);
+    for setting in Setting::iter() {
+        if let Some(result) = apply_setting(runner, scene, setting) {
+            report.record(setting, result);
+        }
+    }
+
+    // Failures are reported like any other error
+    if report.failed.is_empty() {
+        Notification::new("Scene")
+            .body(name)
+            .icon("preferences-desktop-display")
+            .tag("scene")
+            .send();
+    }
+    report.into_output("applied")
+}
+
+/// The scene as key paths and values of the config, e.g. `scenes.movie.brightness`
+fn scene_values(name: &str, scene: &Scene) -> Vec<(String, Value)> {
+    let key = |setting: &str| format!("scenes.{}.{}", name, setting);
+    let mut values = Vec::new();
+
+    if let Some(audio_output) = &scene.audio_output {
+        values.push((key("audio_output"), Value::String(audio_output.clone())));
+    }
+    if let Some(brightness) = scene.brightness {
+        values.push((key("brightness"), Value::Integer(brightness.into())));
+    }
+    if let Some(colortemp) = scene.colortemp {
+        values.push((key("colortemp"), Value::Integer(colortemp.into())));
+    }
+    if let Some(font_size) = scene.font_size {
+        values.push((key("font_size"), Value::Float(font_size)));
+    }
+    if let Some(font_family) = &scene.font_family {
+        values.push((key("font_family"), Value::String(font_family.clone())));
+    }
+
+    values
+}
+
+/// Save the current values of the settings into the scene. Settings which are not captured keep
+/// the value they had in the scene, if any.
+fn save(
+    runner: &dyn CommandRunner,
+    name: &str,
+    settings: &[Setting],
+) -> anyhow::Result<ScriptOutput> {
+    if name.is_empty() || name.contains('.') {
+        anyhow::bail!("Invalid scene name '{}'", name);
+    }
+
+    let mut scene = Scene::default();
+    let mut report = Report::new(name);
+    for &setting in settings {
+        let result = capture_setting(runner, &mut scene, setting);
+        report.record(setting, result);
+    }
+
+    // The config file is optional, but it has to exist to be edited
+    let path = config::config_path()?;
+    if !path.exists() {
+        fs::create_dir_all(path.parent().unwrap_or(Path::new("/")))?;
+        fs::write(&path, "")?;
+    }
+    let path = path
+        .to_str()
+        .ok_or(anyhow::anyhow!("The config path is not valid UTF-8"))?;
+    config_edit::edit(path, |config| {
+        scene_values(name, &scene)
+            .into_iter()
+            .try_for_each(|(key, value)| config.set(&key, value))
+    })
+    .context("Failed to save the scene")?;
+
+    report.into_output("saved")
+}
+
+fn list() -> anyhow::Result<ScriptOutput> {
+    let scenes = &config::get().scenes;
+
+    let text = scenes
+        .iter()
+        .map(|(name, scene)| {
+            let settings = scene_values(name, scene)
+                .into_iter()
+                .map(|(key, value)| format!("{}={}", key.rsplit('.').next().unwrap_or(&key), value))
+                .collect::<Vec<_>>()
+                .join(" ");
+            format!("{:<16} {}", name, settings)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Ok(ScriptOutput::new(scenes)?.with_text(text))
+}
+
+fn scene_candidates(_: &Shell) -> anyhow::Result<Vec<CompletionCandidate>> {
+    Ok(config::get()
+        .scenes
+        .keys()
+        .map(CompletionCandidate::new)
+        .collect())
+}
+
+pub fn command_extension(cmd: Command) -> Command {
+    let inner_subcommands = [
+        Command::new("apply")
+            .about("Apply the settings of a scene")
+            .arg(
+                arg!([NAME] "The name of the scene")
+                    .required(true)
+                    .add(completion::dynamic_values(scene_candidates)),
+            ),
+        Command::new("save")
+            .about("Save the current settings as a scene")
+            .arg(
+                arg!([NAME] "The name of the scene")
+                    .required(true)
+                    .add(completion::dynamic_values(scene_candidates)),
+            )
+            .arg(
+                arg!(-o --only <SETTING> "Only save this setting; can be given more than once")
+                    .value_parser(value_parser!(Setting))
+                    .action(clap::ArgAction::Append),
+            ),
+        Command::new("list").about("List the scenes and their settings"),
+    ];
+
+    cmd.subcommand_required(true)
+        .arg_required_else_help(true)
+        .subcommands(inner_subcommands.iter())
+}
+
+pub fn run(sh: &Shell, args: &ArgMatches) -> anyhow::Result<Option<ScriptOutput>> {
+    let name = |args: &ArgMatches| {
+        args.get_one::<String>("NAME")
+            .expect("NAME should be a required argument")
+            .clone()
+    };
+
+    let output = match args.subcommand() {
+        Some(("apply", apply_args)) => apply(sh, &name(apply_args))?,
+        Some(("save", save_args)) => {
+            let settings = match save_args.get_many::<Setting>("only") {
+                Some(only) => only.copied().collect(),
+                None => Setting::iter().collect::<Vec<_>>(),
+            };
+            save(sh, &name(save_args), &settings)?
+        }
+        Some(("list", _)) => list()?,
+        _ => return Ok(None),
+    };
+
+    Ok(Some(output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_settings_are_listed() {
+        let mut report = Report::new("movie");
+        report.record(Setting::AudioOutput, Ok(()));
+        report.record(
+            Setting::Brightness,
+            Err(anyhow::anyhow!("Not running").context("Failed to call gammarelay")),
+        );
+
+        let err = report.into_output("applied").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "1 of 2 settings of scene 'movie' could not be applied:\n  brightness: Failed to call gammarelay: Not running"
+        );
+    }
+
+    #[test]
+    fn only_the_set_values_are_saved() {
+        let scene = Scene {
+            audio_output: Some("tv".to_owned()),
+            font_size: Some(14.0),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            scene_values("movie", &scene),
+            [
+                (
+                    "scenes.movie.audio_output".to_owned(),
+                    Value::String("tv".to_owned())
+                ),
+                ("scenes.movie.font_size".to_owned(), Value::Float(14.0))
+            ]
+        );
+    }
+}
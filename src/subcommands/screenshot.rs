@@ -1,4 +1,3 @@
-use core::str;
 use std::str::FromStr;
 
 use anyhow::Context;
@@ -9,9 +8,16 @@ use hyprland::{
 };
 use strum::IntoEnumIterator;
 use strum_macros::{Display, EnumIter, EnumString};
-use xshell::{cmd, Cmd, Shell};
+use xshell::Shell;
 
-use crate::util::dmenu::Dmenu;
+use crate::{
+    util::{
+        dmenu::Dmenu,
+        notify::Notification,
+        runner::{CommandRunner, Invocation},
+    },
+    ScriptOutput,
+};
 
 #[derive(ValueEnum, Clone, Debug, Display, EnumString, EnumIter)]
 #[strum(serialize_all = "lowercase")]
@@ -21,10 +27,10 @@ enum Output {
 }
 
 impl Output {
-    fn add_grim_command_arguments<'a>(&self, cmd: Cmd<'a>) -> Cmd<'a> {
+    fn add_grim_command_arguments(&self, grim: Invocation) -> Invocation {
         match self {
-            Self::Clipboard => cmd.arg("-"),
-            Self::File => cmd,
+            Self::Clipboard => grim.arg("-"),
+            Self::File => grim,
         }
     }
 }
@@ -47,12 +53,12 @@ enum Target {
 }
 
 impl Target {
-    fn add_grim_command_arguments<'a>(&self, cmd: Cmd<'a>) -> Cmd<'a> {
+    fn add_grim_command_arguments(&self, grim: Invocation) -> Invocation {
         match self {
-            Self::Full => cmd,
-            Self::Window(area) => cmd.arg("-g").arg(area.to_string_repr()),
-            Self::Monitor(monitor) => cmd.arg("-o").arg(monitor),
-            Self::Area(area) => cmd.arg("-g").arg(area.to_string_repr()),
+            Self::Full => grim,
+            Self::Window(area) => grim.arg("-g").arg(area.to_string_repr()),
+            Self::Monitor(monitor) => grim.arg("-o").arg(monitor),
+            Self::Area(area) => grim.arg("-g").arg(area.to_string_repr()),
         }
     }
 }
@@ -137,64 +143,67 @@ fn get_window_target() -> anyhow::Result<Target> {
     }))
 }
 
-fn dmenu_monitor(sh: &Shell) -> anyhow::Result<String> {
+fn dmenu_monitor(runner: &dyn CommandRunner) -> anyhow::Result<String> {
     let monitors = Monitors::get()?;
     let monitors = monitors.iter().map(|m| m.name.as_ref()).collect::<Vec<_>>();
 
-    Dmenu::new(sh)
+    Dmenu::new(runner)
         .numbered()
         .auto_select()
         .choose_one_str("Select monitor", &monitors)
 }
 
-fn get_monitor_target(sh: &Shell, monitor: Option<&String>) -> anyhow::Result<Target> {
+fn get_monitor_target(
+    runner: &dyn CommandRunner,
+    monitor: Option<&String>,
+) -> anyhow::Result<Target> {
     if let Some(monitor) = monitor {
         Ok(Target::Monitor(monitor.clone()))
     } else {
-        let monitor = dmenu_monitor(sh)?;
+        let monitor = dmenu_monitor(runner)?;
         Ok(Target::Monitor(monitor))
     }
 }
 
-fn get_area_target(sh: &Shell, area: Option<&Area>) -> anyhow::Result<Target> {
+fn get_area_target(runner: &dyn CommandRunner, area: Option<&Area>) -> anyhow::Result<Target> {
     if let Some(area) = area {
         Ok(Target::Area(area.clone()))
     } else {
-        let area = cmd!(sh, "slurp").read()?;
+        let area = runner.read(&Invocation::new("slurp"))?;
         Ok(Target::Area(Area::from_slurp(area)))
     }
 }
 
-fn get_target(sh: &Shell, args: &ArgMatches) -> anyhow::Result<Target> {
+fn get_target(runner: &dyn CommandRunner, args: &ArgMatches) -> anyhow::Result<Target> {
     match args.subcommand() {
         Some(("full", _)) => Ok(Target::Full),
         Some(("window", _)) => get_window_target(),
         Some(("monitor", monitor_args)) => {
             let monitor = monitor_args.get_one::<String>("MONITOR");
-            get_monitor_target(sh, monitor)
+            get_monitor_target(runner, monitor)
         }
         Some(("area", area_args)) => {
             let area = area_args.get_one::<Area>("AREA");
-            get_area_target(sh, area)
+            get_area_target(runner, area)
         }
         _ => {
-            let target_name = dmenu_target_name(sh)?;
+            let target_name = dmenu_target_name(runner)?;
             match target_name {
                 TargetName::Full => Ok(Target::Full),
                 TargetName::Window => get_window_target(),
-                TargetName::Monitor => get_monitor_target(sh, None),
-                TargetName::Area => get_area_target(sh, None),
+                TargetName::Monitor => get_monitor_target(runner, None),
+                TargetName::Area => get_area_target(runner, None),
             }
         }
     }
 }
 
-fn get_output(sh: &Shell, args: &ArgMatches) -> anyhow::Result<Output> {
+fn get_output(runner: &dyn CommandRunner, args: &ArgMatches) -> anyhow::Result<Output> {
     if let Some(output) = args.get_one::<Output>("output") {
         Ok(output.clone())
     } else {
         let outputs = Output::iter().map(|o| o.to_string()).collect::<Vec<_>>();
-        let result = Dmenu::new(sh).auto_select().choose_one(
+        let result = Dmenu::new(runner).auto_select().choose_one(
             "Select screenshot output",
             &outputs,
             String::as_ref,
@@ -203,11 +212,11 @@ fn get_output(sh: &Shell, args: &ArgMatches) -> anyhow::Result<Output> {
     }
 }
 
-fn dmenu_target_name(sh: &Shell) -> anyhow::Result<TargetName> {
+fn dmenu_target_name(runner: &dyn CommandRunner) -> anyhow::Result<TargetName> {
     let target_names = TargetName::iter()
         .map(|t| t.to_string())
         .collect::<Vec<_>>();
-    let result = Dmenu::new(sh).auto_select().choose_one(
+    let result = Dmenu::new(runner).auto_select().choose_one(
         "Select screenshot target",
         &target_names,
         String::as_ref,
@@ -232,29 +241,25 @@ pub fn command_extension(cmd: Command) -> Command {
             .value_parser(value_parser!(Output)))
 }
 
-fn take_screenshot(sh: &Shell, target: &Target, output: &Output) -> anyhow::Result<()> {
-    let command = cmd!(sh, "grim");
-    let command = target.add_grim_command_arguments(command);
-    let command = output.add_grim_command_arguments(command);
+fn take_screenshot(
+    runner: &dyn CommandRunner,
+    target: &Target,
+    output: &Output,
+) -> anyhow::Result<()> {
+    let grim = Invocation::new("grim").capture_stderr();
+    let grim = target.add_grim_command_arguments(grim);
+    let grim = output.add_grim_command_arguments(grim);
 
-    let result = command.output()?;
-
-    if !result.status.success() {
-        anyhow::bail!(
-            "Command failed with exit code {}\n{}",
-            result.status,
-            str::from_utf8(&result.stderr).expect("Stderr to be valid UTF-8")
-        )
-    }
+    let result = runner.output(&grim)?.check(&grim)?;
 
     if let Output::Clipboard = output {
-        cmd!(sh, "wl-copy").stdin(&result.stdout).run()?;
+        runner.run(&Invocation::new("wl-copy").stdin(result.stdout))?;
     }
 
     Ok(())
 }
 
-fn send_desktop_notification(sh: &Shell, target: &Target, output: &Output) -> anyhow::Result<()> {
+fn send_desktop_notification(target: &Target, output: &Output) {
     let of = match target {
         Target::Full => "the entire display",
         Target::Window(_) => "the current window",
@@ -262,25 +267,59 @@ fn send_desktop_notification(sh: &Shell, target: &Target, output: &Output) -> an
         Target::Area(_) => "the selected area",
     };
 
-    cmd!(sh, "notify-send")
-        .arg("Screenshot")
-        .arg(format!(
-            "Screenshot taken of {} to {}",
-            of,
-            &output.to_string()
-        ))
-        .arg("-i")
-        .arg("accessories-screenshot-tool")
-        .run()
-        .map_err(|e| e.into())
+    Notification::new("Screenshot")
+        .body(format!("Screenshot taken of {} to {}", of, output))
+        .icon("accessories-screenshot-tool")
+        .send();
 }
 
-pub fn run(sh: &Shell, args: &ArgMatches) -> anyhow::Result<Option<String>> {
+pub fn run(sh: &Shell, args: &ArgMatches) -> anyhow::Result<Option<ScriptOutput>> {
     let target = get_target(sh, args)?;
     let output = get_output(sh, args)?;
 
     take_screenshot(sh, &target, &output)?;
-    send_desktop_notification(sh, &target, &output)?;
+    send_desktop_notification(&target, &output);
 
     Ok(None)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::util::runner::FakeRunner;
+
+    use super::*;
+
+    #[test]
+    fn clipboard_screenshot_is_piped_into_wl_copy() {
+        let fake = FakeRunner::new().respond("grim -o DP-1 -", "png bytes", 0);
+
+        take_screenshot(
+            &fake,
+            &Target::Monitor("DP-1".to_owned()),
+            &Output::Clipboard,
+        )
+        .unwrap();
+
+        let invocations = fake.invocations();
+        assert_eq!(fake.command_lines(), ["grim -o DP-1 -", "wl-copy"]);
+        assert_eq!(invocations[1].stdin.as_deref(), Some(&b"png bytes"[..]));
+    }
+
+    #[test]
+    fn failed_screenshot_is_not_copied() {
+        let area = "0,0,100,50".parse::<Area>().unwrap();
+        let fake = FakeRunner::new().respond_with_stderr(
+            "grim -g 0,0 100x50",
+            "",
+            "grim: screencopy is not supported by the compositor\n",
+            1,
+        );
+
+        let err = take_screenshot(&fake, &Target::Area(area), &Output::File).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "`grim -g 0,0 100x50` exited with code 1: grim: screencopy is not supported by the compositor"
+        );
+        assert_eq!(fake.command_lines(), ["grim -g 0,0 100x50"]);
+    }
+}
@@ -0,0 +1,333 @@
+//! The server runs commands on behalf of other plsdo processes, so that commands which are run
+//! often from keybinds (e.g. changing the volume while the key is held down) don't pay for
+//! starting up every time: the server has its command line parser built, its config loaded and its
+//! D-Bus connection open already.
+//!
+//! A plsdo process which is not started from a terminal sends its arguments to the server, and
+//! prints what the server answers. If no server is running, or the command must not run in the
+//! server, the process runs the command itself, as it would without a server.
+
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fs,
+    io::{BufRead, BufReader, IsTerminal, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::PathBuf,
+};
+
+use anyhow::Context;
+use clap::{ArgMatches, Command};
+use serde::{Deserialize, Serialize};
+use xshell::Shell;
+
+use crate::{
+    config,
+    system_atlas::SYSTEM_ATLAS,
+    util::{
+        dmenu::{is_aborted, MenuAborted},
+        is_same_user,
+        listener::{get_pidfile_lock, write_pid},
+        logging,
+    },
+    OutputFormat, ScriptOutput, SUBCOMMANDS,
+};
+
+/// Commands which are never forwarded: the ones which keep running, and the ones which need a
+/// process of their own, like ytdl downloads, which are told apart by their pid. The server reads
+/// the config once when it starts, so every forwarded command sees the config as it was then;
+/// scenes and keybinds are about the config itself, and are usually run right after editing it
+/// (`scene save` even writes it), so they read it afresh in their own process. The menu can run
+/// any of these, so it runs in its own process as well.
+const IN_PROCESS: &[&[&str]] = &[
+    &["daemon"],
+    &["serve"],
+    &["subscribe"],
+    &["completions"],
+    &["manpage"],
+    &["ytdl"],
+    &["scene"],
+    &["keybinds"],
+    &["menu"],
+    &["audio", "run_listener"],
+    &["workspace", "run_listener"],
+];
+
+/// The variables which decide which display, compositor and session bus a command acts on, and
+/// which programs it runs. The server opens its D-Bus connection and talks to the compositor with
+/// its own environment, so it only runs commands for clients whose values are the same.
+const SESSION_VARIABLES: &[&str] = &[
+    "WAYLAND_DISPLAY",
+    "DISPLAY",
+    "HYPRLAND_INSTANCE_SIGNATURE",
+    "XDG_RUNTIME_DIR",
+    "DBUS_SESSION_BUS_ADDRESS",
+    "PATH",
+];
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Request {
+    /// The full command line, including the name of the executable
+    args: Vec<String>,
+    /// The working directory of the client, which the command runs in
+    cwd: PathBuf,
+    /// The values of the session variables in the client; unset ones are left out
+    env: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+enum Response {
+    /// The command succeeded, and printed this
+    Done(Option<String>),
+    /// The command failed; these are the messages of the error chain, outermost first
+    Failed(Vec<String>),
+    Aborted,
+    /// The arguments could not be parsed (or asked for help). The client should run the command
+    /// itself, so that clap can print the usage exactly like it usually does.
+    Unparsable,
+    /// The client runs in another session or with another `PATH` than the server. The client
+    /// should run the command itself, so that it acts on its own display and runs its own programs.
+    ForeignEnvironment,
+}
+
+fn session_env() -> BTreeMap<String, String> {
+    SESSION_VARIABLES
+        .iter()
+        .filter_map(|&name| Some((name.to_owned(), std::env::var(name).ok()?)))
+        .collect()
+}
+
+pub fn command_extension(cmd: Command) -> Command {
+    cmd
+}
+
+/// Whether the command line (without the executable) names a built-in subcommand which may run
+/// in the server
+fn is_forwardable(args: &[String]) -> bool {
+    // The log level is global to the server, so it can't be changed for a single command
+    if args.iter().any(|arg| arg.starts_with("--log-level")) {
+        return false;
+    }
+
+    let mut words = args.iter().map(String::as_str).peekable();
+    while let Some(&word) = words.peek() {
+        match word {
+            "--output-format" => {
+                words.nth(1);
+            }
+            _ if word.starts_with("--output-format=") => {
+                words.next();
+            }
+            _ => break,
+        }
+    }
+
+    let Some(name) = words.next() else {
+        return false;
+    };
+    let Some((definition, _)) = SUBCOMMANDS
+        .iter()
+        .find(|(definition, _)| definition.is_called(name))
+    else {
+        // Plugins replace the process which runs them
+        return false;
+    };
+    let rest = words.collect::<Vec<_>>();
+
+    !IN_PROCESS.iter().any(|prefix| {
+        prefix[0] == definition.name
+            && prefix.len() - 1 <= rest.len()
+            && prefix[1..] == rest[..prefix.len() - 1]
+    })
+}
+
+/// Rebuild an error from the messages of its chain, so that it's reported like a local one
+fn error_from_causes(causes: Vec<String>) -> anyhow::Error {
+    let mut causes = causes.into_iter().rev();
+    let root = anyhow::anyhow!(causes.next().unwrap_or_default());
+    causes.fold(root, |err, cause| err.context(cause))
+}
+
+fn exchange(stream: &UnixStream, request: &Request) -> anyhow::Result<Response> {
+    let mut writer = stream;
+    serde_json::to_writer(&mut writer, request)?;
+    writer.write_all(b"\n")?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    if line.is_empty() {
+        anyhow::bail!("The server closed the connection without answering");
+    }
+    Ok(serde_json::from_str(&line)?)
+}
+
+/// Run the command in the server, if one is running and the command may run there. Returns the
+/// output of the command, or `None` if the command should run in this process instead.
+pub fn forward(args: &[OsString]) -> Option<anyhow::Result<Option<String>>> {
+    // From a terminal, the command may need the terminal itself (e.g. for the fzf menu), and the
+    // startup time doesn't matter
+    if std::io::stdin().is_terminal() || std::env::var_os("PLSDO_LOG").is_some() {
+        return None;
+    }
+
+    let args = args
+        .iter()
+        .map(|arg| arg.to_str().map(str::to_owned))
+        .collect::<Option<Vec<_>>>()?;
+    if !is_forwardable(args.get(1..)?) {
+        return None;
+    }
+
+    // A broken config is reported by running the command locally
+    config::init().ok()?;
+    let stream = UnixStream::connect(&SYSTEM_ATLAS.server_socket).ok()?;
+    // The arguments and the environment are only sent to a server of our own
+    if !is_same_user(&stream).ok()? {
+        return None;
+    }
+    let request = Request {
+        args,
+        cwd: std::env::current_dir().ok()?,
+        env: session_env(),
+    };
+
+    // Once the request is sent, the command may have run, so it can't be retried locally
+    let result = match exchange(&stream, &request) {
+        Ok(Response::Unparsable | Response::ForeignEnvironment) => return None,
+        Ok(Response::Done(output)) => Ok(output),
+        Ok(Response::Failed(causes)) => Err(error_from_causes(causes)),
+        Ok(Response::Aborted) => Err(MenuAborted.into()),
+        Err(err) => Err(err.context("Lost the connection to the server")),
+    };
+    Some(result)
+}
+
+fn execute(command: Command, request: &Request) -> Response {
+    if request.env != session_env() {
+        log::debug!(env:? = request.env; "The client runs in another environment");
+        return Response::ForeignEnvironment;
+    }
+    if !is_forwardable(request.args.get(1..).unwrap_or_default()) {
+        return Response::Failed(vec![format!(
+            "`{}` can't be run by the server",
+            request.args.join(" ")
+        )]);
+    }
+    let Ok(matches) = command.try_get_matches_from(&request.args) else {
+        return Response::Unparsable;
+    };
+    let format = matches
+        .get_one::<OutputFormat>("output-format")
+        .copied()
+        .unwrap_or_default();
+
+    let result = Shell::new().map_err(anyhow::Error::from).and_then(|sh| {
+        sh.change_dir(&request.cwd);
+        crate::run_subcommand(&sh, &request.args, &matches)?
+            .map(|output| output.render(format))
+            .transpose()
+    });
+
+    match result {
+        Ok(output) => Response::Done(output),
+        Err(err) if is_aborted(&err) => Response::Aborted,
+        Err(err) => {
+            log::warn!(args = request.args.join(" "), error = format!("{err:#}"); "Command failed");
+            Response::Failed(err.chain().map(|cause| cause.to_string()).collect())
+        }
+    }
+}
+
+fn handle_connection(command: Command, stream: UnixStream) -> anyhow::Result<()> {
+    if !is_same_user(&stream)? {
+        anyhow::bail!("Refused a connection from another user");
+    }
+
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let request = serde_json::from_str::<Request>(&line).context("Invalid request")?;
+    log::debug!(args = request.args.join(" "), cwd:? = request.cwd; "Running command");
+
+    let response = execute(command, &request);
+
+    let mut writer = &stream;
+    serde_json::to_writer(&mut writer, &response)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Serve until the process is killed. Every command runs in a thread of its own, so a command
+/// which waits on a menu doesn't hold up the others.
+pub fn run(_: &Shell, _: &ArgMatches) -> anyhow::Result<Option<ScriptOutput>> {
+    let mut lock = get_pidfile_lock(&SYSTEM_ATLAS.server_pidfile)?;
+    let mut guard = lock.try_write().context("The server is already running")?;
+    write_pid(&mut guard)?;
+    logging::log_to_file("server")?;
+
+    let path = &SYSTEM_ATLAS.server_socket;
+    let _ = fs::remove_file(path);
+    let listener = UnixListener::bind(path)?;
+    let command = crate::get_command();
+    log::info!(socket = path; "Serving commands");
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                log::warn!(error:% = err; "Failed to accept a connection");
+                continue;
+            }
+        };
+
+        let command = command.clone();
+        std::thread::spawn(move || {
+            if let Err(err) = handle_connection(command, stream) {
+                log::warn!(error = format!("{err:#}"); "Failed to handle a connection");
+            }
+        });
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forwardable(command_line: &str) -> bool {
+        let args = command_line
+            .split_whitespace()
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+        is_forwardable(&args)
+    }
+
+    #[test]
+    fn only_short_lived_builtins_are_forwarded() {
+        assert!(forwardable("audio volume set 50"));
+        assert!(forwardable("--output-format json audio output get"));
+        assert!(forwardable("audio"));
+
+        assert!(!forwardable("audio run_listener"));
+        assert!(!forwardable("--output-format=json ytdl download url x"));
+        assert!(!forwardable("--log-level debug audio volume set 50"));
+        assert!(!forwardable("some-plugin"));
+        assert!(!forwardable(""));
+    }
+
+    #[test]
+    fn errors_keep_their_chain() {
+        let causes = vec![
+            "Failed to run `lutris -l`".to_owned(),
+            "Not found".to_owned(),
+        ];
+        let err = error_from_causes(causes.clone());
+
+        assert_eq!(
+            err.chain()
+                .map(|cause| cause.to_string())
+                .collect::<Vec<_>>(),
+            causes
+        );
+    }
+}
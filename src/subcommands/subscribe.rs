@@ -0,0 +1,29 @@
+use clap::{arg, value_parser, ArgMatches, Command};
+use xshell::Shell;
+
+use crate::{
+    util::bus::{self, Topic},
+    ScriptOutput,
+};
+
+pub fn command_extension(cmd: Command) -> Command {
+    cmd.arg(
+        arg!([TOPIC] "The piece of state to follow")
+            .value_parser(value_parser!(Topic))
+            .required(true),
+    )
+}
+
+/// Print the latest value of the topic, then every change to it as a line of JSON, until the
+/// daemon shuts down
+pub fn run(_: &Shell, args: &ArgMatches) -> anyhow::Result<Option<ScriptOutput>> {
+    let topic = *args
+        .get_one::<Topic>("TOPIC")
+        .expect("TOPIC should be a required argument");
+
+    for value in bus::subscribe(topic)? {
+        println!("{}", value?);
+    }
+
+    Ok(None)
+}
@@ -1,4 +1,7 @@
+use std::fmt::Display;
+
 use clap::{ArgMatches, Command};
+use serde::Serialize;
 use transmission_rpc::{
     types::{TorrentGetField, TorrentStatus},
     TransClient,
@@ -6,11 +9,31 @@ use transmission_rpc::{
 use url::Url;
 use xshell::Shell;
 
+use crate::ScriptOutput;
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "lowercase")]
+enum TransferStatus {
+    Downloading { eta_minutes: u64 },
+    Seeding,
+    Idle,
+}
+
+impl Display for TransferStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Downloading { eta_minutes } => write!(f, "{}m", eta_minutes),
+            Self::Seeding => write!(f, "up"),
+            Self::Idle => Ok(()),
+        }
+    }
+}
+
 pub fn command_extension(cmd: Command) -> Command {
     cmd
 }
 
-pub fn run(_: &Shell, _args: &ArgMatches) -> anyhow::Result<Option<String>> {
+pub fn run(_: &Shell, _args: &ArgMatches) -> anyhow::Result<Option<ScriptOutput>> {
     let async_runtime = tokio::runtime::Builder::new_current_thread()
         .enable_all()
         .build()?;
@@ -33,22 +56,22 @@ pub fn run(_: &Shell, _args: &ArgMatches) -> anyhow::Result<Option<String>> {
         .map(|t| t.eta)
         .sum();
 
-    if let Some(total_eta) = total_eta {
-        if total_eta > 0 {
-            let eta_minutes = ((total_eta as f64) / 60f64).ceil();
-            println!("{}m", eta_minutes);
-            return Ok(None);
-        }
-    }
-
     let some_uploading = torrents
         .iter()
-        .filter(|&t| t.status == Some(TorrentStatus::Seeding))
-        .count();
+        .any(|t| t.status == Some(TorrentStatus::Seeding));
+
+    let status = match total_eta {
+        Some(total_eta) if total_eta > 0 => TransferStatus::Downloading {
+            eta_minutes: ((total_eta as f64) / 60f64).ceil() as u64,
+        },
+        _ if some_uploading => TransferStatus::Seeding,
+        _ => TransferStatus::Idle,
+    };
 
-    if some_uploading > 0 {
-        println!("up");
+    // Nothing to show, rather than an empty line
+    if let TransferStatus::Idle = status {
+        return Ok(None);
     }
 
-    Ok(None)
+    Ok(Some(ScriptOutput::new(&status)?.with_text(&status)))
 }
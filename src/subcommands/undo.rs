@@ -0,0 +1,358 @@
+//! Undo and redo of the state changes made by plsdo: the audio output and volume, the brightness,
+//! the color temperature, the keyboard layout and the font size.
+//!
+//! Commands which change one of them wrap the change in `record`, which snapshots the value
+//! before and after the change into the history file (`paths.undo_history`). `plsdo undo` puts
+//! the value from before the last change back through the same functions which the commands use,
+//! and `plsdo redo` reapplies it.
+
+use std::{
+    fmt::Display,
+    fs::{self, OpenOptions},
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use clap::{ArgMatches, Command};
+use fd_lock::RwLock;
+use serde::{Deserialize, Serialize};
+use xshell::Shell;
+
+use crate::{
+    system_atlas::SYSTEM_ATLAS,
+    util::{notify::Notification, runner::CommandRunner},
+    ScriptOutput,
+};
+
+#[cfg(feature = "hyprland")]
+use super::keyboard;
+use super::{audio::state as audio, font_size};
+#[cfg(feature = "dbus")]
+use super::{brightness, colortemp};
+
+/// Number of changes which can be undone
+const MAX_CHANGES: usize = 50;
+/// Changes of the same setting which follow each other within this many seconds are undone
+/// together, so that holding down the volume key doesn't take as many undos to revert
+const MERGE_WINDOW_SECONDS: u64 = 2;
+
+/// The value of a setting at some point in time
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Snapshot {
+    /// The name of the sink
+    AudioOutput(String),
+    Volume {
+        volume: u32,
+        is_muted: bool,
+    },
+    /// In percent
+    Brightness(u32),
+    /// In Kelvin
+    Colortemp(u16),
+    /// The id of the layout, e.g. "us"
+    KeyboardLayout(String),
+    FontSize(f64),
+}
+
+impl Snapshot {
+    fn is_same_setting(&self, other: &Self) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+impl Display for Snapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AudioOutput(name) => write!(f, "audio output {}", name),
+            Self::Volume {
+                volume,
+                is_muted: true,
+            } => write!(f, "volume {}% (muted)", volume),
+            Self::Volume { volume, .. } => write!(f, "volume {}%", volume),
+            Self::Brightness(brightness) => write!(f, "brightness {}%", brightness),
+            Self::Colortemp(colortemp) => write!(f, "color temperature {}K", colortemp),
+            Self::KeyboardLayout(id) => write!(f, "keyboard layout {}", id),
+            Self::FontSize(size) => write!(f, "font size {}", size),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Change {
+    before: Snapshot,
+    after: Snapshot,
+    /// Seconds since the epoch
+    changed_at: u64,
+}
+
+/// The changes which can be undone and redone, oldest first
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct History {
+    undo: Vec<Change>,
+    redo: Vec<Change>,
+}
+
+impl History {
+    fn push(&mut self, before: Snapshot, after: Snapshot, now: u64) {
+        // A new change makes the undone ones unreachable, like in an editor
+        self.redo.clear();
+
+        if let Some(last) = self.undo.last_mut() {
+            if last.after.is_same_setting(&after)
+                && now.saturating_sub(last.changed_at) <= MERGE_WINDOW_SECONDS
+            {
+                last.after = after;
+                last.changed_at = now;
+                return;
+            }
+        }
+
+        self.undo.push(Change {
+            before,
+            after,
+            changed_at: now,
+        });
+        if self.undo.len() > MAX_CHANGES {
+            self.undo.drain(..self.undo.len() - MAX_CHANGES);
+        }
+    }
+}
+
+/// Read the history, let `update` change it, and write it back. The file is locked in the
+/// meantime, so that commands which run at the same time don't lose each other's changes. If
+/// `update` fails, the history is left as it was.
+fn update_history<R>(
+    path: &Path,
+    update: impl FnOnce(&mut History) -> anyhow::Result<R>,
+) -> anyhow::Result<R> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut lock = RwLock::new(file);
+    let mut guard = lock.write()?;
+
+    let mut contents = String::new();
+    guard.read_to_string(&mut contents)?;
+    let mut history = if contents.trim().is_empty() {
+        History::default()
+    } else {
+        serde_json::from_str(&contents)
+            .with_context(|| format!("Invalid undo history {}", path.display()))?
+    };
+
+    let result = update(&mut history)?;
+
+    guard.set_len(0)?;
+    guard.seek(SeekFrom::Start(0))?;
+    serde_json::to_writer(&mut *guard, &history)?;
+    Ok(result)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs())
+}
+
+/// Make a change, and record it in the history, so that it can be undone. `capture` reads the
+/// value of the setting which is changed. A change which can't be recorded is still made.
+pub fn record<T>(
+    capture: impl Fn() -> anyhow::Result<Snapshot>,
+    change: impl FnOnce() -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    let before = capture();
+    let result = change()?;
+
+    let recorded = before.and_then(|before| {
+        let after = capture()?;
+        if before != after {
+            update_history(Path::new(&SYSTEM_ATLAS.undo_history), |history| {
+                history.push(before, after, now());
+                Ok(())
+            })?;
+        }
+        Ok(())
+    });
+    if let Err(err) = recorded {
+        log::warn!(error = format!("{err:#}"); "Failed to record a change for undo");
+    }
+
+    Ok(result)
+}
+
+/// Put the setting back to the value of the snapshot
+fn restore(runner: &dyn CommandRunner, snapshot: &Snapshot) -> anyhow::Result<()> {
+    match snapshot {
+        Snapshot::AudioOutput(name) => {
+            let outputs = audio::get_all_audio_outputs(runner)?;
+            let output = outputs
+                .iter()
+                .find(|output| output.name == *name)
+                .ok_or_else(|| anyhow::anyhow!("The audio output {} is gone", name))?;
+            audio::set_audio_output(runner, output)?;
+            audio::write_to_backing_file(audio::get_current_audio_state(runner)?)
+        }
+        Snapshot::Volume { volume, is_muted } => {
+            audio::set_volume(runner, (*volume).try_into()?, false)?;
+            audio::set_mute(runner, *is_muted)?;
+            audio::write_to_backing_file(audio::get_current_audio_state(runner)?)
+        }
+        #[cfg(feature = "dbus")]
+        Snapshot::Brightness(brightness) => brightness::set_brightness(*brightness),
+        #[cfg(feature = "dbus")]
+        Snapshot::Colortemp(colortemp) => colortemp::set_colortemp(*colortemp),
+        #[cfg(not(feature = "dbus"))]
+        Snapshot::Brightness(_) | Snapshot::Colortemp(_) => {
+            anyhow::bail!("plsdo was built without the `dbus` feature")
+        }
+        #[cfg(feature = "hyprland")]
+        Snapshot::KeyboardLayout(id) => keyboard::set_layout_by_id(runner, id),
+        #[cfg(not(feature = "hyprland"))]
+        Snapshot::KeyboardLayout(_) => {
+            anyhow::bail!("plsdo was built without the `hyprland` feature")
+        }
+        Snapshot::FontSize(size) => font_size::set_font_size(*size),
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Direction {
+    Undo,
+    Redo,
+}
+
+/// Undo the last change, or redo the last undone one
+pub fn step(runner: &dyn CommandRunner, direction: Direction) -> anyhow::Result<ScriptOutput> {
+    let snapshot = update_history(Path::new(&SYSTEM_ATLAS.undo_history), |history| {
+        let (from, to) = match direction {
+            Direction::Undo => (&mut history.undo, &mut history.redo),
+            Direction::Redo => (&mut history.redo, &mut history.undo),
+        };
+        let change = from.pop().ok_or_else(|| match direction {
+            Direction::Undo => anyhow::anyhow!("There is nothing to undo"),
+            Direction::Redo => anyhow::anyhow!("There is nothing to redo"),
+        })?;
+
+        let snapshot = match direction {
+            Direction::Undo => change.before.clone(),
+            Direction::Redo => change.after.clone(),
+        };
+        restore(runner, &snapshot)
+            .with_context(|| format!("Failed to restore the {}", snapshot))?;
+
+        to.push(change);
+        Ok(snapshot)
+    })?;
+
+    let (summary, icon) = match direction {
+        Direction::Undo => ("Undo", "edit-undo"),
+        Direction::Redo => ("Redo", "edit-redo"),
+    };
+    let text = format!("Restored the {}", snapshot);
+    Notification::new(summary)
+        .body(&text)
+        .icon(icon)
+        .tag("undo")
+        .send();
+
+    Ok(ScriptOutput::new(&snapshot)?.with_text(text))
+}
+
+pub fn command_extension(cmd: Command) -> Command {
+    cmd
+}
+
+pub fn run(sh: &Shell, _: &ArgMatches) -> anyhow::Result<Option<ScriptOutput>> {
+    step(sh, Direction::Undo).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn brightness_change(before: u32, after: u32, at: u64) -> (Snapshot, Snapshot, u64) {
+        (
+            Snapshot::Brightness(before),
+            Snapshot::Brightness(after),
+            at,
+        )
+    }
+
+    #[test]
+    fn quick_changes_of_a_setting_are_merged() {
+        let mut history = History::default();
+        for (before, after, at) in [
+            brightness_change(50, 45, 100),
+            brightness_change(45, 40, 101),
+            brightness_change(40, 35, 103),
+            brightness_change(35, 60, 110),
+        ] {
+            history.push(before, after, at);
+        }
+        history.push(Snapshot::FontSize(11.0), Snapshot::FontSize(12.0), 110);
+
+        let changes = history
+            .undo
+            .iter()
+            .map(|change| (change.before.clone(), change.after.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            [
+                (Snapshot::Brightness(50), Snapshot::Brightness(35)),
+                (Snapshot::Brightness(35), Snapshot::Brightness(60)),
+                (Snapshot::FontSize(11.0), Snapshot::FontSize(12.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let mut history = History::default();
+        history.redo.push(Change {
+            before: Snapshot::Colortemp(6500),
+            after: Snapshot::Colortemp(4000),
+            changed_at: 0,
+        });
+
+        for i in 0..MAX_CHANGES as u32 + 10 {
+            let (before, after, at) = brightness_change(i, i + 1, u64::from(i) * 10);
+            history.push(before, after, at);
+        }
+
+        assert_eq!(history.undo.len(), MAX_CHANGES);
+        assert_eq!(history.undo[0].before, Snapshot::Brightness(10));
+        assert!(history.redo.is_empty());
+    }
+
+    #[test]
+    fn failed_update_leaves_the_history_alone() {
+        let path = std::env::temp_dir().join(format!("plsdo-undo-test-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        update_history(&path, |history| {
+            history.push(Snapshot::FontSize(11.0), Snapshot::FontSize(12.0), 0);
+            Ok(())
+        })
+        .unwrap();
+        let result: anyhow::Result<()> = update_history(&path, |history| {
+            history.undo.clear();
+            anyhow::bail!("Not restored")
+        });
+
+        assert!(result.is_err());
+        let undone = update_history(&path, |history| Ok(history.undo.pop())).unwrap();
+        assert_eq!(undone.unwrap().before, Snapshot::FontSize(11.0));
+        fs::remove_file(&path).unwrap();
+    }
+}
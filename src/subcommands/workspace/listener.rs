@@ -1,68 +1,66 @@
-use std::{
-    fs::OpenOptions,
-    io::{LineWriter, Write},
-};
-
 use anyhow::Context;
-use clap::ArgMatches;
 use hyprland::event_listener::{MonitorAddedEventData, WorkspaceEventData};
 
 use crate::{
     system_atlas::SYSTEM_ATLAS,
-    util::listener::{get_pidfile_lock, write_pid},
+    util::{
+        backing_file,
+        bus::{self, Topic},
+        listener::{get_pidfile_lock, write_pid},
+        logging,
+    },
 };
 
 use super::{update_system_bar_layout, write_workspace_state_to_backing_file};
 
-const PIDFILE: &str = "/tmp/plsdo-hypr-workspace-listener.pid";
-
 fn handle_workspace_changed_event(_data: WorkspaceEventData) {
+    log::debug!("Workspace changed");
     if let Err(e) = write_workspace_state_to_backing_file() {
-        eprintln!("Failed to write to backing file: {}", e);
+        log::error!(error = format!("{e:#}"); "Failed to write the workspaces to the backing file");
     }
 }
 
-fn handle_monitor_added_event(_data: MonitorAddedEventData) {
+fn handle_monitor_added_event(data: MonitorAddedEventData) {
+    log::info!(monitor = data.name; "Monitor added");
     if let Err(e) = update_system_bar_layout() {
-        eprintln!("Failed to handle monitor added event: {}", e);
+        log::error!(error = format!("{e:#}"); "Failed to update the system bar layout");
     };
     if let Err(e) = write_workspace_state_to_backing_file() {
-        eprintln!("Failed to write to backing file: {}", e);
+        log::error!(error = format!("{e:#}"); "Failed to write the workspaces to the backing file");
     }
 }
 
-fn handle_monitor_removed_event(_monitor_name: String) {
+fn handle_monitor_removed_event(monitor_name: String) {
+    log::info!(monitor = monitor_name; "Monitor removed");
     if let Err(e) = update_system_bar_layout() {
-        eprintln!("Failed to handle monitor added event: {}", e);
+        log::error!(error = format!("{e:#}"); "Failed to update the system bar layout");
     };
     if let Err(e) = write_workspace_state_to_backing_file() {
-        eprintln!("Failed to write to backing file: {}", e);
+        log::error!(error = format!("{e:#}"); "Failed to write the workspaces to the backing file");
     }
 }
 
 pub fn write_submap_to_backing_file(submap_name: String) -> anyhow::Result<()> {
-    let file = OpenOptions::new()
-        .append(true)
-        .open(SYSTEM_ATLAS.hypr_submap)?;
+    backing_file::append_line(&SYSTEM_ATLAS.hypr_submap, &submap_name)?;
 
-    let mut writer = LineWriter::new(&file);
-    writer.write_fmt(format_args!("{}\n", submap_name))?;
-
-    Ok(())
+    bus::publish(Topic::Submap, &submap_name)
 }
 
 fn handle_submap_change_event(submap_name: String) {
+    log::debug!(submap = submap_name; "Submap changed");
     if let Err(e) = write_submap_to_backing_file(submap_name) {
-        eprintln!("Failed to write to backing file: {}", e);
+        log::error!(error = format!("{e:#}"); "Failed to write the submap to the backing file");
     }
 }
 
-pub fn run(_args: &ArgMatches) -> anyhow::Result<()> {
-    let mut lock = get_pidfile_lock(PIDFILE)?;
+pub fn run() -> anyhow::Result<()> {
+    let mut lock = get_pidfile_lock(&SYSTEM_ATLAS.workspace_listener_pidfile)?;
     let mut guard = lock
         .try_write()
         .context("The listener is already running")?;
     write_pid(&mut guard)?;
+    logging::log_to_file("workspace")?;
+    log::info!("Listening to Hyprland events");
 
     let mut listener = hyprland::event_listener::EventListener::new();
 
@@ -1,11 +1,8 @@
-use std::{
-    collections::{BTreeSet, HashMap},
-    fs::OpenOptions,
-    io::{LineWriter, Write},
-};
+use std::collections::{BTreeSet, HashMap};
 
 use anyhow::Context;
-use clap::{arg, value_parser, ArgMatches, Command, ValueEnum};
+use clap::{Subcommand, ValueEnum};
+use clap_complete::engine::{ArgValueCandidates, CompletionCandidate};
 use gio::{prelude::AppInfoExt, AppInfo, AppLaunchContext};
 use hyprland::{
     data::{Clients, Monitors},
@@ -16,7 +13,14 @@ use listener::write_submap_to_backing_file;
 use serde::Serialize;
 use xshell::{cmd, Shell};
 
-use crate::system_atlas::SYSTEM_ATLAS;
+use crate::{
+    system_atlas::SYSTEM_ATLAS,
+    util::{
+        backing_file,
+        bus::{self, Topic},
+    },
+    ScriptOutput,
+};
 
 mod listener;
 
@@ -28,7 +32,7 @@ struct WorkspaceState {
 }
 
 /// Append the state of the workspaces to a file, from which the eww workspaces widget can read it
-/// from.
+/// from, and publish it to the state bus.
 pub fn write_workspace_state_to_backing_file() -> anyhow::Result<()> {
     let active_workspaces = get_active_workspaces()?;
 
@@ -54,15 +58,9 @@ pub fn write_workspace_state_to_backing_file() -> anyhow::Result<()> {
         occupied_workspace_ids: occupied_workspace_ids.into_iter().collect::<Vec<_>>(),
     };
 
-    let file = OpenOptions::new()
-        .append(true)
-        .open(SYSTEM_ATLAS.eww_workspaces)?;
-    let mut writer = LineWriter::new(&file);
-
-    serde_json::to_writer(&mut writer, &workspace_state)?;
-    writer.write_all(b"\n")?;
+    backing_file::append_json(&SYSTEM_ATLAS.eww_workspaces, &workspace_state)?;
 
-    Ok(())
+    bus::publish(Topic::Workspace, &workspace_state)
 }
 
 pub fn update_system_bar_layout() -> anyhow::Result<()> {
@@ -110,7 +108,7 @@ impl MonitorConfiguration {
                 }
             }
             Err(e) => {
-                println!("There are unrecognized monitors connected; Error: {}", e);
+                log::warn!(error = format!("{e:#}"); "There are unrecognized monitors connected");
                 return Ok(Self::Unrecognized);
             }
         }
@@ -120,8 +118,10 @@ impl MonitorConfiguration {
 }
 
 #[derive(ValueEnum, Debug, Clone, Hash, Eq, PartialEq)]
-enum Monitor {
+pub enum Monitor {
+    /// HDMI-A-1
     Primary,
+    /// DP-1
     Secondary,
 }
 
@@ -185,91 +185,87 @@ struct PinnedProgram<'a> {
     wm_class: &'a str,
 }
 
-pub fn command_extension(cmd: Command) -> Command {
-    let inner_subcommands = [
-        Command::new("init").about("Initialize workspaces and workspace-related information"),
-        Command::new("focus")
-            .about("Move focus to the specified workspace")
-            .arg_required_else_help(true)
-            .subcommand_required(true)
-            .subcommands(
-                [
-                    Command::new("next")
-                        .about("Move focus to the next workspace on the specified monitor")
-                        .arg_required_else_help(true)
-                        .arg(
-                            arg!([MONITOR] "Identifier of the monitor")
-                                .value_parser(value_parser!(Monitor))
-                                .required(true),
-                        ),
-                    Command::new("next-current")
-                        .about("Move focus to the next workspace on the current monitor"),
-                    Command::new("prev")
-                        .about("Move focus to the previous workspace on the specified monitor")
-                        .arg_required_else_help(true)
-                        .arg(
-                            arg!([MONITOR] "Identifier of the monitor")
-                                .value_parser(value_parser!(Monitor))
-                                .required(true),
-                        ),
-                    Command::new("prev-current")
-                        .about("Move focus to the next workspace on the current monitor"),
-                    Command::new("id")
-                        .about("Move focus to the workspace with the given identifier")
-                        .arg_required_else_help(true)
-                        .arg(
-                            arg!([WORKSPACE] "Identifier of the workspace")
-                                .value_parser(value_parser!(WorkspaceId)),
-                        ),
-                ]
-                .iter(),
-            ),
-        Command::new("move")
-            .about("Move focus and the current window to the specified workspace")
-            .arg_required_else_help(true)
-            .subcommand_required(true)
-            .subcommands(
-                [
-                    Command::new("next")
-                        .about("Move focus and the current window to the next workspace on the specified monitor")
-                        .arg_required_else_help(true)
-                        .arg(
-                            arg!([MONITOR] "Identifier of the monitor")
-                                .value_parser(value_parser!(Monitor))
-                                .required(true),
-                        ),
-                    Command::new("next-current")
-                        .about("Move focus and the current window to the next workspace on the current monitor"),
-                    Command::new("prev")
-                        .about("Move focus and the current window to the previous workspace on the specified monitor")
-                        .arg_required_else_help(true)
-                        .arg(
-                            arg!([MONITOR] "Identifier of the monitor")
-                                .value_parser(value_parser!(Monitor))
-                                .required(true),
-                        ),
-                    Command::new("prev-current")
-                        .about("Move focus and the current window to the next workspace on the current monitor"),
-                    Command::new("id")
-                        .about("Move focus and the current window to the workspace with the given identifier")
-                        .arg_required_else_help(true)
-                        .arg(
-                            arg!([WORKSPACE] "Identifier of the workspace")
-                                .value_parser(value_parser!(WorkspaceId)),
-                        ),
-                ]
-                .iter(),
-            ),
-        Command::new("open_pinned")
-            .about("Open and navigate to a pinned window")
-            .arg_required_else_help(true)
-            .arg(arg!([PROGRAM] "The name of the program whose pinned window to navigate to")),
-        Command::new("run_listener")
-            .about("Launch the hypr event listener process")
-    ];
-    cmd.subcommand_required(true)
-        .arg_required_else_help(true)
-        .subcommands(inner_subcommands.iter())
+const PINNED_PROGRAMS: [PinnedProgram<'static>; 6] = [
+    PinnedProgram {
+        name: "newsboat",
+        wm_class: "newsboat",
+    },
+    PinnedProgram {
+        name: "ncmpcpp",
+        wm_class: "ncmpcpp",
+    },
+    PinnedProgram {
+        name: "btop",
+        wm_class: "btop",
+    },
+    PinnedProgram {
+        name: "pulsemixer",
+        wm_class: "pulsemixer",
+    },
+    PinnedProgram {
+        name: "notes",
+        wm_class: "notes",
+    },
+    PinnedProgram {
+        name: "Firefox",
+        wm_class: "firefox",
+    },
+];
+
+fn pinned_program_candidates() -> Vec<CompletionCandidate> {
+    PINNED_PROGRAMS
+        .iter()
+        .map(|program| CompletionCandidate::new(program.name))
+        .collect()
+}
+
+#[derive(Subcommand, Debug)]
+pub enum WorkspaceCommand {
+    /// Initialize workspaces and workspace-related information
+    Init,
+    /// Move focus to the specified workspace
+    #[command(subcommand)]
+    Focus(Target),
+    /// Move focus and the current window to the specified workspace
+    #[command(subcommand)]
+    Move(Target),
+    /// Open and navigate to a pinned window
+    #[command(name = "open_pinned", arg_required_else_help = true)]
+    OpenPinned {
+        /// The name of the program whose pinned window to navigate to
+        #[arg(add = ArgValueCandidates::new(pinned_program_candidates))]
+        program: String,
+    },
+    /// Launch the hypr event listener process
+    #[command(name = "run_listener", hide = true)]
+    RunListener,
+}
+
+/// The workspace to focus (or to move the current window to)
+#[derive(Subcommand, Debug)]
+pub enum Target {
+    /// The next workspace on the specified monitor
+    #[command(arg_required_else_help = true)]
+    Next {
+        /// Identifier of the monitor
+        monitor: Monitor,
+    },
+    /// The next workspace on the current monitor
+    NextCurrent,
+    /// The previous workspace on the specified monitor
+    #[command(arg_required_else_help = true)]
+    Prev {
+        /// Identifier of the monitor
+        monitor: Monitor,
+    },
+    /// The previous workspace on the current monitor
+    PrevCurrent,
+    /// The workspace with the given identifier
+    #[command(arg_required_else_help = true)]
+    Id {
+        /// Identifier of the workspace
+        workspace: WorkspaceId,
+    },
 }
 
 fn focus_window_by_wm_class(wm_class: &str) -> anyhow::Result<()> {
@@ -370,86 +366,32 @@ fn get_focus_workspace_dispatcher<'a>(
     }
 }
 
-fn focus_workspace(sh: &Shell, args: &ArgMatches, move_window: bool) -> anyhow::Result<()> {
-    match args.subcommand() {
-        Some(("next", next_args)) => {
-            let monitor = next_args
-                .get_one::<Monitor>("MONITOR")
-                .expect("MONITOR should be a required argument");
-            let workspace_id =
-                get_relative_workspace_id_on_monitor(monitor, WorkspaceDirection::Next)?;
-            let dispatch = get_focus_workspace_dispatcher(workspace_id, move_window);
-            Dispatch::call(dispatch)?;
+fn focus_workspace(sh: &Shell, target: Target, move_window: bool) -> anyhow::Result<()> {
+    let workspace_id = match target {
+        Target::Next { monitor } => {
+            get_relative_workspace_id_on_monitor(&monitor, WorkspaceDirection::Next)?
         }
-        Some(("next-current", _)) => {
+        Target::NextCurrent => {
             let monitor = get_active_monitor(sh)?;
-            let workspace_id =
-                get_relative_workspace_id_on_monitor(&monitor, WorkspaceDirection::Next)?;
-            let dispatch = get_focus_workspace_dispatcher(workspace_id, move_window);
-            Dispatch::call(dispatch)?;
+            get_relative_workspace_id_on_monitor(&monitor, WorkspaceDirection::Next)?
         }
-        Some(("prev", prev_args)) => {
-            let monitor = prev_args
-                .get_one::<Monitor>("MONITOR")
-                .expect("MONITOR should be a required argument");
-            let workspace_id =
-                get_relative_workspace_id_on_monitor(monitor, WorkspaceDirection::Previous)?;
-            let dispatch = get_focus_workspace_dispatcher(workspace_id, move_window);
-            Dispatch::call(dispatch)?;
+        Target::Prev { monitor } => {
+            get_relative_workspace_id_on_monitor(&monitor, WorkspaceDirection::Previous)?
         }
-        Some(("prev-current", _)) => {
+        Target::PrevCurrent => {
             let monitor = get_active_monitor(sh)?;
-            let workspace_id =
-                get_relative_workspace_id_on_monitor(&monitor, WorkspaceDirection::Previous)?;
-            let dispatch = get_focus_workspace_dispatcher(workspace_id, move_window);
-            Dispatch::call(dispatch)?;
-        }
-        Some(("id", id_args)) => {
-            let id = id_args
-                .get_one::<WorkspaceId>("WORKSPACE")
-                .expect("WORKSPACE should be a required argument");
-            let dispatch = get_focus_workspace_dispatcher(*id, move_window);
-            Dispatch::call(dispatch)?;
+            get_relative_workspace_id_on_monitor(&monitor, WorkspaceDirection::Previous)?
         }
-        _ => return Ok(()),
+        Target::Id { workspace } => workspace,
     };
 
+    let dispatch = get_focus_workspace_dispatcher(workspace_id, move_window);
+    Dispatch::call(dispatch)?;
     Ok(())
 }
 
-fn open_pinned(args: &ArgMatches) -> anyhow::Result<()> {
-    let program_name = args
-        .get_one::<String>("PROGRAM")
-        .expect("PROGRAM should be a required argument");
-
-    let pinned_programs = [
-        PinnedProgram {
-            name: "newsboat",
-            wm_class: "newsboat",
-        },
-        PinnedProgram {
-            name: "ncmpcpp",
-            wm_class: "ncmpcpp",
-        },
-        PinnedProgram {
-            name: "btop",
-            wm_class: "btop",
-        },
-        PinnedProgram {
-            name: "pulsemixer",
-            wm_class: "pulsemixer",
-        },
-        PinnedProgram {
-            name: "notes",
-            wm_class: "notes",
-        },
-        PinnedProgram {
-            name: "Firefox",
-            wm_class: "firefox",
-        },
-    ];
-
-    let Some(pinned_program) = pinned_programs
+fn open_pinned(program_name: &str) -> anyhow::Result<()> {
+    let Some(pinned_program) = PINNED_PROGRAMS
         .iter()
         .find(|program| program.name == program_name)
     else {
@@ -500,14 +442,13 @@ fn initialize() -> anyhow::Result<()> {
     Ok(())
 }
 
-pub fn run(sh: &Shell, args: &ArgMatches) -> anyhow::Result<Option<String>> {
-    match args.subcommand() {
-        Some(("init", _)) => initialize(),
-        Some(("focus", focus_args)) => focus_workspace(sh, focus_args, false),
-        Some(("move", move_args)) => focus_workspace(sh, move_args, true),
-        Some(("open_pinned", open_pinned_args)) => open_pinned(open_pinned_args),
-        Some(("run_listener", run_listener_args)) => listener::run(run_listener_args),
-        _ => Ok(()),
+pub fn run(sh: &Shell, command: WorkspaceCommand) -> anyhow::Result<Option<ScriptOutput>> {
+    match command {
+        WorkspaceCommand::Init => initialize(),
+        WorkspaceCommand::Focus(target) => focus_workspace(sh, target, false),
+        WorkspaceCommand::Move(target) => focus_workspace(sh, target, true),
+        WorkspaceCommand::OpenPinned { program } => open_pinned(&program),
+        WorkspaceCommand::RunListener => listener::run(),
     }?;
 
     Ok(None)
@@ -6,9 +6,16 @@ use std::{
     sync::{Arc, Mutex},
 };
 
+use anyhow::Context;
 use serde::{Deserialize, Serialize};
 
-use crate::system_atlas::SYSTEM_ATLAS;
+use crate::{
+    system_atlas::SYSTEM_ATLAS,
+    util::{
+        listener::{get_pidfile_lock, write_pid},
+        logging,
+    },
+};
 
 use super::{ytdl_line::Progress, DownloadProcessMessage, Message, ProcessId};
 
@@ -76,7 +83,7 @@ impl FullDownloadInfo {
     }
 }
 
-// TODO: prepare progress server to be a systemd service? logging and stuff?
+// TODO: prepare progress server to be a systemd service?
 // TODO: pretty printing progress to console?
 
 type State = Arc<Mutex<BTreeMap<ProcessId, DownloadInfo>>>;
@@ -98,7 +105,7 @@ fn handle_query_message(
     ))?;
     let state = state.lock().expect("lock to work");
     let state_string = serde_json::to_string_pretty(&*state)?;
-    println!("State string: {state_string}");
+    log::debug!(downloads = state.len(); "Answering a query");
     socket.send_to(state_string.as_bytes(), dp_socket_path)?;
     Ok(())
 }
@@ -109,7 +116,6 @@ fn handle_message(
     socket: &UnixDatagram,
     dp_addr: &SocketAddr,
 ) -> anyhow::Result<()> {
-    println!("Message: {:?}", message);
     match message {
         Message::QueryMessage => handle_query_message(state, socket, dp_addr),
         Message::DownloadProcessMessage(message) => handle_download_process_message(state, message),
@@ -124,6 +130,7 @@ fn handle_download_process_message(
 
     let mut handle_exit = |pid: ProcessId, ecode: i32| {
         let _dlinfo = state.remove(&pid);
+        log::info!(pid, exit_code = ecode; "Download process exited");
         if ecode != 0 {
             anyhow::bail!("Download Process exited with exit code {}", ecode);
         }
@@ -134,6 +141,7 @@ fn handle_download_process_message(
         super::MessagePayload::YtdlLine(line) => match line {
             super::ytdl_line::YtdlLine::VideoUrl(url) => {
                 // TODO: maybe get angry here if pid is already tracked?
+                log::info!(pid = message.pid, url; "Download started");
                 state.insert(message.pid, DownloadInfo::UrlOnly(UrlOnly { url }));
             }
             super::ytdl_line::YtdlLine::VideoDownloadPath(path_string) => {
@@ -144,9 +152,19 @@ fn handle_download_process_message(
                     anyhow::bail!("Received VideoDownloadPath while DownloadInfo wasn't UrlOnly")
                 };
                 let metadata = url.create_metadata(path_string)?;
+                log::debug!(pid = message.pid, path = metadata.path; "Download destination is known");
                 state.insert(message.pid, DownloadInfo::MetadataOnly(metadata));
             }
             super::ytdl_line::YtdlLine::VideoDownloadProgress(progress) => {
+                if let Progress::Downloading(p) = &progress {
+                    log::trace!(
+                        pid = message.pid,
+                        percent = p.percent,
+                        speed_kib = p.download_speed,
+                        eta = p.eta;
+                        "Download progress"
+                    );
+                }
                 let dlinfo = state.remove(&message.pid).ok_or(anyhow::anyhow!(
                     "Received VideoDownloadProgress for a download which was not tracked"
                 ))?;
@@ -173,9 +191,11 @@ fn handle_download_process_message(
                     anyhow::bail!("Received VideoDownloadDone before VideoDownloadProgress");
                 };
                 full_dlinfo.set_as_completed();
+                log::info!(pid = message.pid, title = full_dlinfo.metadata.title; "Download completed");
                 state.insert(message.pid, DownloadInfo::Full(full_dlinfo));
             }
-            super::ytdl_line::YtdlLine::VideoDownloadError(_) => {
+            super::ytdl_line::YtdlLine::VideoDownloadError(error) => {
+                log::warn!(pid = message.pid, error; "Download failed");
                 let _dlinfo = state.remove(&message.pid);
             }
             super::ytdl_line::YtdlLine::VideoExtractAudio(_) => {
@@ -191,6 +211,7 @@ fn handle_download_process_message(
                     );
                 }
                 full_dlinfo.set_as_extracting();
+                log::info!(pid = message.pid; "Extracting audio");
                 state.insert(message.pid, DownloadInfo::Full(full_dlinfo));
             }
             super::ytdl_line::YtdlLine::Exit(ecode) => handle_exit(message.pid, ecode)?,
@@ -208,15 +229,23 @@ fn handle_download_process_message(
 }
 
 fn start_socket() -> anyhow::Result<UnixDatagram> {
-    let _ = fs::remove_file(SYSTEM_ATLAS.ytdl_aggregator_socket);
-    let listener = UnixDatagram::bind(SYSTEM_ATLAS.ytdl_aggregator_socket)?;
+    let _ = fs::remove_file(&SYSTEM_ATLAS.ytdl_aggregator_socket);
+    let listener = UnixDatagram::bind(&SYSTEM_ATLAS.ytdl_aggregator_socket)?;
     Ok(listener)
 }
 
 /// Launch a daemon process, which maintains a map of ongoing ytdl downloads
 pub fn run() -> anyhow::Result<()> {
+    let mut lock = get_pidfile_lock(&SYSTEM_ATLAS.ytdl_aggregator_pidfile)?;
+    let mut guard = lock
+        .try_write()
+        .context("The aggregator is already running")?;
+    write_pid(&mut guard)?;
+    logging::log_to_file("ytdl")?;
+
     let state: State = Arc::new(Mutex::new(BTreeMap::new()));
     let socket = start_socket()?;
+    log::info!(socket = SYSTEM_ATLAS.ytdl_aggregator_socket; "Aggregating download progress");
 
     // TODO: what would be the optimal size?
     let mut buf = vec![0; 1024];
@@ -224,13 +253,15 @@ pub fn run() -> anyhow::Result<()> {
     loop {
         match socket.recv_from(buf.as_mut_slice()) {
             Ok((n, dp_addr)) => {
-                println!("Aggregator: Received {n} bytes");
+                log::trace!(bytes = n; "Received a message");
 
                 match serde_json::from_slice::<Message>(&buf[0..n]) {
                     Ok(message) => {
-                        let _ = handle_message(&state, message, &socket, &dp_addr);
+                        if let Err(e) = handle_message(&state, message, &socket, &dp_addr) {
+                            log::warn!(error = format!("{e:#}"); "Failed to handle a message");
+                        }
                     }
-                    Err(e) => eprintln!("{e:?}"),
+                    Err(e) => log::warn!(error = e.to_string(); "Received an invalid message"),
                 };
             }
             Err(e) => return Err(e.into()),
@@ -8,7 +8,14 @@ use xshell::Shell;
 
 use crate::{
     system_atlas::SYSTEM_ATLAS,
-    util::{determine_wm, dmenu::Dmenu, Clipboard, RealClipboard},
+    util::{
+        determine_wm,
+        dmenu::Dmenu,
+        notify::{Notification, Urgency},
+        runner::{CommandRunner, Invocation},
+        Clipboard, RealClipboard,
+    },
+    ScriptOutput,
 };
 
 use self::{
@@ -59,8 +66,11 @@ pub fn command_extension(cmd: Command) -> Command {
                         .arg(arg!([URL] "The URL to download from")),
                     Command::new("clipboard").about("Download a video or audio file, trying to interpret the clipboard contents as an URL"),
                 ]
-            ).arg(arg!(-f --format <FORMAT>).value_parser(value_parser!(DownloadFormat))),
-        Command::new("run_aggregator").about("Run the aggregator server, which aggregates the progress of ongoing downloads"),
+            ).arg(
+                arg!(-f --format <FORMAT> "The quality, or format, to download")
+                    .value_parser(value_parser!(DownloadFormat))
+            ),
+        Command::new("run_aggregator").about("Run the aggregator server, which aggregates the progress of ongoing downloads").hide(true),
         Command::new("get_download_progress").about("Get the progress of ongoing downloads from the aggregator")
     ];
     cmd.subcommand_required(true)
@@ -130,38 +140,88 @@ fn send_message(socket: &UnixDatagram, message: &Message) -> anyhow::Result<()>
     Ok(())
 }
 
-fn send_query_message(socket: &UnixDatagram, message: &Message) -> anyhow::Result<String> {
+fn send_query_message(
+    socket: &UnixDatagram,
+    message: &Message,
+) -> anyhow::Result<serde_json::Value> {
     send_message(socket, message)?;
     // TODO: find optimal buffer size
     let mut buf = vec![0; 1024];
-    let _ = socket.recv(buf.as_mut_slice());
-    println!("Query: received answer");
-    let response = String::from_utf8(buf)?;
+    let n = socket.recv(buf.as_mut_slice())?;
+    let response = serde_json::from_slice(&buf[..n])
+        .context("Received malformed response from the aggregator")?;
     Ok(response)
 }
 
+/// Forward the lines to the aggregator, and return the path of the downloaded file, if the
+/// lines mentioned it
 fn process_lines(
     pid: ProcessId,
     stream: &UnixDatagram,
     lines: impl Iterator<Item = std::io::Result<String>>,
-) {
+) -> Option<String> {
+    let mut path = None;
+
     for line in lines {
         match line {
             Ok(line) => {
                 if let Ok(line) = ytdl_line::parse(&line) {
+                    if let YtdlLine::VideoDownloadPath(p) | YtdlLine::VideoExtractAudio(p) = &line {
+                        path = Some(p.clone());
+                    }
                     let message = Message::DownloadProcessMessage(DownloadProcessMessage {
                         pid,
                         payload: MessagePayload::YtdlLine(line),
                     });
                     if let Err(err) = send_message(stream, &message) {
-                        eprintln!("Could not send message!");
-                        eprintln!("{:?}", err);
+                        log::warn!(error = format!("{err:#}"); "Could not send a message to the aggregator");
                     }
                 }
             }
-            Err(err) => eprintln!("Error: {:?}", err),
+            Err(err) => log::warn!(error = err.to_string(); "Could not read the output of yt-dlp"),
         }
     }
+
+    path
+}
+
+/// Tell the user that the download is over, and open the file if they click the notification
+fn notify_finished(
+    runner: &dyn CommandRunner,
+    url: &str,
+    path: Option<&str>,
+    exit_code: i32,
+) -> anyhow::Result<()> {
+    if exit_code != 0 {
+        Notification::new("Download failed")
+            .body(format!("{} (exit code {})", url, exit_code))
+            .icon("dialog-error")
+            .urgency(Urgency::Critical)
+            .send();
+        return Ok(());
+    }
+
+    let Some(path) = path else {
+        Notification::new("Download finished")
+            .body(url)
+            .icon("folder-download")
+            .send();
+        return Ok(());
+    };
+
+    let title = std::path::Path::new(path)
+        .file_stem()
+        .map_or(path.into(), |stem| stem.to_string_lossy());
+    let action = Notification::new("Download finished")
+        .body(title)
+        .icon("folder-download")
+        .action("default", "Open")
+        .send_and_wait();
+
+    if action.is_some() {
+        runner.run(&Invocation::new("xdg-open").arg(path))?;
+    }
+    Ok(())
 }
 
 fn connect_to_aggregator(path: Option<&str>) -> anyhow::Result<UnixDatagram> {
@@ -170,7 +230,7 @@ fn connect_to_aggregator(path: Option<&str>) -> anyhow::Result<UnixDatagram> {
         None => UnixDatagram::unbound(),
     }?;
     socket
-        .connect(SYSTEM_ATLAS.ytdl_aggregator_socket)
+        .connect(&SYSTEM_ATLAS.ytdl_aggregator_socket)
         .context("Cannot connect to the aggregator process; is it running?")?;
     Ok(socket)
 }
@@ -184,10 +244,10 @@ fn download(
     let url = get_download_url(download_args, clipboard)?;
     let format = get_download_format(download_args, sh)?;
 
-    let (pid, stdout_lines, wait_handle) = downloader.download(url, &format)?;
+    let (pid, stdout_lines, wait_handle) = downloader.download(&url, &format)?;
 
     let stream = connect_to_aggregator(None)?;
-    process_lines(pid, &stream, stdout_lines);
+    let path = process_lines(pid, &stream, stdout_lines);
 
     let ecode = wait_handle.wait().expect("wait on child failed"); // TODO: return error instead of panic
     let ecode = ecode.code().unwrap_or(1);
@@ -198,10 +258,11 @@ fn download(
         payload: MessagePayload::ProcessExited(ecode),
     });
     let _ = send_message(&stream, &message);
-    Ok(())
+
+    notify_finished(sh, &url, path.as_deref(), ecode)
 }
 
-pub fn run(sh: &Shell, args: &ArgMatches) -> anyhow::Result<Option<String>> {
+pub fn run(sh: &Shell, args: &ArgMatches) -> anyhow::Result<Option<ScriptOutput>> {
     match args.subcommand() {
         Some(("download", download_args)) => {
             let wm = determine_wm();
@@ -213,11 +274,12 @@ pub fn run(sh: &Shell, args: &ArgMatches) -> anyhow::Result<Option<String>> {
         Some(("get_download_progress", _)) => {
             let message = Message::QueryMessage;
             let socket_path = generate_socket_path();
+            // A previous process with the same pid may have left its socket behind
+            let _ = std::fs::remove_file(&socket_path);
             let socket = connect_to_aggregator(Some(&socket_path))?;
-            let response = send_query_message(&socket, &message)?;
-            println!("Response:");
-            println!("{response}");
-            return Ok(Some(response));
+            let response = send_query_message(&socket, &message);
+            let _ = std::fs::remove_file(&socket_path);
+            return Ok(Some(ScriptOutput::new(&response?)?));
         }
         _ => {}
     }
@@ -226,9 +288,49 @@ pub fn run(sh: &Shell, args: &ArgMatches) -> anyhow::Result<Option<String>> {
 }
 
 fn generate_socket_path() -> String {
-    let secs_since_epoch = std::time::SystemTime::UNIX_EPOCH
-        .elapsed()
-        .expect("UNIX_EPOCH is later than current system time")
-        .as_secs();
-    format!("/tmp/plsdo-ytdl-download-process-{secs_since_epoch}")
+    std::env::temp_dir()
+        .join(format!(
+            "plsdo-ytdl-download-process-{}",
+            std::process::id()
+        ))
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::OutputFormat;
+
+    use super::*;
+
+    #[test]
+    fn download_quality_is_apart_from_the_output_format() {
+        let command = crate::get_command();
+        command.clone().debug_assert();
+
+        let matches = command
+            .try_get_matches_from([
+                "plsdo",
+                "--output-format",
+                "json",
+                "ytdl",
+                "download",
+                "--format",
+                "720p",
+                "url",
+                "x",
+            ])
+            .unwrap();
+        let (_, ytdl_args) = matches.subcommand().unwrap();
+        let (_, download_args) = ytdl_args.subcommand().unwrap();
+
+        assert!(matches!(
+            download_args.get_one::<DownloadFormat>("format"),
+            Some(DownloadFormat::UpTo720p)
+        ));
+        assert!(matches!(
+            download_args.get_one::<OutputFormat>("output-format"),
+            Some(OutputFormat::Json)
+        ));
+    }
 }
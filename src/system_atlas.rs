@@ -1,29 +1,266 @@
-/// Collection of system paths which we are interested about.
-/// NOTE: File::open does not expand '~', so it's safer to specify the full path!
-pub struct SystemAtlas<'a> {
-    pub alacritty: &'a str,
-    pub fontconfig: &'a str,
-    pub eww_brightness: &'a str,
-    pub eww_colortemp: &'a str,
-    pub eww_audio: &'a str,
-    pub eww_workspaces: &'a str,
-    pub keyboard_layout: &'a str,
-    pub ytdl_aggregator_socket: &'a str,
-    pub hypr_submap: &'a str,
-    pub main_dotfiles: &'a str,
-    pub canary_dotfiles: &'a str,
+use std::{path::PathBuf, sync::LazyLock};
+
+use serde::Deserialize;
+
+use crate::config::{self, expand_tilde};
+
+/// Collection of system paths which we are interested about. The paths are read from the `paths`
+/// section of the config file; a leading '~' is expanded, so the paths are safe to pass to
+/// `File::open`.
+#[derive(Debug)]
+// Some of the paths are only used by subcommands behind optional features
+#[cfg_attr(not(all(feature = "dbus", feature = "hyprland")), allow(dead_code))]
+pub struct SystemAtlas {
+    pub alacritty: String,
+    pub fontconfig: String,
+    pub eww_brightness: String,
+    pub eww_colortemp: String,
+    pub eww_audio: String,
+    pub eww_workspaces: String,
+    pub keyboard_layout: String,
+    pub ytdl_aggregator_socket: String,
+    pub state_bus_socket: String,
+    pub server_socket: String,
+    /// The pidfiles of the daemon and its components, which also keep a second instance from
+    /// starting
+    pub daemon_pidfile: String,
+    pub audio_listener_pidfile: String,
+    pub workspace_listener_pidfile: String,
+    pub ytdl_aggregator_pidfile: String,
+    pub server_pidfile: String,
+    /// The socket through which `plsdo daemon` is controlled
+    pub daemon_socket: String,
+    pub hypr_submap: String,
+    pub main_dotfiles: String,
+    pub canary_dotfiles: String,
+    pub dotfiles_id: String,
+    pub clipboard_history: String,
+    pub config_backups: String,
+    pub notification_ids: String,
+    /// The changes which `plsdo undo` can revert
+    pub undo_history: String,
+    /// How often and how recently the actions of `plsdo menu` were chosen
+    pub menu_frecency: String,
+    /// The keybinds which `plsdo keybinds generate` writes for Hyprland
+    pub hyprland_keybinds: String,
+    /// The keybinds which `plsdo keybinds generate` writes for sxhkd
+    pub sxhkd_keybinds: String,
+    /// The directory of the log files of the daemon and its components
+    pub logs: String,
+}
+
+pub static SYSTEM_ATLAS: LazyLock<&SystemAtlas> = LazyLock::new(|| &config::get().paths);
+
+/// The `paths` section of the config file, as it was written by the user.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct RawSystemAtlas {
+    alacritty: Option<String>,
+    fontconfig: Option<String>,
+    eww_brightness: Option<String>,
+    eww_colortemp: Option<String>,
+    eww_audio: Option<String>,
+    eww_workspaces: Option<String>,
+    keyboard_layout: Option<String>,
+    ytdl_aggregator_socket: Option<String>,
+    state_bus_socket: Option<String>,
+    server_socket: Option<String>,
+    daemon_pidfile: Option<String>,
+    audio_listener_pidfile: Option<String>,
+    workspace_listener_pidfile: Option<String>,
+    ytdl_aggregator_pidfile: Option<String>,
+    server_pidfile: Option<String>,
+    daemon_socket: Option<String>,
+    hypr_submap: Option<String>,
+    main_dotfiles: Option<String>,
+    canary_dotfiles: Option<String>,
+    dotfiles_id: Option<String>,
+    clipboard_history: Option<String>,
+    config_backups: Option<String>,
+    notification_ids: Option<String>,
+    undo_history: Option<String>,
+    menu_frecency: Option<String>,
+    hyprland_keybinds: Option<String>,
+    sxhkd_keybinds: Option<String>,
+    logs: Option<String>,
+}
+
+/// Use the configured path if there is one, otherwise fall back to the default.
+/// * `value`: the configured value
+/// * `key`: the name of the key in the `paths` section
+/// * `base`: the directory the default path is relative to, if it could be determined
+/// * `default`: the default path, relative to `base`
+fn resolve(
+    value: Option<String>,
+    key: &str,
+    base: Option<PathBuf>,
+    default: &str,
+) -> anyhow::Result<String> {
+    if let Some(value) = value {
+        return expand_tilde(&value);
+    }
+
+    let path = base.map(|base| base.join(default)).ok_or_else(|| {
+        anyhow::anyhow!("Could not determine a default for `paths.{key}`; please set it explicitly")
+    })?;
+
+    path.into_os_string()
+        .into_string()
+        .map_err(|_| anyhow::anyhow!("Default for `paths.{key}` is not valid UTF-8"))
 }
 
-pub const SYSTEM_ATLAS: SystemAtlas = SystemAtlas {
-    alacritty: "/home/rg/.config/alacritty/alacritty.yaml",
-    fontconfig: "/home/rg/.config/fontconfig/fonts.conf",
-    eww_brightness: "/home/rg/.local/share/eww-brightness",
-    eww_colortemp: "/home/rg/.local/share/eww-colortemp",
-    eww_audio: "/home/rg/.local/share/eww-audio",
-    eww_workspaces: "/home/rg/.local/share/eww-workspaces",
-    keyboard_layout: "/home/rg/.local/share/keyboard-layout",
-    ytdl_aggregator_socket: "/tmp/plsdo-ytdl-aggregator.sock",
-    hypr_submap: "/home/rg/.local/share/hypr-submap",
-    main_dotfiles: "/home/rg/.dotfiles",
-    canary_dotfiles: "/home/rg/.dotfiles__canary",
-};
+/// The directory for sockets and pidfiles: `$XDG_RUNTIME_DIR`, which only the user can write to,
+/// or the temporary directory if it's not set
+fn runtime_dir() -> Option<PathBuf> {
+    Some(dirs::runtime_dir().unwrap_or_else(std::env::temp_dir))
+}
+
+impl SystemAtlas {
+    pub fn resolve(raw: RawSystemAtlas) -> anyhow::Result<Self> {
+        let config = dirs::config_dir;
+        let data = dirs::data_dir;
+        let home = dirs::home_dir;
+        let state = dirs::state_dir;
+
+        Ok(Self {
+            alacritty: resolve(
+                raw.alacritty,
+                "alacritty",
+                config(),
+                "alacritty/alacritty.toml",
+            )?,
+            fontconfig: resolve(
+                raw.fontconfig,
+                "fontconfig",
+                config(),
+                "fontconfig/fonts.conf",
+            )?,
+            eww_brightness: resolve(
+                raw.eww_brightness,
+                "eww_brightness",
+                data(),
+                "eww-brightness",
+            )?,
+            eww_colortemp: resolve(raw.eww_colortemp, "eww_colortemp", data(), "eww-colortemp")?,
+            eww_audio: resolve(raw.eww_audio, "eww_audio", data(), "eww-audio")?,
+            eww_workspaces: resolve(
+                raw.eww_workspaces,
+                "eww_workspaces",
+                data(),
+                "eww-workspaces",
+            )?,
+            keyboard_layout: resolve(
+                raw.keyboard_layout,
+                "keyboard_layout",
+                data(),
+                "keyboard-layout",
+            )?,
+            ytdl_aggregator_socket: resolve(
+                raw.ytdl_aggregator_socket,
+                "ytdl_aggregator_socket",
+                runtime_dir(),
+                "plsdo-ytdl-aggregator.sock",
+            )?,
+            state_bus_socket: resolve(
+                raw.state_bus_socket,
+                "state_bus_socket",
+                runtime_dir(),
+                "plsdo-state-bus.sock",
+            )?,
+            server_socket: resolve(
+                raw.server_socket,
+                "server_socket",
+                runtime_dir(),
+                "plsdo-server.sock",
+            )?,
+            daemon_pidfile: resolve(
+                raw.daemon_pidfile,
+                "daemon_pidfile",
+                runtime_dir(),
+                "plsdo-daemon.pid",
+            )?,
+            audio_listener_pidfile: resolve(
+                raw.audio_listener_pidfile,
+                "audio_listener_pidfile",
+                runtime_dir(),
+                "plsdo-audio-device-listener.pid",
+            )?,
+            workspace_listener_pidfile: resolve(
+                raw.workspace_listener_pidfile,
+                "workspace_listener_pidfile",
+                runtime_dir(),
+                "plsdo-hypr-workspace-listener.pid",
+            )?,
+            ytdl_aggregator_pidfile: resolve(
+                raw.ytdl_aggregator_pidfile,
+                "ytdl_aggregator_pidfile",
+                runtime_dir(),
+                "plsdo-ytdl-aggregator.pid",
+            )?,
+            server_pidfile: resolve(
+                raw.server_pidfile,
+                "server_pidfile",
+                runtime_dir(),
+                "plsdo-server.pid",
+            )?,
+            daemon_socket: resolve(
+                raw.daemon_socket,
+                "daemon_socket",
+                runtime_dir(),
+                "plsdo-daemon.sock",
+            )?,
+            hypr_submap: resolve(raw.hypr_submap, "hypr_submap", data(), "hypr-submap")?,
+            main_dotfiles: resolve(raw.main_dotfiles, "main_dotfiles", home(), ".dotfiles")?,
+            canary_dotfiles: resolve(
+                raw.canary_dotfiles,
+                "canary_dotfiles",
+                home(),
+                ".dotfiles__canary",
+            )?,
+            dotfiles_id: resolve(raw.dotfiles_id, "dotfiles_id", config(), "dotfiles_id")?,
+            clipboard_history: resolve(
+                raw.clipboard_history,
+                "clipboard_history",
+                state(),
+                "plsdo/clipboard-history",
+            )?,
+            config_backups: resolve(
+                raw.config_backups,
+                "config_backups",
+                state(),
+                "plsdo/backups",
+            )?,
+            notification_ids: resolve(
+                raw.notification_ids,
+                "notification_ids",
+                runtime_dir(),
+                "plsdo-notification-ids",
+            )?,
+            undo_history: resolve(
+                raw.undo_history,
+                "undo_history",
+                state(),
+                "plsdo/undo-history.json",
+            )?,
+            menu_frecency: resolve(
+                raw.menu_frecency,
+                "menu_frecency",
+                state(),
+                "plsdo/menu-frecency.json",
+            )?,
+            hyprland_keybinds: resolve(
+                raw.hyprland_keybinds,
+                "hyprland_keybinds",
+                config(),
+                "hypr/plsdo-keybinds.conf",
+            )?,
+            sxhkd_keybinds: resolve(
+                raw.sxhkd_keybinds,
+                "sxhkd_keybinds",
+                config(),
+                "sxhkd/plsdo-keybinds",
+            )?,
+            logs: resolve(raw.logs, "logs", state(), "plsdo")?,
+        })
+    }
+}
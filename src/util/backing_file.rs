@@ -0,0 +1,197 @@
+//! Backing files are append-only files from which the eww widgets read the latest state, usually
+//! by following them with `tail -F`.
+//!
+//! To stop them from growing forever, a backing file is compacted once it grows too large: its
+//! last few entries are written to a new file, which then atomically replaces the old one. `tail
+//! -F` notices that the file was replaced, and continues by reading the new file from the start,
+//! so the readers end up with the latest entry either way.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt},
+    path::Path,
+};
+
+use anyhow::Context;
+use fd_lock::RwLock;
+use serde::Serialize;
+
+use super::get_temp_path;
+
+/// Size in bytes above which a backing file is compacted
+const MAX_SIZE: u64 = 64 * 1024;
+/// Number of entries which are kept when a backing file is compacted
+pub const KEPT_ENTRIES: usize = 16;
+
+fn compact(path: &Path, kept_entries: usize) -> anyhow::Result<()> {
+    let contents = fs::read_to_string(path)?;
+    let lines = contents.lines().collect::<Vec<_>>();
+    let kept_lines = &lines[lines.len().saturating_sub(kept_entries)..];
+
+    // The new file gets the permissions of the old one, which may be private, like those of the
+    // clipboard history
+    let permissions = fs::metadata(path)?.permissions();
+    let temp_path = get_temp_path(path)?;
+    let mut temp_file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(permissions.mode())
+        .open(&temp_path)?;
+    // The mode only applies if the file is created, not to a leftover of an interrupted compaction
+    temp_file.set_permissions(permissions)?;
+    for line in kept_lines {
+        writeln!(temp_file, "{}", line)?;
+    }
+    temp_file.sync_all()?;
+
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+fn append(path: &Path, line: &str, max_size: u64, kept_entries: usize) -> anyhow::Result<()> {
+    loop {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open backing file '{}'", path.display()))?;
+
+        // The lock is taken on the open file, not on the path. A writer which compacts the file
+        // replaces it while holding the lock, so a writer which was waiting for the lock may end
+        // up holding it on the old file; its line would be lost with that file, so it opens the
+        // new one and tries again.
+        let mut lock = RwLock::new(file);
+        let mut guard = lock.write()?;
+        let inode = guard.metadata()?.ino();
+        if fs::metadata(path).map(|metadata| metadata.ino()).ok() != Some(inode) {
+            continue;
+        }
+
+        guard.write_all(format!("{}\n", line).as_bytes())?;
+
+        if guard.metadata()?.len() > max_size {
+            compact(path, kept_entries)?;
+        }
+
+        return Ok(());
+    }
+}
+
+/// Append a line to the backing file, compacting it if it has grown too large. The file is created
+/// if it does not exist yet.
+pub fn append_line(path: &str, line: &str) -> anyhow::Result<()> {
+    append(Path::new(path), line, MAX_SIZE, KEPT_ENTRIES)
+}
+
+/// Append a value to the backing file as a line of JSON
+pub fn append_json(path: &str, value: &impl Serialize) -> anyhow::Result<()> {
+    append_line(path, &serde_json::to_string(value)?)
+}
+
+/// Read the latest entry of the backing file, if there is one
+pub fn read_last_line(path: &str) -> anyhow::Result<Option<String>> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read backing file '{}'", path))?;
+    Ok(contents.lines().last().map(|line| line.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn test_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "plsdo-test-backing-file-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn appending_creates_the_file() {
+        let path = test_file("create");
+        append(&path, "1", MAX_SIZE, KEPT_ENTRIES).unwrap();
+        append(&path, "2", MAX_SIZE, KEPT_ENTRIES).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "1\n2\n");
+        assert_eq!(
+            read_last_line(path.to_str().unwrap()).unwrap().as_deref(),
+            Some("2")
+        );
+    }
+
+    #[test]
+    fn large_file_is_compacted_to_last_entries() {
+        let path = test_file("compact");
+        for i in 0..10 {
+            append(&path, &i.to_string(), 8, 3).unwrap();
+        }
+
+        // the file is compacted to its last 3 entries whenever it exceeds 8 bytes, the last time
+        // after "8" was appended
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(contents, "6\n7\n8\n9\n");
+        assert!(!get_temp_path(&path).unwrap().exists());
+    }
+
+    #[test]
+    fn compaction_replaces_the_file() {
+        let path = test_file("replace");
+        append(&path, "old", MAX_SIZE, KEPT_ENTRIES).unwrap();
+        let file = fs::File::open(&path).unwrap();
+
+        compact(&path, 1).unwrap();
+
+        // readers which keep the old file open won't see the new one; `tail -F` reopens it
+        assert_ne!(
+            file.metadata().unwrap().ino(),
+            fs::metadata(&path).unwrap().ino()
+        );
+    }
+
+    #[test]
+    fn compaction_keeps_the_permissions() {
+        let path = test_file("permissions");
+        append(&path, "secret", MAX_SIZE, KEPT_ENTRIES).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+
+        compact(&path, 1).unwrap();
+
+        assert_eq!(fs::metadata(&path).unwrap().mode() & 0o777, 0o600);
+    }
+
+    #[test]
+    fn racing_writers_lose_no_lines_to_compaction() {
+        let path = test_file("race");
+
+        // Every line is kept, but the file is replaced by nearly every append
+        let writers = (0..4)
+            .map(|writer| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    for i in 0..50 {
+                        append(&path, &format!("{}-{}", writer, i), 16, usize::MAX).unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        writers
+            .into_iter()
+            .for_each(|writer| writer.join().unwrap());
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let mut lines = contents.lines().collect::<Vec<_>>();
+        lines.sort_unstable();
+        let mut expected = (0..4)
+            .flat_map(|writer| (0..50).map(move |i| format!("{}-{}", writer, i)))
+            .collect::<Vec<_>>();
+        expected.sort_unstable();
+        assert_eq!(lines, expected);
+    }
+}
@@ -0,0 +1,185 @@
+//! The state bus is a publish/subscribe socket hosted by the daemon. Whenever some piece of state
+//! (the audio volume, the active workspaces, ...) changes, its new value is published to the bus,
+//! which forwards it to every subscriber of that topic. Subscribers receive the latest value of
+//! the topic right after subscribing, so they don't need to wait for the next change.
+//!
+//! Values travel over the socket as JSON lines.
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, ErrorKind, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::Path,
+    time::Duration,
+};
+
+use anyhow::Context;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::system_atlas::SYSTEM_ATLAS;
+
+/// Clients which can't keep up are dropped, instead of stalling the whole bus
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Topic {
+    Audio,
+    Brightness,
+    Colortemp,
+    Keyboard,
+    Submap,
+    Workspace,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+enum Request {
+    Publish {
+        topic: Topic,
+        value: serde_json::Value,
+    },
+    Subscribe(Topic),
+}
+
+#[derive(Default)]
+struct Bus {
+    latest: HashMap<Topic, String>,
+    subscribers: HashMap<Topic, Vec<UnixStream>>,
+}
+
+fn send_request(stream: &mut UnixStream, request: &Request) -> anyhow::Result<()> {
+    serde_json::to_writer(&mut *stream, request)?;
+    stream.write_all(b"\n")?;
+    Ok(())
+}
+
+fn publish_to(path: &Path, topic: Topic, value: &impl Serialize) -> anyhow::Result<()> {
+    let mut stream = match UnixStream::connect(path) {
+        Ok(stream) => stream,
+        // Nobody is listening, so there's no one to tell about the change either
+        Err(err)
+            if matches!(
+                err.kind(),
+                ErrorKind::NotFound | ErrorKind::ConnectionRefused
+            ) =>
+        {
+            return Ok(())
+        }
+        Err(err) => return Err(err).context("Failed to connect to the state bus"),
+    };
+
+    let request = Request::Publish {
+        topic,
+        value: serde_json::to_value(value)?,
+    };
+    send_request(&mut stream, &request)
+}
+
+fn subscribe_to(
+    path: &Path,
+    topic: Topic,
+) -> anyhow::Result<impl Iterator<Item = std::io::Result<String>>> {
+    let mut stream = UnixStream::connect(path)
+        .context("Cannot connect to the state bus; is the daemon running?")?;
+    send_request(&mut stream, &Request::Subscribe(topic))?;
+    Ok(BufReader::new(stream).lines())
+}
+
+/// Publish the new value of the topic. Does nothing if the bus is not running.
+pub fn publish(topic: Topic, value: &impl Serialize) -> anyhow::Result<()> {
+    publish_to(Path::new(&SYSTEM_ATLAS.state_bus_socket), topic, value)
+}
+
+/// Subscribe to the topic. The returned iterator yields the latest value first, then every
+/// subsequent value as JSON, until the bus shuts down.
+pub fn subscribe(topic: Topic) -> anyhow::Result<impl Iterator<Item = std::io::Result<String>>> {
+    subscribe_to(Path::new(&SYSTEM_ATLAS.state_bus_socket), topic)
+}
+
+/// Connections are handled one after the other, so that values published in quick succession
+/// (e.g. while a volume key is held down) can't overtake each other.
+fn handle_connection(bus: &mut Bus, mut stream: UnixStream) -> anyhow::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let request = serde_json::from_str::<Request>(&line)?;
+
+    match request {
+        Request::Publish { topic, value } => {
+            let value = serde_json::to_string(&value)?;
+            if let Some(subscribers) = bus.subscribers.get_mut(&topic) {
+                subscribers.retain_mut(|subscriber| writeln!(subscriber, "{value}").is_ok());
+            }
+            bus.latest.insert(topic, value);
+        }
+        Request::Subscribe(topic) => {
+            if let Some(latest) = bus.latest.get(&topic) {
+                writeln!(stream, "{latest}")?;
+            }
+            bus.subscribers.entry(topic).or_default().push(stream);
+        }
+    }
+
+    Ok(())
+}
+
+fn serve_at(path: &Path) -> anyhow::Result<()> {
+    let _ = std::fs::remove_file(path);
+    let listener = UnixListener::bind(path)?;
+    let mut bus = Bus::default();
+
+    for stream in listener.incoming() {
+        if let Err(err) = handle_connection(&mut bus, stream?) {
+            log::warn!(error = format!("{err:#}"); "State bus failed to handle a connection");
+        }
+    }
+
+    Ok(())
+}
+
+/// Host the bus. Blocks for as long as the bus is running.
+pub fn serve() -> anyhow::Result<()> {
+    serve_at(Path::new(&SYSTEM_ATLAS.state_bus_socket))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn start_bus(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("plsdo-test-bus-{}-{}", name, std::process::id()));
+        let server_path = path.clone();
+        std::thread::spawn(move || serve_at(&server_path));
+
+        while UnixStream::connect(&path).is_err() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        path
+    }
+
+    #[test]
+    fn subscriber_receives_latest_and_subsequent_values() {
+        let path = start_bus("latest");
+        publish_to(&path, Topic::Brightness, &40).unwrap();
+        publish_to(&path, Topic::Brightness, &50).unwrap();
+
+        let mut values = subscribe_to(&path, Topic::Brightness).unwrap();
+        assert_eq!(values.next().unwrap().unwrap(), "50");
+
+        publish_to(&path, Topic::Colortemp, &6500).unwrap();
+        publish_to(&path, Topic::Brightness, &60).unwrap();
+        assert_eq!(values.next().unwrap().unwrap(), "60");
+    }
+
+    #[test]
+    fn publishing_without_a_bus_is_not_an_error() {
+        let path = std::env::temp_dir().join("plsdo-test-bus-does-not-exist");
+        publish_to(&path, Topic::Audio, &"value").unwrap();
+    }
+}
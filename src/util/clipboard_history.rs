@@ -0,0 +1,149 @@
+//! History of the clipboard contents which plsdo has seen, for systems where no clipboard manager
+//! keeps one. Entries are stored as JSON strings, one per line, so that they may span several
+//! lines themselves.
+
+use std::{
+    fs::{self, OpenOptions},
+    os::unix::fs::OpenOptionsExt,
+    path::Path,
+};
+
+use serde::Deserialize;
+
+use crate::system_atlas::SYSTEM_ATLAS;
+
+use super::backing_file;
+
+/// The `clipboard` section of the config file
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClipboardConfig {
+    /// Keep the history where no clipboard manager does. It's written to disk in plain text,
+    /// passwords included, so it's off unless asked for.
+    pub keep_history: bool,
+}
+
+/// The history is a backing file, so only its latest entries survive a compaction
+pub const MAX_ENTRIES: usize = backing_file::KEPT_ENTRIES;
+
+fn record_at(path: &Path, contents: &str) -> anyhow::Result<()> {
+    if contents.is_empty() || read_latest_at(path, 1)?.first().map(String::as_str) == Some(contents)
+    {
+        return Ok(());
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // Copied passwords end up in the history, so only the user may read it. Compaction keeps the
+    // permissions of the file.
+    OpenOptions::new()
+        .append(true)
+        .create(true)
+        .mode(0o600)
+        .open(path)?;
+
+    let path = path
+        .to_str()
+        .ok_or_else(|| anyhow::anyhow!("Clipboard history path is not valid UTF-8"))?;
+    backing_file::append_json(path, &contents)
+}
+
+fn read_latest_at(path: &Path, n: usize) -> anyhow::Result<Vec<String>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut entries = Vec::new();
+    for line in contents.lines().rev() {
+        let entry = serde_json::from_str::<String>(line)?;
+        if !entries.contains(&entry) {
+            entries.push(entry);
+        }
+        if entries.len() == n {
+            break;
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Add the contents to the history, unless they are empty or the same as the latest entry
+pub fn record(contents: &str) -> anyhow::Result<()> {
+    record_at(Path::new(&SYSTEM_ATLAS.clipboard_history), contents)
+}
+
+/// Fail if more entries are asked for than the history keeps
+pub fn check_size(n: usize) -> anyhow::Result<()> {
+    if n > MAX_ENTRIES {
+        anyhow::bail!(
+            "The clipboard history of plsdo keeps only {} entries, but {} were asked for",
+            MAX_ENTRIES,
+            n
+        );
+    }
+    Ok(())
+}
+
+/// Read the latest `n` distinct entries, newest first
+pub fn read_latest(n: usize) -> anyhow::Result<Vec<String>> {
+    check_size(n)?;
+    read_latest_at(Path::new(&SYSTEM_ATLAS.clipboard_history), n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latest_entries_are_distinct_and_newest_first() {
+        let dir = std::env::temp_dir().join(format!(
+            "plsdo-test-clipboard-history-{}",
+            std::process::id()
+        ));
+        let path = dir.join("history");
+        let _ = fs::remove_dir_all(&dir);
+
+        for contents in [
+            "a",
+            "b\nwith a second line",
+            "",
+            "b\nwith a second line",
+            "c",
+            "a",
+        ] {
+            record_at(&path, contents).unwrap();
+        }
+
+        assert_eq!(
+            read_latest_at(&path, 3).unwrap(),
+            ["a", "c", "b\nwith a second line"]
+        );
+        assert_eq!(read_latest_at(&path, 10).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn only_the_user_may_read_the_history() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = std::env::temp_dir().join(format!(
+            "plsdo-test-clipboard-history-mode-{}",
+            std::process::id()
+        ));
+        let path = dir.join("history");
+        let _ = fs::remove_dir_all(&dir);
+
+        record_at(&path, "hunter2").unwrap();
+
+        assert_eq!(fs::metadata(&path).unwrap().mode() & 0o777, 0o600);
+    }
+
+    #[test]
+    fn more_entries_than_are_kept_are_refused() {
+        assert!(check_size(MAX_ENTRIES).is_ok());
+        let err = check_size(MAX_ENTRIES + 1).unwrap_err();
+        assert!(err.to_string().contains("keeps only 16 entries"));
+    }
+}
@@ -0,0 +1,19 @@
+//! Shell completion of argument values which are only known at runtime, such as the connected
+//! audio outputs. The scripts printed by `plsdo completions` call back into plsdo with
+//! `COMPLETE=<shell>` set, and `main` hands those invocations over to clap_complete.
+
+use clap_complete::engine::{ArgValueCandidates, CompletionCandidate};
+use xshell::Shell;
+
+/// Complete an argument with the values returned by `values`. Failures are swallowed, since the
+/// shell has no good way of showing them; the argument is simply not completed.
+pub fn dynamic_values(
+    values: fn(&Shell) -> anyhow::Result<Vec<CompletionCandidate>>,
+) -> ArgValueCandidates {
+    ArgValueCandidates::new(move || {
+        crate::config::init()
+            .and_then(|_| Ok(Shell::new()?))
+            .and_then(|sh| values(&sh))
+            .unwrap_or_default()
+    })
+}
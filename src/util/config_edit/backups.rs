@@ -0,0 +1,142 @@
+//! Snapshots of config files, taken right before plsdo edits them, so that a bad edit can be
+//! rolled back. Every snapshot is a JSON file in the backups directory, named after its id.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// Older snapshots are deleted once there are more than this many
+const MAX_BACKUPS: usize = 50;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Backup {
+    pub id: u64,
+    /// The file which was snapshotted
+    pub path: String,
+    /// Unix timestamp (in seconds) of when the snapshot was taken
+    pub created: u64,
+    /// The plsdo command which made the edit
+    pub command: String,
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    contents: String,
+}
+
+fn backup_path(backups: &Path, id: u64) -> PathBuf {
+    backups.join(format!("{}.json", id))
+}
+
+fn read_backup(path: &Path) -> anyhow::Result<Backup> {
+    let contents = fs::read_to_string(path)?;
+    serde_json::from_str(&contents)
+        .with_context(|| format!("Invalid backup file {}", path.display()))
+}
+
+/// List the snapshots, newest first. Their contents are left out.
+pub fn list(backups: &Path) -> anyhow::Result<Vec<Backup>> {
+    let entries = match fs::read_dir(backups) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut all_backups = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            let mut backup = read_backup(&path)?;
+            backup.contents.clear();
+            all_backups.push(backup);
+        }
+    }
+
+    all_backups.sort_by_key(|backup| std::cmp::Reverse(backup.id));
+    Ok(all_backups)
+}
+
+/// Store the current contents of the file as a new snapshot, and return its id. `command` is the
+/// plsdo command line (without the executable) which is about to edit the file.
+pub fn snapshot(backups: &Path, path: &Path, contents: &str, command: &str) -> anyhow::Result<u64> {
+    fs::create_dir_all(backups)?;
+    let existing = list(backups)?;
+
+    let backup = Backup {
+        id: existing.first().map_or(1, |newest| newest.id + 1),
+        path: path.to_string_lossy().to_string(),
+        created: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        command: command.to_owned(),
+        contents: contents.to_owned(),
+    };
+    fs::write(
+        backup_path(backups, backup.id),
+        serde_json::to_string(&backup)?,
+    )?;
+
+    for old in existing.iter().skip(MAX_BACKUPS - 1) {
+        match fs::remove_file(backup_path(backups, old.id)) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+    }
+
+    Ok(backup.id)
+}
+
+/// Read the snapshot with the given id, including its contents
+pub fn get(backups: &Path, id: u64) -> anyhow::Result<(Backup, String)> {
+    let path = backup_path(backups, id);
+    if !path.exists() {
+        anyhow::bail!("There is no backup with id {}", id);
+    }
+
+    let mut backup = read_backup(&path)?;
+    let contents = std::mem::take(&mut backup.contents);
+    Ok((backup, contents))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_newest_snapshots_are_kept() {
+        let dir = std::env::temp_dir().join(format!("plsdo-test-backups-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let file = Path::new("/some/config.toml");
+
+        for i in 0..MAX_BACKUPS + 2 {
+            snapshot(&dir, file, &format!("size = {}", i), "font_size 12").unwrap();
+        }
+
+        let backups = list(&dir).unwrap();
+        assert_eq!(backups.len(), MAX_BACKUPS);
+        assert_eq!(backups[0].id, MAX_BACKUPS as u64 + 2);
+        assert_eq!(backups[0].path, "/some/config.toml");
+
+        let (_, contents) = get(&dir, 3).unwrap();
+        assert_eq!(contents, "size = 2");
+        assert!(get(&dir, 2).is_err());
+    }
+
+    #[test]
+    fn snapshots_record_the_given_command() {
+        let dir =
+            std::env::temp_dir().join(format!("plsdo-test-backups-command-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let id = snapshot(
+            &dir,
+            Path::new("/some/config.toml"),
+            "size = 11",
+            "font_size -d up 1",
+        )
+        .unwrap();
+
+        let (backup, _) = get(&dir, id).unwrap();
+        assert_eq!(backup.command, "font_size -d up 1");
+    }
+}
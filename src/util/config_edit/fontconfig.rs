@@ -0,0 +1,223 @@
+use std::ops::Range;
+
+use quick_xml::{escape::escape, events::Event, Reader};
+
+/// The `<prefer>` list of an `<alias>`
+struct PreferList {
+    /// The byte range between `<prefer>` and `</prefer>`
+    inner: Range<usize>,
+    /// The byte range of every `<family>` element, and its text
+    families: Vec<(Range<usize>, String)>,
+}
+
+/// A fontconfig file, such as `fonts.conf`. Edits only touch the elements which change, so the
+/// rest of the file is kept exactly as it was.
+pub struct FontconfigDocument {
+    contents: String,
+}
+
+impl FontconfigDocument {
+    pub fn parse(contents: &str) -> anyhow::Result<Self> {
+        // Make sure the whole file is well-formed up front, so that edits don't fail halfway
+        let mut reader = Reader::from_str(contents);
+        while !matches!(reader.read_event()?, Event::Eof) {}
+
+        Ok(Self {
+            contents: contents.to_owned(),
+        })
+    }
+
+    fn find_prefer_list(&self, family: &str) -> anyhow::Result<PreferList> {
+        let mut reader = Reader::from_str(&self.contents);
+        let mut path = Vec::<String>::new();
+
+        let mut alias_family = None::<String>;
+        let mut prefer_start = 0;
+        let mut prefer_list = None::<PreferList>;
+        let mut family_start = 0;
+        let mut text = String::new();
+
+        loop {
+            let event_start = reader.buffer_position() as usize;
+            let event = reader.read_event()?;
+            let event_end = reader.buffer_position() as usize;
+            let parent = path.last().map(String::as_str);
+
+            match event {
+                Event::Start(start) => {
+                    let name = String::from_utf8_lossy(start.name().as_ref()).to_string();
+                    match (parent, name.as_str()) {
+                        (Some("alias"), "prefer") => prefer_start = event_end,
+                        (_, "family") => family_start = event_start,
+                        _ => {}
+                    }
+                    text.clear();
+                    path.push(name);
+                }
+                Event::Text(t) => text.push_str(&t.unescape()?),
+                Event::End(_) => {
+                    let name = path.pop().unwrap_or_default();
+                    let parent = path.last().map(String::as_str);
+
+                    match (parent, name.as_str()) {
+                        (Some("alias"), "family") => alias_family = Some(text.trim().to_owned()),
+                        (Some("prefer"), "family") => {
+                            if let Some(list) = prefer_list.as_mut() {
+                                list.families
+                                    .push((family_start..event_end, text.trim().to_owned()));
+                            }
+                        }
+                        (Some("alias"), "prefer") => {
+                            if let Some(list) = prefer_list.as_mut() {
+                                list.inner = prefer_start..event_start;
+                            }
+                        }
+                        (_, "alias") => {
+                            if let Some(list) = prefer_list.take() {
+                                if alias_family.as_deref() == Some(family) {
+                                    return Ok(list);
+                                }
+                            }
+                            alias_family = None;
+                        }
+                        _ => {}
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+
+            // The list is created as soon as <prefer> opens, so that its families can be added
+            if path.last().map(String::as_str) == Some("prefer") && prefer_list.is_none() {
+                prefer_list = Some(PreferList {
+                    inner: prefer_start..prefer_start,
+                    families: Vec::new(),
+                });
+            }
+        }
+
+        anyhow::bail!("No <alias> with a <prefer> list found for '{}'", family)
+    }
+
+    /// The families which are preferred for the given (generic) family, e.g. "monospace"
+    pub fn preferred_families(&self, family: &str) -> anyhow::Result<Vec<String>> {
+        let list = self.find_prefer_list(family)?;
+        Ok(list.families.into_iter().map(|(_, name)| name).collect())
+    }
+
+    pub fn set_preferred_families(
+        &mut self,
+        family: &str,
+        families: &[String],
+    ) -> anyhow::Result<()> {
+        let list = self.find_prefer_list(family)?;
+
+        if list.families.len() == families.len() {
+            // Only the names change, so every <family> element stays where it is
+            for ((range, _), name) in list.families.iter().zip(families).rev() {
+                let element = format!("<family>{}</family>", escape(name.as_str()));
+                self.contents.replace_range(range.clone(), &element);
+            }
+            return Ok(());
+        }
+
+        let indentation_before = |position: usize| {
+            let line_start = self.contents[..position].rfind('\n').map_or(0, |i| i + 1);
+            self.contents[line_start..position].to_owned()
+        };
+        let closing_indentation = indentation_before(list.inner.end);
+        let indentation = match list.families.first() {
+            Some((range, _)) => indentation_before(range.start),
+            None => format!("{}  ", closing_indentation),
+        };
+
+        let mut inner = String::new();
+        for name in families {
+            inner.push_str(&format!(
+                "\n{}<family>{}</family>",
+                indentation,
+                escape(name.as_str())
+            ));
+        }
+        inner.push_str(&format!("\n{}", closing_indentation));
+
+        self.contents.replace_range(list.inner, &inner);
+        Ok(())
+    }
+
+    pub fn render(&self) -> String {
+        self.contents.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FONTS_CONF: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE fontconfig SYSTEM "fonts.dtd">
+<fontconfig>
+  <alias>
+    <family>sans-serif</family>
+    <prefer><family>Inter</family></prefer>
+  </alias>
+  <alias>
+    <family>monospace</family>
+    <prefer>
+      <family>Iosevka</family>
+      <!-- icons -->
+      <family>Symbols Nerd Font</family>
+      <family>Hack</family>
+    </prefer>
+  </alias>
+</fontconfig>
+"#;
+
+    #[test]
+    fn preferred_families_are_read_from_the_right_alias() {
+        let document = FontconfigDocument::parse(FONTS_CONF).unwrap();
+
+        assert_eq!(
+            document.preferred_families("monospace").unwrap(),
+            ["Iosevka", "Symbols Nerd Font", "Hack"]
+        );
+        assert_eq!(
+            document.preferred_families("sans-serif").unwrap(),
+            ["Inter"]
+        );
+        assert!(document.preferred_families("serif").is_err());
+    }
+
+    #[test]
+    fn reordering_keeps_the_layout() {
+        let mut document = FontconfigDocument::parse(FONTS_CONF).unwrap();
+        let families = ["Hack", "Iosevka", "Symbols Nerd Font"].map(String::from);
+        document
+            .set_preferred_families("monospace", &families)
+            .unwrap();
+
+        let expected = FONTS_CONF
+            .replace(
+                "<family>Iosevka</family>\n      <!--",
+                "<family>Hack</family>\n      <!--",
+            )
+            .replace(
+                "<family>Symbols Nerd Font</family>\n      <family>Hack</family>",
+                "<family>Iosevka</family>\n      <family>Symbols Nerd Font</family>",
+            );
+        assert_eq!(document.render(), expected);
+    }
+
+    #[test]
+    fn changed_list_is_rebuilt_with_the_same_indentation() {
+        let mut document = FontconfigDocument::parse(FONTS_CONF).unwrap();
+        let families = ["Hack & Co", "Iosevka"].map(String::from);
+        document
+            .set_preferred_families("monospace", &families)
+            .unwrap();
+
+        assert!(document.render().contains(
+            "    <prefer>\n      <family>Hack &amp; Co</family>\n      <family>Iosevka</family>\n    </prefer>\n"
+        ));
+    }
+}
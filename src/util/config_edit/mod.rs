@@ -0,0 +1,342 @@
+//! Editing of config files which belong to other programs, e.g. the font size in the alacritty
+//! config.
+//!
+//! Files are parsed according to their format, so that edits don't depend on the exact layout of
+//! the file, and everything which is not edited (comments, whitespace, ordering) is written back
+//! as it was. TOML and YAML files are edited through key paths such as `font.size`; fontconfig
+//! files have their own, more specific operations (see `FontconfigDocument`).
+//!
+//! Edited files are written to a temporary file first, which then replaces the original, so a
+//! failed edit never leaves a half-written config behind. Before that, the original is
+//! snapshotted (see `backups`), so that the edit can be rolled back.
+
+use std::{
+    cell::RefCell,
+    fmt::Display,
+    fs::{self, OpenOptions},
+    path::Path,
+};
+
+use anyhow::Context;
+use fd_lock::RwLock;
+
+use crate::system_atlas::SYSTEM_ATLAS;
+
+use super::get_temp_path;
+
+pub mod backups;
+mod fontconfig;
+mod toml_document;
+mod yaml_document;
+
+pub use fontconfig::FontconfigDocument;
+use toml_document::TomlDocument;
+use yaml_document::YamlDocument;
+
+/// A scalar value in a config file
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Bool(bool),
+}
+
+impl Value {
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Integer(i) => Some(*i as f64),
+            Self::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Integer(i) => write!(f, "{}", i),
+            // Debug formatting keeps the decimal point of whole numbers, so they stay floats
+            Self::Float(x) => write!(f, "{:?}", x),
+            Self::String(s) => write!(f, "{}", s),
+            Self::Bool(b) => write!(f, "{}", b),
+        }
+    }
+}
+
+/// A parsed config file whose values can be addressed with key paths
+pub trait Document {
+    /// Get the scalar value at the key path, if there is one
+    fn get(&self, key_path: &str) -> anyhow::Result<Option<Value>>;
+
+    /// Set the value at the key path, creating the tables leading up to it if necessary
+    fn set(&mut self, key_path: &str, value: Value) -> anyhow::Result<()>;
+
+    fn render(&self) -> String;
+}
+
+fn split_key_path(key_path: &str) -> anyhow::Result<Vec<&str>> {
+    let keys = key_path.split('.').collect::<Vec<_>>();
+    if keys.iter().any(|key| key.is_empty()) {
+        anyhow::bail!("Invalid key path '{}'", key_path);
+    }
+    Ok(keys)
+}
+
+fn parse_document(path: &Path, contents: &str) -> anyhow::Result<Box<dyn Document>> {
+    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+
+    let document: Box<dyn Document> = match extension {
+        "toml" => Box::new(TomlDocument::parse(contents)?),
+        "yaml" | "yml" => Box::new(YamlDocument::parse(contents)),
+        _ => anyhow::bail!(
+            "Don't know how to edit '{}'; expected a .toml or .yaml file",
+            path.display()
+        ),
+    };
+
+    Ok(document)
+}
+
+thread_local! {
+    /// The command line of the command which the thread is running, see `for_command`
+    static COMMAND: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+/// Run the command, recording `command_line` (without the executable) as the command which made
+/// the edits it makes. The server runs many commands, each in a thread of its own, so the command
+/// line of the process can't be used.
+pub fn for_command<R>(command_line: &str, run: impl FnOnce() -> R) -> R {
+    let outer = COMMAND.with(|command| command.replace(command_line.to_owned()));
+    let result = run();
+    COMMAND.with(|command| command.replace(outer));
+    result
+}
+
+fn get_backups_dir() -> &'static Path {
+    Path::new(&SYSTEM_ATLAS.config_backups)
+}
+
+/// Replace the contents of the file atomically
+fn write_atomically(path: &Path, contents: &str) -> anyhow::Result<()> {
+    let temp_path = get_temp_path(path)?;
+
+    fs::write(&temp_path, contents)?;
+    fs::set_permissions(&temp_path, fs::metadata(path)?.permissions())?;
+    fs::rename(&temp_path, path)?;
+
+    Ok(())
+}
+
+/// Read the file, let `modify` change its contents, and write the result back, after taking a
+/// snapshot. Symlinks (e.g. into a dotfiles repository) are followed, so that the file they point
+/// to is replaced instead of the link.
+///
+/// A lock file in the backups directory is held in the meantime, so that edits which run at the
+/// same time (e.g. in the threads of the server) don't lose each other's changes or snapshots.
+fn rewrite<R>(
+    backups_dir: &Path,
+    path: &str,
+    modify: impl FnOnce(&Path, &str) -> anyhow::Result<(String, R)>,
+) -> anyhow::Result<R> {
+    fs::create_dir_all(backups_dir)?;
+    let lock_path = backups_dir.join(".lock");
+    let lock_file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)
+        .with_context(|| format!("Failed to open {}", lock_path.display()))?;
+    let mut lock = RwLock::new(lock_file);
+    let _guard = lock.write()?;
+
+    let path = fs::canonicalize(path).with_context(|| format!("Failed to read {}", path))?;
+    let contents =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let (new_contents, result) =
+        modify(&path, &contents).with_context(|| format!("Failed to edit {}", path.display()))?;
+
+    if new_contents != contents {
+        let command = COMMAND.with(|command| command.borrow().clone());
+        backups::snapshot(backups_dir, &path, &contents, &command)
+            .with_context(|| format!("Failed to back up {}", path.display()))?;
+        write_atomically(&path, &new_contents)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+
+    Ok(result)
+}
+
+fn edit_with_backups_in<R>(
+    backups_dir: &Path,
+    path: &str,
+    edit: impl FnOnce(&mut dyn Document) -> anyhow::Result<R>,
+) -> anyhow::Result<R> {
+    rewrite(backups_dir, path, |path, contents| {
+        let mut document = parse_document(path, contents)?;
+        let result = edit(document.as_mut())?;
+        Ok((document.render(), result))
+    })
+}
+
+/// Edit a TOML or YAML file through key paths. The format is determined by the file extension.
+pub fn edit<R>(
+    path: &str,
+    edit: impl FnOnce(&mut dyn Document) -> anyhow::Result<R>,
+) -> anyhow::Result<R> {
+    edit_with_backups_in(get_backups_dir(), path, edit)
+}
+
+/// Read values from a TOML or YAML file through key paths, without editing it
+pub fn read<R>(
+    path: &str,
+    read: impl FnOnce(&dyn Document) -> anyhow::Result<R>,
+) -> anyhow::Result<R> {
+    let contents = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+    let document = parse_document(Path::new(path), &contents)?;
+    read(document.as_ref())
+}
+
+/// Edit a fontconfig file
+pub fn edit_fontconfig<R>(
+    path: &str,
+    edit: impl FnOnce(&mut FontconfigDocument) -> anyhow::Result<R>,
+) -> anyhow::Result<R> {
+    rewrite(get_backups_dir(), path, |_, contents| {
+        let mut document = FontconfigDocument::parse(contents)?;
+        let result = edit(&mut document)?;
+        Ok((document.render(), result))
+    })
+}
+
+fn restore_from(backups_dir: &Path, id: u64) -> anyhow::Result<backups::Backup> {
+    let (backup, contents) = backups::get(backups_dir, id)?;
+    // The current contents are snapshotted as well, so that the restore can be undone too
+    rewrite(backups_dir, &backup.path, |_, _| Ok((contents, ())))?;
+    Ok(backup)
+}
+
+/// Put the contents of a snapshot back into the file it was taken of
+pub fn restore(id: u64) -> anyhow::Result<backups::Backup> {
+    restore_from(get_backups_dir(), id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edits_are_written_through_symlinks() {
+        let dir =
+            std::env::temp_dir().join(format!("plsdo-test-config-edit-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("alacritty.toml");
+        let link = dir.join("link.toml");
+        fs::write(&target, "[font]\nsize = 11 # small\n").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        let backups_dir = dir.join("backups");
+        edit_with_backups_in(&backups_dir, link.to_str().unwrap(), |document| {
+            document.set("font.size", Value::Integer(12))
+        })
+        .unwrap();
+
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(
+            fs::read_to_string(&target).unwrap(),
+            "[font]\nsize = 12 # small\n"
+        );
+    }
+
+    #[test]
+    fn edits_can_be_restored() {
+        let dir =
+            std::env::temp_dir().join(format!("plsdo-test-config-restore-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("alacritty.yaml");
+        let backups_dir = dir.join("backups");
+        fs::write(&file, "font:\n  size: 11\n").unwrap();
+
+        let edit_size = |size| {
+            edit_with_backups_in(&backups_dir, file.to_str().unwrap(), |document| {
+                document.set("font.size", Value::Integer(size))
+            })
+            .unwrap()
+        };
+        edit_size(12);
+        edit_size(12);
+        edit_size(13);
+
+        // editing to the same value doesn't take a snapshot
+        let all_backups = backups::list(&backups_dir).unwrap();
+        assert_eq!(all_backups.iter().map(|b| b.id).collect::<Vec<_>>(), [2, 1]);
+
+        restore_from(&backups_dir, 1).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "font:\n  size: 11\n");
+
+        restore_from(&backups_dir, 3).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "font:\n  size: 13\n");
+    }
+
+    #[test]
+    fn edits_at_the_same_time_are_not_lost() {
+        let dir =
+            std::env::temp_dir().join(format!("plsdo-test-config-parallel-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("alacritty.toml");
+        let backups_dir = dir.join("backups");
+        fs::write(&file, "[font]\nsize = 10\n").unwrap();
+
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    edit_with_backups_in(&backups_dir, file.to_str().unwrap(), |document| {
+                        let Some(Value::Integer(size)) = document.get("font.size")? else {
+                            anyhow::bail!("no font size");
+                        };
+                        document.set("font.size", Value::Integer(size + 1))
+                    })
+                    .unwrap();
+                });
+            }
+        });
+
+        assert_eq!(fs::read_to_string(&file).unwrap(), "[font]\nsize = 18\n");
+        assert_eq!(backups::list(&backups_dir).unwrap().len(), 8);
+    }
+
+    #[test]
+    fn edits_record_the_command_which_made_them() {
+        let dir =
+            std::env::temp_dir().join(format!("plsdo-test-config-command-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("alacritty.toml");
+        let backups_dir = dir.join("backups");
+        fs::write(&file, "[font]\nsize = 11\n").unwrap();
+
+        for_command("font_size 12", || {
+            edit_with_backups_in(&backups_dir, file.to_str().unwrap(), |document| {
+                document.set("font.size", Value::Integer(12))
+            })
+        })
+        .unwrap();
+
+        let all_backups = backups::list(&backups_dir).unwrap();
+        assert_eq!(all_backups[0].command, "font_size 12");
+    }
+}
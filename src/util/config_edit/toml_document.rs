@@ -0,0 +1,125 @@
+use toml_edit::{Document as TomlEditDocument, Item, Table};
+
+use super::{split_key_path, Document, Value};
+
+pub struct TomlDocument {
+    document: TomlEditDocument,
+}
+
+impl TomlDocument {
+    pub fn parse(contents: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            document: contents.parse()?,
+        })
+    }
+}
+
+/// Turn a missing item into a table, which will hold `key`
+fn ensure_table(item: &mut Item, key: &str, key_path: &str) -> anyhow::Result<()> {
+    if item.is_none() {
+        // A table of its own, with a `[header]`, rather than an inline one in its parent
+        let mut table = Table::new();
+        table.set_implicit(true);
+        *item = Item::Table(table);
+    } else if !item.is_table_like() {
+        anyhow::bail!("'{}' in '{}' is not a table", key, key_path);
+    }
+    Ok(())
+}
+
+impl Document for TomlDocument {
+    fn get(&self, key_path: &str) -> anyhow::Result<Option<Value>> {
+        let mut item = self.document.as_item();
+        for key in split_key_path(key_path)? {
+            match item.get(key) {
+                Some(child) => item = child,
+                None => return Ok(None),
+            }
+        }
+
+        let value = match item.as_value() {
+            Some(toml_edit::Value::Integer(i)) => Value::Integer(*i.value()),
+            Some(toml_edit::Value::Float(f)) => Value::Float(*f.value()),
+            Some(toml_edit::Value::String(s)) => Value::String(s.value().clone()),
+            Some(toml_edit::Value::Boolean(b)) => Value::Bool(*b.value()),
+            _ => anyhow::bail!("'{}' is not a scalar value", key_path),
+        };
+        Ok(Some(value))
+    }
+
+    fn set(&mut self, key_path: &str, value: Value) -> anyhow::Result<()> {
+        let keys = split_key_path(key_path)?;
+        let (last, parents) = keys.split_last().expect("key path is not empty");
+
+        let mut item = self.document.as_item_mut();
+        for key in parents {
+            ensure_table(item, key, key_path)?;
+            item = &mut item[key];
+        }
+        ensure_table(item, last, key_path)?;
+
+        let mut new_value = match value {
+            Value::Integer(i) => toml_edit::Value::from(i),
+            Value::Float(f) => toml_edit::Value::from(f),
+            Value::String(s) => toml_edit::Value::from(s),
+            Value::Bool(b) => toml_edit::Value::from(b),
+        };
+
+        // Keep the whitespace and comments around the old value
+        if let Some(old_value) = item.get(last).and_then(Item::as_value) {
+            *new_value.decor_mut() = old_value.decor().clone();
+        }
+
+        item[last] = Item::Value(new_value);
+        Ok(())
+    }
+
+    fn render(&self) -> String {
+        self.document.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formatting_is_preserved() {
+        let contents =
+            "# My config\n[font]\nsize   = 11.5  # points\n\n[font.normal]\nfamily = \"Iosevka\"\n";
+        let mut document = TomlDocument::parse(contents).unwrap();
+
+        assert_eq!(document.get("font.size").unwrap(), Some(Value::Float(11.5)));
+        document.set("font.size", Value::Float(12.5)).unwrap();
+        document
+            .set("font.normal.family", Value::String("Hack".to_owned()))
+            .unwrap();
+
+        assert_eq!(
+            document.render(),
+            "# My config\n[font]\nsize   = 12.5  # points\n\n[font.normal]\nfamily = \"Hack\"\n"
+        );
+    }
+
+    #[test]
+    fn missing_tables_are_created() {
+        let mut document = TomlDocument::parse("").unwrap();
+        document.set("font.size", Value::Integer(12)).unwrap();
+
+        assert_eq!(document.get("font.size").unwrap(), Some(Value::Integer(12)));
+        assert_eq!(document.get("font.offset").unwrap(), None);
+    }
+
+    #[test]
+    fn new_tables_get_a_header() {
+        let mut document = TomlDocument::parse("[devices]\ntv = \"TV\"\n").unwrap();
+        document
+            .set("scenes.movie.brightness", Value::Integer(100))
+            .unwrap();
+
+        assert_eq!(
+            document.render(),
+            "[devices]\ntv = \"TV\"\n\n[scenes.movie]\nbrightness = 100\n"
+        );
+    }
+}
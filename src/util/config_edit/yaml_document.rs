@@ -0,0 +1,328 @@
+//! A minimal YAML editor, which understands just enough of the format to edit scalar values in
+//! block mappings, such as the ones of the old alacritty config. Lines which aren't edited are
+//! kept exactly as they were.
+
+use super::{split_key_path, Document, Value};
+
+pub struct YamlDocument {
+    lines: Vec<String>,
+}
+
+/// Where a key path was found, or where it should be inserted
+enum Location {
+    Found {
+        line: usize,
+    },
+    Missing {
+        /// Index of the first key which could not be found
+        missing_from: usize,
+        insert_at: usize,
+        indentation: usize,
+    },
+}
+
+/// The indentation of the line, or `None` if the line has no content
+fn indentation(line: &str) -> Option<usize> {
+    let trimmed = line.trim_start();
+    if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with("---") {
+        return None;
+    }
+    Some(line.len() - trimmed.len())
+}
+
+/// Split a `key: value # comment` line into the key, the byte offset at which the value starts,
+/// and the byte offset at which the comment starts
+fn split_line(line: &str) -> Option<(&str, usize, usize)> {
+    let indent = indentation(line)?;
+    let content = &line[indent..];
+
+    let colon = content
+        .match_indices(':')
+        .map(|(i, _)| i)
+        .find(|&i| content[i + 1..].is_empty() || content[i + 1..].starts_with([' ', '\t']))?;
+    let key = content[..colon]
+        .trim()
+        .trim_matches(|c| c == '"' || c == '\'');
+
+    let value_start = indent + colon + 1;
+    let mut in_quotes = None;
+    let mut comment_start = line.len();
+    let mut previous = ' ';
+    for (i, c) in line[value_start..].char_indices() {
+        match (in_quotes, c) {
+            (None, '"' | '\'') => in_quotes = Some(c),
+            (Some(quote), _) if c == quote => in_quotes = None,
+            (None, '#') if previous.is_whitespace() => {
+                comment_start = value_start + i;
+                break;
+            }
+            _ => {}
+        }
+        previous = c;
+    }
+
+    Some((key, value_start, comment_start))
+}
+
+fn parse_scalar(text: &str) -> Value {
+    if text.starts_with('"') {
+        if let Ok(s) = serde_json::from_str::<String>(text) {
+            return Value::String(s);
+        }
+    }
+    if let Some(s) = text
+        .strip_prefix('\'')
+        .and_then(|text| text.strip_suffix('\''))
+    {
+        return Value::String(s.replace("''", "'"));
+    }
+
+    if let Ok(i) = text.parse::<i64>() {
+        return Value::Integer(i);
+    }
+    // Rust also accepts e.g. "inf", which YAML would read as a string
+    let looks_numeric = text.chars().any(|c| c.is_ascii_digit())
+        && text
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-'));
+    if let Some(f) = text.parse::<f64>().ok().filter(|_| looks_numeric) {
+        return Value::Float(f);
+    }
+
+    match text {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ => Value::String(text.to_owned()),
+    }
+}
+
+fn render_scalar(value: &Value) -> String {
+    match value {
+        Value::String(s) => {
+            let is_plain = !s.is_empty()
+                && s.trim() == s
+                && matches!(parse_scalar(s), Value::String(_))
+                && !s.starts_with(|c| "-?:,[]{}#&*!|>'\"%@`~".contains(c))
+                && !s.contains(": ")
+                && !s.contains(" #")
+                && s != "null";
+
+            if is_plain {
+                s.clone()
+            } else {
+                // JSON strings are valid double-quoted YAML strings
+                serde_json::to_string(s).expect("strings can be serialized")
+            }
+        }
+        value => value.to_string(),
+    }
+}
+
+impl YamlDocument {
+    pub fn parse(contents: &str) -> Self {
+        Self {
+            lines: contents.split('\n').map(|line| line.to_owned()).collect(),
+        }
+    }
+
+    /// The index after the last line with content in the block which starts at `start`, and
+    /// whose lines are indented more than `parent_indentation`
+    fn block_end(&self, start: usize, parent_indentation: Option<usize>) -> usize {
+        let mut end = start;
+        for (i, line) in self.lines.iter().enumerate().skip(start) {
+            match indentation(line) {
+                Some(indent) if parent_indentation.is_some_and(|parent| indent <= parent) => break,
+                Some(_) => end = i + 1,
+                None => {}
+            }
+        }
+        end
+    }
+
+    fn locate(&self, keys: &[&str]) -> Location {
+        let mut start = 0;
+        let mut parent_indentation = None;
+
+        for (depth, key) in keys.iter().enumerate() {
+            let end = self.block_end(start, parent_indentation);
+            let child_indentation = self.lines[start..end]
+                .iter()
+                .find_map(|line| indentation(line))
+                .unwrap_or_else(|| parent_indentation.map_or(0, |parent| parent + 2));
+
+            let found = (start..end).find(|&i| {
+                indentation(&self.lines[i]) == Some(child_indentation)
+                    && split_line(&self.lines[i]).is_some_and(|(k, _, _)| k == *key)
+            });
+
+            match found {
+                Some(i) => {
+                    start = i + 1;
+                    parent_indentation = Some(child_indentation);
+                    if depth == keys.len() - 1 {
+                        return Location::Found { line: i };
+                    }
+                }
+                None => {
+                    return Location::Missing {
+                        missing_from: depth,
+                        insert_at: end,
+                        indentation: child_indentation,
+                    }
+                }
+            }
+        }
+
+        unreachable!("key paths are not empty")
+    }
+
+    /// Make sure that the line of an intermediate key opens a block mapping
+    fn check_is_mapping(&self, line: usize, key: &str) -> anyhow::Result<()> {
+        let (_, value_start, comment_start) =
+            split_line(&self.lines[line]).expect("located lines are key lines");
+        if !self.lines[line][value_start..comment_start]
+            .trim()
+            .is_empty()
+        {
+            anyhow::bail!("'{}' is not a block mapping", key);
+        }
+        Ok(())
+    }
+}
+
+impl Document for YamlDocument {
+    fn get(&self, key_path: &str) -> anyhow::Result<Option<Value>> {
+        match self.locate(&split_key_path(key_path)?) {
+            Location::Found { line } => {
+                let line = &self.lines[line];
+                let (_, value_start, comment_start) =
+                    split_line(line).expect("located lines are key lines");
+                let text = line[value_start..comment_start].trim();
+
+                if text.is_empty() {
+                    anyhow::bail!("'{}' is not a scalar value", key_path);
+                }
+                Ok(Some(parse_scalar(text)))
+            }
+            Location::Missing { .. } => Ok(None),
+        }
+    }
+
+    fn set(&mut self, key_path: &str, value: Value) -> anyhow::Result<()> {
+        let keys = split_key_path(key_path)?;
+        let rendered = render_scalar(&value);
+
+        // Every key but the last has to be a mapping
+        for depth in 0..keys.len() - 1 {
+            if let Location::Found { line } = self.locate(&keys[..=depth]) {
+                self.check_is_mapping(line, keys[depth])?;
+            }
+        }
+
+        match self.locate(&keys) {
+            Location::Found { line } => {
+                let old_line = &self.lines[line];
+                let (_, value_start, comment_start) =
+                    split_line(old_line).expect("located lines are key lines");
+
+                // Keep the whitespace between the value and the comment
+                let old_value = &old_line[value_start..comment_start];
+                let padding = &old_value[old_value.trim_end().len()..];
+                let comment = &old_line[comment_start..];
+
+                self.lines[line] = format!(
+                    "{} {}{}{}",
+                    &old_line[..value_start],
+                    rendered,
+                    if comment.is_empty() { "" } else { padding },
+                    comment
+                );
+            }
+            Location::Missing {
+                missing_from,
+                insert_at,
+                indentation,
+            } => {
+                let new_lines = keys[missing_from..].iter().enumerate().map(|(i, key)| {
+                    let indent = " ".repeat(indentation + 2 * i);
+                    if missing_from + i == keys.len() - 1 {
+                        format!("{}{}: {}", indent, key, rendered)
+                    } else {
+                        format!("{}{}:", indent, key)
+                    }
+                });
+                self.lines.splice(insert_at..insert_at, new_lines);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn render(&self) -> String {
+        self.lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "\
+env:
+  TERM: xterm-256color
+
+font:
+  normal:
+    family: Iosevka   # the best one
+    style: Regular
+
+  # Point size
+  size: 11.0
+colors:
+  primary: '#1d1f21'
+";
+
+    #[test]
+    fn values_are_read_by_key_path() {
+        let document = YamlDocument::parse(CONFIG);
+
+        assert_eq!(document.get("font.size").unwrap(), Some(Value::Float(11.0)));
+        assert_eq!(
+            document.get("font.normal.family").unwrap(),
+            Some(Value::String("Iosevka".to_owned()))
+        );
+        assert_eq!(
+            document.get("colors.primary").unwrap(),
+            Some(Value::String("#1d1f21".to_owned()))
+        );
+        assert_eq!(document.get("font.offset").unwrap(), None);
+        assert!(document.get("font.normal").is_err());
+    }
+
+    #[test]
+    fn only_the_edited_line_changes() {
+        let mut document = YamlDocument::parse(CONFIG);
+        document.set("font.size", Value::Float(12.0)).unwrap();
+        document
+            .set("font.normal.family", Value::String("Hack Nerd".to_owned()))
+            .unwrap();
+
+        let expected = CONFIG
+            .replace("size: 11.0", "size: 12.0")
+            .replace("family: Iosevka   #", "family: Hack Nerd   #");
+        assert_eq!(document.render(), expected);
+    }
+
+    #[test]
+    fn missing_keys_are_inserted_into_their_block() {
+        let mut document = YamlDocument::parse("font:\n    size: 11\nwindow:\n    opacity: 0.9\n");
+        document
+            .set("font.bold.family", Value::String("x: y".to_owned()))
+            .unwrap();
+
+        assert_eq!(
+            document.render(),
+            "font:\n    size: 11\n    bold:\n      family: \"x: y\"\nwindow:\n    opacity: 0.9\n"
+        );
+    }
+}
@@ -0,0 +1,364 @@
+//! The programs which can show a menu. Each backend maps the features of `Dmenu` onto the flags of
+//! its program, as far as the program supports them; the remaining validation is done by `Dmenu`
+//! itself.
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::util::runner::{CommandRunner, Invocation};
+
+/// What the backend is asked to show
+pub struct MenuRequest<'a> {
+    pub prompt: &'a str,
+    pub choices_len: usize,
+    /// Choices are prefixed with `_<number>: `, so the menu should start out filtering on `_`,
+    /// which lets the user pick a choice by typing its number
+    pub numbered: bool,
+    /// Pick the choice as soon as it is the only one left
+    pub auto_select: bool,
+    pub allow_invalid: bool,
+    /// Let the user pick several choices, which are printed on separate lines
+    pub multi_select: bool,
+}
+
+pub trait MenuBackend: Sync {
+    fn program(&self) -> &'static str;
+
+    /// Arguments which tune the looks of the menu. They are replaced by `menu.args` from the
+    /// config, if it's set.
+    fn default_args(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn args(&self, request: &MenuRequest) -> Vec<String>;
+
+    fn supports_multi_select(&self) -> bool {
+        false
+    }
+
+    /// Show the menu, and return the line that the user picked or typed, or `None` if the user
+    /// closed the menu instead
+    fn run(
+        &self,
+        runner: &dyn CommandRunner,
+        request: &MenuRequest,
+        input: &str,
+        extra_args: &[String],
+    ) -> anyhow::Result<Option<String>> {
+        let invocation = Invocation::new(self.program())
+            .args(self.args(request))
+            .args(extra_args)
+            .stdin(input);
+        let output = runner.output(&invocation)?;
+
+        // All of the menu programs exit with a non-zero code when they're closed
+        if output.code != Some(0) {
+            return Ok(None);
+        }
+        let stdout = String::from_utf8(output.stdout)
+            .with_context(|| format!("`{}` printed invalid UTF-8", invocation))?;
+        Ok(Some(stdout.trim_end_matches(['\n', '\r']).to_owned()))
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MenuBackendKind {
+    Dmenu,
+    Bemenu,
+    Fuzzel,
+    Rofi,
+    Wofi,
+    Fzf,
+}
+
+impl MenuBackendKind {
+    pub fn backend(self) -> &'static dyn MenuBackend {
+        match self {
+            Self::Dmenu => &DmenuBackend,
+            Self::Bemenu => &BemenuBackend,
+            Self::Fuzzel => &FuzzelBackend,
+            Self::Rofi => &RofiBackend,
+            Self::Wofi => &WofiBackend,
+            Self::Fzf => &FzfBackend,
+        }
+    }
+}
+
+fn strings(args: &[&str]) -> Vec<String> {
+    args.iter().map(|&arg| arg.to_owned()).collect()
+}
+
+pub struct DmenuBackend;
+
+// dmenu supports neither filtering up front, nor auto select
+impl MenuBackend for DmenuBackend {
+    fn program(&self) -> &'static str {
+        "dmenu"
+    }
+
+    fn default_args(&self) -> Vec<String> {
+        strings(&["-fn", "monospace:size=24"])
+    }
+
+    fn args(&self, request: &MenuRequest) -> Vec<String> {
+        let lines = request.choices_len.min(10).to_string();
+        strings(&["-p", request.prompt, "-i", "-l", &lines])
+    }
+}
+
+pub struct BemenuBackend;
+
+impl MenuBackend for BemenuBackend {
+    fn program(&self) -> &'static str {
+        "bemenu"
+    }
+
+    fn args(&self, request: &MenuRequest) -> Vec<String> {
+        let auto_select_prompt = if request.auto_select { " [AS]" } else { "" };
+        let mut args = vec![
+            "-l".to_owned(),
+            request.choices_len.to_string(),
+            "--prompt".to_owned(),
+            format!("{}{}", request.prompt, auto_select_prompt),
+        ];
+
+        if request.auto_select {
+            args.push("--auto-select".to_owned());
+        }
+        if request.numbered {
+            args.push("-F_".to_owned());
+        }
+
+        args
+    }
+}
+
+pub struct FuzzelBackend;
+
+// fuzzel does not support auto select
+impl MenuBackend for FuzzelBackend {
+    fn program(&self) -> &'static str {
+        "fuzzel"
+    }
+
+    fn args(&self, request: &MenuRequest) -> Vec<String> {
+        let lines = request.choices_len.min(15).to_string();
+        let prompt = format!("{}: ", request.prompt);
+        let mut args = strings(&["--dmenu", "--prompt", &prompt, "--lines", &lines]);
+
+        if request.numbered {
+            args.extend(strings(&["--search", "_"]));
+        }
+        if !request.allow_invalid {
+            args.push("--only-match".to_owned());
+        }
+
+        args
+    }
+}
+
+pub struct RofiBackend;
+
+impl MenuBackend for RofiBackend {
+    fn program(&self) -> &'static str {
+        "rofi"
+    }
+
+    fn args(&self, request: &MenuRequest) -> Vec<String> {
+        let lines = request.choices_len.min(15).to_string();
+        let mut args = strings(&["-dmenu", "-i", "-p", request.prompt, "-l", &lines]);
+
+        if request.auto_select {
+            args.push("-auto-select".to_owned());
+        }
+        if request.numbered {
+            args.extend(strings(&["-filter", "_"]));
+        }
+        if !request.allow_invalid {
+            args.push("-no-custom".to_owned());
+        }
+        if request.multi_select {
+            args.push("-multi-select".to_owned());
+        }
+
+        args
+    }
+
+    fn supports_multi_select(&self) -> bool {
+        true
+    }
+}
+
+pub struct WofiBackend;
+
+// wofi does not support auto select
+impl MenuBackend for WofiBackend {
+    fn program(&self) -> &'static str {
+        "wofi"
+    }
+
+    fn args(&self, request: &MenuRequest) -> Vec<String> {
+        // wofi counts the search bar as a line too
+        let lines = (request.choices_len.min(15) + 1).to_string();
+        let mut args = strings(&[
+            "--dmenu",
+            "--insensitive",
+            "--prompt",
+            request.prompt,
+            "--lines",
+            &lines,
+        ]);
+
+        if request.numbered {
+            args.extend(strings(&["--search", "_"]));
+        }
+
+        args
+    }
+}
+
+pub struct FzfBackend;
+
+impl MenuBackend for FzfBackend {
+    fn program(&self) -> &'static str {
+        "fzf"
+    }
+
+    fn default_args(&self) -> Vec<String> {
+        strings(&["--height", "~40%", "--layout", "reverse"])
+    }
+
+    fn args(&self, request: &MenuRequest) -> Vec<String> {
+        let prompt = format!("{}: ", request.prompt);
+        let mut args = strings(&["--prompt", &prompt]);
+
+        // fzf only selects automatically if a single choice matches when it starts
+        if request.auto_select {
+            args.push("--select-1".to_owned());
+        }
+        if request.numbered {
+            args.extend(strings(&["--query", "_"]));
+        }
+        if request.allow_invalid {
+            args.push("--print-query".to_owned());
+        }
+        if request.multi_select {
+            args.push("-m".to_owned());
+        }
+
+        args
+    }
+
+    fn supports_multi_select(&self) -> bool {
+        true
+    }
+
+    fn run(
+        &self,
+        runner: &dyn CommandRunner,
+        request: &MenuRequest,
+        input: &str,
+        extra_args: &[String],
+    ) -> anyhow::Result<Option<String>> {
+        let output = runner.output(
+            &Invocation::new("fzf")
+                .args(self.args(request))
+                .args(extra_args)
+                .stdin(input),
+        )?;
+        let stdout = String::from_utf8(output.stdout)?;
+        let mut lines = stdout.lines();
+
+        // With --print-query, the query is printed before the selections. Exit code 1 means that
+        // nothing matched the query.
+        let query = if request.allow_invalid {
+            lines.next()
+        } else {
+            None
+        };
+        let chosen = match output.code {
+            Some(0) => lines.collect::<Vec<_>>(),
+            Some(1) => query.into_iter().collect(),
+            _ => vec![],
+        };
+
+        if chosen.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(chosen.join("\n")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(numbered: bool, auto_select: bool, allow_invalid: bool) -> MenuRequest<'static> {
+        MenuRequest {
+            prompt: "Pick",
+            choices_len: 3,
+            numbered,
+            auto_select,
+            allow_invalid,
+            multi_select: false,
+        }
+    }
+
+    #[test]
+    fn plain_request_maps_to_no_feature_flags() {
+        assert_eq!(
+            RofiBackend.args(&request(false, false, true)),
+            ["-dmenu", "-i", "-p", "Pick", "-l", "3"]
+        );
+        assert_eq!(
+            DmenuBackend.args(&request(true, true, false)),
+            ["-p", "Pick", "-i", "-l", "3"]
+        );
+    }
+
+    #[test]
+    fn features_map_to_backend_flags() {
+        assert_eq!(
+            BemenuBackend.args(&request(true, true, false)),
+            ["-l", "3", "--prompt", "Pick [AS]", "--auto-select", "-F_"]
+        );
+        assert_eq!(
+            RofiBackend.args(&request(true, true, false)),
+            [
+                "-dmenu",
+                "-i",
+                "-p",
+                "Pick",
+                "-l",
+                "3",
+                "-auto-select",
+                "-filter",
+                "_",
+                "-no-custom"
+            ]
+        );
+        assert_eq!(
+            FzfBackend.args(&request(true, false, true)),
+            ["--prompt", "Pick: ", "--query", "_", "--print-query"]
+        );
+    }
+
+    #[test]
+    fn multi_select_maps_to_backend_flags() {
+        let request = MenuRequest {
+            multi_select: true,
+            ..request(false, false, true)
+        };
+
+        assert_eq!(
+            RofiBackend.args(&request).last().map(String::as_str),
+            Some("-multi-select")
+        );
+        assert_eq!(
+            FzfBackend.args(&request).last().map(String::as_str),
+            Some("-m")
+        );
+        assert!(!BemenuBackend.supports_multi_select());
+    }
+}
@@ -0,0 +1,281 @@
+use std::{fmt, sync::OnceLock};
+
+use serde::Deserialize;
+
+use crate::{
+    config,
+    util::{find_on_path, runner::CommandRunner},
+};
+
+mod backend;
+
+pub use backend::MenuBackendKind;
+use backend::{MenuBackend, MenuRequest};
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct MenuConfig {
+    pub backend: Option<MenuBackendKind>,
+    /// Replaces the arguments which tune the looks of the backend's menu (e.g. its font)
+    pub args: Option<Vec<String>>,
+}
+
+/// The error returned when the user closes the menu without choosing anything. It's not worth
+/// reporting, so `main` exits quietly when it finds one in the error chain.
+#[derive(Debug)]
+pub struct MenuAborted;
+
+impl fmt::Display for MenuAborted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "The menu was aborted")
+    }
+}
+
+impl std::error::Error for MenuAborted {}
+
+pub fn is_aborted(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<MenuAborted>().is_some()
+}
+
+pub struct Dmenu<'a> {
+    runner: &'a dyn CommandRunner,
+    allow_invalid: bool,
+    numbered: bool,
+    auto_select: bool,
+}
+
+impl<'a> Dmenu<'a> {
+    pub fn new(runner: &'a dyn CommandRunner) -> Self {
+        Self {
+            runner,
+            allow_invalid: false,
+            numbered: false,
+            auto_select: false,
+        }
+    }
+
+    pub fn allow_invalid(mut self) -> Self {
+        self.allow_invalid = true;
+        self
+    }
+
+    pub fn numbered(mut self) -> Self {
+        self.numbered = true;
+        self
+    }
+
+    pub fn auto_select(mut self) -> Self {
+        self.auto_select = true;
+        self
+    }
+
+    pub fn choose_one<'c, T>(
+        &self,
+        prompt: &str,
+        choices: &'c [T],
+        stringifier: impl Fn(&'c T) -> &'c str,
+    ) -> anyhow::Result<&'c T> {
+        let choice_strs = choices.iter().map(stringifier).collect::<Vec<_>>();
+
+        let chosen = self.choose_one_str(prompt, &choice_strs)?;
+        let i = choice_strs
+            .iter()
+            .position(|&choice| choice == chosen)
+            .ok_or_else(|| anyhow::anyhow!("Chosen string is not recognized"))?;
+
+        Ok(&choices[i])
+    }
+
+    pub fn choose_one_str(&self, prompt: &str, choices: &[&str]) -> anyhow::Result<String> {
+        let backend = get_backend()?;
+        let chosen = self.show(backend, prompt, choices, false)?;
+
+        Ok(chosen.into_iter().next().unwrap_or_default())
+    }
+
+    pub fn choose_many<'c, T>(
+        &self,
+        prompt: &str,
+        choices: &'c [T],
+        stringifier: impl Fn(&'c T) -> &'c str,
+    ) -> anyhow::Result<Vec<&'c T>> {
+        let choice_strs = choices.iter().map(stringifier).collect::<Vec<_>>();
+
+        self.choose_many_str(prompt, &choice_strs)?
+            .iter()
+            .map(|chosen| {
+                choice_strs
+                    .iter()
+                    .position(|choice| choice == chosen)
+                    .map(|i| &choices[i])
+                    .ok_or_else(|| anyhow::anyhow!("Chosen string is not recognized"))
+            })
+            .collect()
+    }
+
+    /// Let the user pick several choices. Backends without a multi-select mode prompt repeatedly
+    /// instead, until the user aborts the menu or there are no choices left.
+    pub fn choose_many_str(&self, prompt: &str, choices: &[&str]) -> anyhow::Result<Vec<String>> {
+        let backend = get_backend()?;
+        if backend.supports_multi_select() {
+            return self.show(backend, prompt, choices, true);
+        }
+
+        let mut remaining = choices.to_vec();
+        let mut all_chosen = Vec::<String>::new();
+
+        while !remaining.is_empty() {
+            let prompt = match all_chosen.len() {
+                0 => prompt.to_owned(),
+                n => format!("{} ({} chosen, abort to finish)", prompt, n),
+            };
+
+            let chosen = match self.show(backend, &prompt, &remaining, false) {
+                Ok(chosen) => chosen,
+                Err(err) if is_aborted(&err) && !all_chosen.is_empty() => break,
+                Err(err) => return Err(err),
+            };
+
+            remaining.retain(|choice| !chosen.iter().any(|c| c == choice));
+            all_chosen.extend(chosen);
+        }
+
+        Ok(all_chosen)
+    }
+
+    /// Show the menu once, and return the validated lines which the backend printed
+    fn show(
+        &self,
+        backend: &dyn MenuBackend,
+        prompt: &str,
+        choices: &[&str],
+        multi_select: bool,
+    ) -> anyhow::Result<Vec<String>> {
+        let choices_string = if self.numbered {
+            let numbered_choices = choices
+                .iter()
+                .enumerate()
+                .map(|(i, s)| format!("_{}: {}", i + 1, s))
+                .collect::<Vec<_>>();
+            numbered_choices.join("\n")
+        } else {
+            choices.join("\n")
+        };
+        let request = MenuRequest {
+            prompt,
+            choices_len: choices.len(),
+            numbered: self.numbered,
+            auto_select: self.auto_select,
+            allow_invalid: self.allow_invalid,
+            multi_select,
+        };
+        let extra_args = config::get()
+            .menu
+            .args
+            .clone()
+            .unwrap_or_else(|| backend.default_args());
+
+        let output = backend
+            .run(self.runner, &request, &choices_string, &extra_args)?
+            .ok_or(MenuAborted)?;
+
+        self.parse_output(&output, choices)
+    }
+
+    fn parse_output(&self, output: &str, choices: &[&str]) -> anyhow::Result<Vec<String>> {
+        let chosen = output
+            .lines()
+            .map(|line| {
+                // Typed input has no number prefix; if it's not allowed, it is rejected below
+                match line.split_once(':').filter(|_| self.numbered) {
+                    Some((_number_prefix, chosen)) => chosen.trim_start().to_owned(),
+                    None => line.to_owned(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if chosen.is_empty() && !self.allow_invalid {
+            return Err(MenuAborted.into());
+        }
+        if !self.allow_invalid && chosen.iter().any(|c| !choices.contains(&c.as_str())) {
+            anyhow::bail!("Invalid input given");
+        }
+
+        Ok(chosen)
+    }
+}
+
+fn is_wayland_session() -> bool {
+    std::env::vars()
+        .find(|(k, _)| k == "XDG_SESSION_TYPE")
+        .map(|(_, v)| v == "wayland")
+        .unwrap_or(false)
+}
+
+/// Pick the first backend whose program is installed, preferring the ones which fit the session.
+/// Without a display (e.g. over SSH) only fzf can work.
+fn detect_backend() -> Option<MenuBackendKind> {
+    let has_display =
+        std::env::var_os("WAYLAND_DISPLAY").is_some() || std::env::var_os("DISPLAY").is_some();
+
+    let candidates: &[MenuBackendKind] = if !has_display {
+        &[MenuBackendKind::Fzf]
+    } else if is_wayland_session() {
+        &[
+            MenuBackendKind::Bemenu,
+            MenuBackendKind::Fuzzel,
+            MenuBackendKind::Wofi,
+            MenuBackendKind::Rofi,
+        ]
+    } else {
+        &[MenuBackendKind::Dmenu, MenuBackendKind::Rofi]
+    };
+
+    candidates
+        .iter()
+        .copied()
+        .find(|kind| find_on_path(kind.backend().program()).is_some())
+}
+
+/// Get the backend chosen in the config, or detect one
+fn get_backend() -> anyhow::Result<&'static dyn MenuBackend> {
+    static DETECTED: OnceLock<Option<MenuBackendKind>> = OnceLock::new();
+
+    let kind = match config::get().menu.backend {
+        Some(kind) => kind,
+        None => DETECTED.get_or_init(detect_backend).ok_or_else(|| {
+            anyhow::anyhow!("Could not find a menu program; set `menu.backend` in the config")
+        })?,
+    };
+
+    Ok(kind.backend())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::runner::FakeRunner;
+
+    use super::*;
+
+    #[test]
+    fn numbered_output_is_stripped_and_validated() {
+        let fake = FakeRunner::new();
+        let dmenu = Dmenu::new(&fake).numbered();
+        let choices = ["one", "two: the sequel", "three"];
+
+        assert_eq!(
+            dmenu
+                .parse_output("_1: one\n_2: two: the sequel", &choices)
+                .unwrap(),
+            ["one", "two: the sequel"]
+        );
+        assert!(dmenu.parse_output("_1: one\nfour", &choices).is_err());
+        assert_eq!(
+            dmenu
+                .allow_invalid()
+                .parse_output("four", &choices)
+                .unwrap(),
+            ["four"]
+        );
+    }
+}
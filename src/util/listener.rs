@@ -6,7 +6,7 @@
 //! of it is already running.
 
 use std::fs::OpenOptions;
-use std::io::Write;
+use std::io::{Seek, SeekFrom, Write};
 
 use fd_lock::{RwLock, RwLockWriteGuard};
 
@@ -28,5 +28,25 @@ pub fn get_pidfile_lock(pidfile_path: &str) -> anyhow::Result<RwLock<std::fs::Fi
 /// * `guard`: the `RwLockWriteGuard` for the pidfile
 pub fn write_pid(guard: &mut RwLockWriteGuard<'_, std::fs::File>) -> anyhow::Result<()> {
     let pid_string = format!("{}", std::process::id());
+    // the previous pid may have been longer than ours
+    guard.set_len(0)?;
+    guard.seek(SeekFrom::Start(0))?;
     Ok(write!(guard, "{pid_string}")?)
 }
+
+/// Get the pid of the listener which is currently holding the lock on the pidfile, if there is
+/// one.
+///
+/// * `pidfile_path`: path to the pidfile
+pub fn get_running_pid(pidfile_path: &str) -> anyhow::Result<Option<u32>> {
+    let mut lock = get_pidfile_lock(pidfile_path)?;
+    if lock.try_write().is_ok() {
+        return Ok(None);
+    }
+
+    let pid = std::fs::read_to_string(pidfile_path)?
+        .trim()
+        .parse::<u32>()
+        .map_err(|_| anyhow::anyhow!("Pidfile '{}' does not contain a pid", pidfile_path))?;
+    Ok(Some(pid))
+}
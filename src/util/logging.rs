@@ -0,0 +1,260 @@
+//! Logging, mostly for the long-running components (the daemon, the listeners and the ytdl
+//! aggregator), whose stderr usually ends up nowhere. Messages go to stderr, and once a component
+//! calls `log_to_file`, to `<logs>/<component>.log` instead. Log files are rotated once they grow
+//! past `MAX_FILE_SIZE`.
+//!
+//! Events carry their details as key-values, which are written at the end of the line:
+//!
+//! ```text
+//! 2026-10-17T09:12:44.031Z INFO  audio::listener: Earbuds were removed device="JBL WAVE200TWS"
+//! ```
+
+use std::{
+    fmt::Write as _,
+    fs::{self, File, OpenOptions},
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{kv, LevelFilter, Log, Metadata, Record};
+
+use crate::system_atlas::SYSTEM_ATLAS;
+
+/// Size in bytes above which a log file is rotated
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+/// Number of rotated log files which are kept, as `<component>.log.1` (the newest) and so on
+const KEPT_FILES: usize = 3;
+
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl LogFile {
+    fn open(path: PathBuf) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self { path, file, size })
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", n));
+        path.into()
+    }
+
+    /// Shift the rotated files up by one, dropping the oldest, and start a new file
+    fn rotate(&mut self) -> io::Result<()> {
+        for n in (1..KEPT_FILES).rev() {
+            let _ = fs::rename(self.rotated_path(n), self.rotated_path(n + 1));
+        }
+        fs::rename(&self.path, self.rotated_path(1))?;
+
+        *self = Self::open(self.path.clone())?;
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 > MAX_FILE_SIZE {
+            self.rotate()?;
+        }
+
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+}
+
+struct Logger {
+    file: Mutex<Option<LogFile>>,
+}
+
+static LOGGER: Logger = Logger {
+    file: Mutex::new(None),
+};
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        // Dependencies only get to report their problems
+        let max_level = if metadata.target().starts_with("plsdo") {
+            log::max_level()
+        } else {
+            log::max_level().min(LevelFilter::Warn)
+        };
+        metadata.level() <= max_level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format_record(record);
+
+        let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(file) = file.as_mut() {
+            let timestamped = format!("{} {}\n", format_timestamp(SystemTime::now()), line);
+            if let Err(err) = file.write_line(&timestamped) {
+                eprintln!("Failed to write to {}: {}", file.path.display(), err);
+            }
+            // Someone is watching the component run, so show them as well
+            if !io::stderr().is_terminal() {
+                return;
+            }
+        }
+
+        eprintln!("{}", line);
+    }
+
+    fn flush(&self) {
+        let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(file) = file.as_mut() {
+            let _ = file.file.flush();
+        }
+    }
+}
+
+/// Writes the key-values of a record as ` key=value`, quoting values which would be ambiguous
+struct KeyValues<'a>(&'a mut String);
+
+impl<'kvs> kv::VisitSource<'kvs> for KeyValues<'_> {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        let value = value.to_string();
+        if value.is_empty() || value.contains(|c: char| c.is_whitespace() || c == '"') {
+            let _ = write!(self.0, " {}={:?}", key, value);
+        } else {
+            let _ = write!(self.0, " {}={}", key, value);
+        }
+        Ok(())
+    }
+}
+
+fn format_record(record: &Record) -> String {
+    let target = record.target();
+    let target = target
+        .strip_prefix("plsdo::subcommands::")
+        .or_else(|| target.strip_prefix("plsdo::"))
+        .unwrap_or(target);
+
+    let mut line = format!("{:<5} {}: {}", record.level(), target, record.args());
+    let _ = record.key_values().visit(&mut KeyValues(&mut line));
+    line
+}
+
+/// Format the time as RFC 3339, in UTC
+fn format_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+    let seconds_of_day = seconds % 86400;
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// The date of the given number of days since 1970-01-01. This is Howard Hinnant's
+/// `civil_from_days`, which counts in eras of 400 years starting on March 1st, so that leap days
+/// fall at the end of the year.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Install the logger. Messages less severe than the level are dropped.
+pub fn init(level: LevelFilter) {
+    // Only fails if a logger is installed already, e.g. by a plugin which embeds plsdo
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(level);
+}
+
+/// Write the messages of this process to the log file of the component, instead of stderr
+pub fn log_to_file(component: &str) -> anyhow::Result<()> {
+    let dir = Path::new(&SYSTEM_ATLAS.logs);
+    fs::create_dir_all(dir)?;
+    let file = LogFile::open(dir.join(format!("{}.log", component)))?;
+
+    *LOGGER.file.lock().unwrap_or_else(PoisonError::into_inner) = Some(file);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use log::Level;
+
+    use super::*;
+
+    #[test]
+    fn timestamps_are_utc_dates() {
+        let time = UNIX_EPOCH + Duration::from_millis(951_782_400_250);
+        assert_eq!(format_timestamp(time), "2000-02-29T00:00:00.250Z");
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+    }
+
+    #[test]
+    fn key_values_follow_the_message() {
+        let key_values = [("pid", "42"), ("title", "Some video")];
+        let record = Record::builder()
+            .level(Level::Info)
+            .target("plsdo::subcommands::ytdl::aggregator")
+            .args(format_args!("Download started"))
+            .key_values(&key_values)
+            .build();
+
+        assert_eq!(
+            format_record(&record),
+            r#"INFO  ytdl::aggregator: Download started pid=42 title="Some video""#
+        );
+    }
+
+    #[test]
+    fn full_log_file_is_rotated() {
+        let dir = std::env::temp_dir().join(format!("plsdo-log-rotation-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("daemon.log");
+        let line = format!("{}\n", "x".repeat(MAX_FILE_SIZE as usize / 2));
+
+        let mut file = LogFile::open(path.clone()).unwrap();
+        for _ in 0..(KEPT_FILES + 2) * 2 {
+            file.write_line(&line).unwrap();
+        }
+
+        let mut rotated = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        rotated.sort();
+        assert_eq!(
+            rotated,
+            ["daemon.log", "daemon.log.1", "daemon.log.2", "daemon.log.3"]
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), line);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,13 +1,27 @@
 #![allow(dead_code)]
 
 use std::env;
-use std::fs::{File, OpenOptions};
-use std::io::{BufReader, BufWriter, Read, Write};
-use std::path::Path;
+use std::os::unix::{fs::PermissionsExt, io::AsRawFd, net::UnixStream};
+use std::path::{Path, PathBuf};
+#[cfg(feature = "dbus")]
+use std::sync::{Arc, Mutex, PoisonError};
 
+#[cfg(feature = "dbus")]
+use dbus::blocking::SyncConnection;
+
+pub mod backing_file;
+pub mod bus;
+pub mod clipboard_history;
+pub mod completion;
+pub mod config_edit;
 pub mod dmenu;
 pub mod listener;
-use wl_clipboard_rs::paste::{get_contents, ClipboardType, Error, MimeType, Seat};
+pub mod logging;
+pub mod notify;
+pub mod runner;
+use xshell::{cmd, Shell};
+
+use crate::config;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum WM {
@@ -16,8 +30,31 @@ pub enum WM {
 }
 
 pub trait Clipboard {
+    /// Get the current contents of the clipboard. Where plsdo keeps the history itself (see
+    /// `History::Own`), the contents are also added to it.
     fn get_one(&self) -> anyhow::Result<String>;
-    fn get_many(&self, n: u32) -> anyhow::Result<&[String]>;
+    /// Get the latest `n` entries of the clipboard history, newest first
+    fn get_many(&self, n: u32) -> anyhow::Result<Vec<String>>;
+}
+
+/// Where the clipboard history comes from
+#[derive(Debug, PartialEq, Eq)]
+enum History {
+    /// The history of cliphist, which only runs on Wayland
+    Cliphist,
+    /// The history which plsdo keeps itself (see `clipboard_history`), if the config asks for it
+    Own,
+    None,
+}
+
+impl History {
+    fn determine(wm: WM, has_cliphist: bool, keep_history: bool) -> Self {
+        match wm {
+            WM::Hyprland if has_cliphist => Self::Cliphist,
+            _ if keep_history => Self::Own,
+            _ => Self::None,
+        }
+    }
 }
 
 pub struct RealClipboard {
@@ -29,7 +66,11 @@ impl RealClipboard {
         Self { wm }
     }
 
+    #[cfg(feature = "wayland")]
     fn get_one_wayland(&self) -> anyhow::Result<String> {
+        use std::io::Read;
+        use wl_clipboard_rs::paste::{get_contents, ClipboardType, Error, MimeType, Seat};
+
         let result = get_contents(ClipboardType::Regular, Seat::Unspecified, MimeType::Text);
         match result {
             Ok((mut pipe, _)) => {
@@ -44,31 +85,89 @@ impl RealClipboard {
         }
     }
 
+    #[cfg(not(feature = "wayland"))]
+    fn get_one_wayland(&self) -> anyhow::Result<String> {
+        anyhow::bail!(
+            "plsdo was built without the `wayland` feature, so it can't read the clipboard"
+        )
+    }
+
+    #[cfg(feature = "x11")]
+    fn get_one_x11(&self) -> anyhow::Result<String> {
+        let clipboard = x11_clipboard::Clipboard::new()?;
+        let atoms = &clipboard.getter.atoms;
+        let result = clipboard.load(
+            atoms.clipboard,
+            atoms.utf8_string,
+            atoms.property,
+            std::time::Duration::from_secs(1),
+        );
+
+        match result {
+            Ok(contents) => Ok(String::from_utf8_lossy(&contents).to_string()),
+            // Nobody owns the clipboard
+            Err(x11_clipboard::error::Error::Timeout) => Ok(String::new()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    #[cfg(not(feature = "x11"))]
     fn get_one_x11(&self) -> anyhow::Result<String> {
-        unimplemented!()
+        anyhow::bail!("plsdo was built without the `x11` feature, so it can't read the clipboard")
     }
 
-    fn get_many_wayland(&self, _n: u32) -> anyhow::Result<&[String]> {
-        unimplemented!()
+    fn history(&self) -> History {
+        History::determine(
+            self.wm,
+            find_on_path("cliphist").is_some(),
+            config::get().clipboard.keep_history,
+        )
+    }
+
+    /// Read the history of cliphist
+    fn get_many_from_cliphist(&self, n: u32) -> anyhow::Result<Vec<String>> {
+        let sh = Shell::new()?;
+        let list = cmd!(sh, "cliphist list").read()?;
+
+        list.lines()
+            .take(n as usize)
+            .map(|entry| Ok(cmd!(sh, "cliphist decode").stdin(entry).read()?))
+            .collect()
     }
 
-    fn get_many_x11(&self, _n: u32) -> anyhow::Result<&[String]> {
-        unimplemented!()
+    /// Read the history which plsdo keeps itself, making sure that the current contents of the
+    /// clipboard are part of it
+    fn get_many_from_own_history(&self, n: u32) -> anyhow::Result<Vec<String>> {
+        clipboard_history::check_size(n as usize)?;
+        self.get_one()?;
+        clipboard_history::read_latest(n as usize)
     }
 }
 
 impl Clipboard for RealClipboard {
     fn get_one(&self) -> anyhow::Result<String> {
-        match self.wm {
+        let contents = match self.wm {
             WM::Hyprland => self.get_one_wayland(),
             WM::GenericX11 => self.get_one_x11(),
+        }?;
+
+        if self.history() == History::Own {
+            if let Err(err) = clipboard_history::record(&contents) {
+                log::warn!(error = format!("{err:#}"); "Failed to record the clipboard history");
+            }
         }
+
+        Ok(contents)
     }
 
-    fn get_many(&self, n: u32) -> anyhow::Result<&[String]> {
-        match self.wm {
-            WM::Hyprland => self.get_many_wayland(n),
-            WM::GenericX11 => self.get_many_x11(n),
+    fn get_many(&self, n: u32) -> anyhow::Result<Vec<String>> {
+        match self.history() {
+            History::Cliphist => self.get_many_from_cliphist(n),
+            History::Own => self.get_many_from_own_history(n),
+            History::None => anyhow::bail!(
+                "No clipboard history is kept; install cliphist, or set `clipboard.keep_history` \
+                 in the config"
+            ),
         }
     }
 }
@@ -87,81 +186,94 @@ pub fn determine_wm() -> WM {
     }
 }
 
-pub fn modify_file<F>(path: &str, splitter: &str, modifier: F) -> anyhow::Result<()>
-where
-    F: FnOnce(&mut LinesWithEndings, &mut BufWriter<&File>) -> anyhow::Result<()>,
-{
-    // unwrap: we don't want to continue if home doesn't exist
-    let path = Path::new(path);
-    let mut temp_path = path.to_path_buf();
-
-    let file_name = path
-        .file_name()
-        .ok_or_else(|| anyhow::anyhow!("Given path is not pointing to a file"))?
-        .to_str()
-        .ok_or_else(|| anyhow::anyhow!("File name is not valid UTF-8"))?;
-
-    temp_path.push(".cache");
-    temp_path.push(file_name.to_string() + ".plsdo");
-
-    let file = File::open(path)?;
-    let mut reader = BufReader::new(&file);
-
-    let temp_file = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open(&temp_path)?;
-    let mut writer = BufWriter::new(&temp_file);
-
-    let mut contents = String::new();
-    reader.read_to_string(&mut contents)?;
-
-    let (before, after) = contents
-        .split_once(splitter)
-        .ok_or_else(|| anyhow::anyhow!("Could not find splitter string"))?;
+/// Get the connection to the D-Bus session bus. It's opened once, and shared by everything which
+/// runs in the process, so that the server (see `plsdo serve`) doesn't connect for every command.
+#[cfg(feature = "dbus")]
+pub fn session_bus() -> anyhow::Result<Arc<SyncConnection>> {
+    // Not a `OnceLock`, so that a failure to connect is not remembered
+    static SESSION_BUS: Mutex<Option<Arc<SyncConnection>>> = Mutex::new(None);
 
-    let mut after_lines = after.into();
-
-    writer.write_all(before.as_bytes())?;
-    writer.write_all(splitter.as_bytes())?;
+    let mut session_bus = SESSION_BUS.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(connection) = session_bus.as_ref() {
+        return Ok(connection.clone());
+    }
 
-    modifier(&mut after_lines, &mut writer)?;
+    let connection = Arc::new(SyncConnection::new_session()?);
+    *session_bus = Some(connection.clone());
+    Ok(connection)
+}
 
-    let rest = after_lines.collect::<String>();
+/// Get the path of a temporary file next to the given one. Being on the same filesystem, it can
+/// atomically replace the given file by being renamed.
+pub fn get_temp_path(path: &Path) -> anyhow::Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow::anyhow!("'{}' is not a valid file path", path.display()))?;
+    Ok(path.with_file_name(format!(".{}.plsdo", file_name)))
+}
 
-    writer.write_all(rest.as_bytes())?;
-    writer.flush()?;
+/// Whether the process at the other end of the socket runs as the same user as this one
+pub fn is_same_user(stream: &UnixStream) -> std::io::Result<bool> {
+    let mut credentials = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
 
-    std::fs::rename(temp_path, path)?;
+    // SAFETY: the buffer is a `ucred` and `len` is its size, which is what SO_PEERCRED writes
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut credentials as *mut libc::ucred).cast(),
+            &mut len,
+        )
+    };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
 
-    Ok(())
+    // SAFETY: getuid can't fail
+    Ok(credentials.uid == unsafe { libc::getuid() })
 }
 
-pub struct LinesWithEndings<'a> {
-    input: &'a str,
+/// Whether the path points to a file which can be executed
+pub fn is_executable(path: &Path) -> bool {
+    path.metadata()
+        .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
 }
 
-impl<'a> From<&'a str> for LinesWithEndings<'a> {
-    fn from(input: &'a str) -> Self {
-        Self { input }
-    }
+/// Find an executable with the given name in one of the directories of `$PATH`
+pub fn find_on_path(name: &str) -> Option<PathBuf> {
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path)
+        .map(|dir| dir.join(name))
+        .find(|candidate| is_executable(candidate))
 }
 
-impl<'a> Iterator for LinesWithEndings<'a> {
-    type Item = &'a str;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    fn next(&mut self) -> Option<&'a str> {
-        if self.input.is_empty() {
-            return None;
-        }
-        let split = self
-            .input
-            .find('\n')
-            .map(|i| i + 1)
-            .unwrap_or(self.input.len());
-        let (line, rest) = self.input.split_at(split);
-        self.input = rest;
-        Some(line)
+    #[test]
+    fn own_history_is_only_kept_without_cliphist_and_when_asked_for() {
+        assert_eq!(
+            History::determine(WM::Hyprland, true, true),
+            History::Cliphist
+        );
+        assert_eq!(History::determine(WM::Hyprland, false, true), History::Own);
+        assert_eq!(History::determine(WM::GenericX11, true, true), History::Own);
+
+        assert_eq!(
+            History::determine(WM::Hyprland, false, false),
+            History::None
+        );
+        assert_eq!(
+            History::determine(WM::GenericX11, false, false),
+            History::None
+        );
     }
 }
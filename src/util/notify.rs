@@ -0,0 +1,293 @@
+//! Desktop notifications, sent to the notification daemon over D-Bus
+//! (`org.freedesktop.Notifications`).
+//!
+//! Notifications which are sent repeatedly, such as the volume OSD, are given a tag. A tagged
+//! notification replaces the previous one with the same tag, even if that one was sent by another
+//! plsdo process, so holding down a volume key updates a single bubble instead of stacking them.
+
+use std::time::Duration;
+
+#[cfg(feature = "dbus")]
+use std::{fs, path::Path};
+
+/// How long notifications stay on screen, unless the daemon decides otherwise
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Urgency {
+    Low,
+    Normal,
+    /// Critical notifications usually stay on screen until they are dismissed
+    Critical,
+}
+
+#[derive(Debug, Clone)]
+pub struct Notification {
+    summary: String,
+    body: String,
+    icon: String,
+    urgency: Urgency,
+    /// Percentage shown as a progress bar, by daemons which support it
+    progress: Option<u32>,
+    tag: Option<String>,
+    /// Pairs of action keys and the labels of their buttons
+    actions: Vec<(String, String)>,
+    timeout: Duration,
+}
+
+impl Notification {
+    pub fn new(summary: impl Into<String>) -> Self {
+        Self {
+            summary: summary.into(),
+            body: String::new(),
+            icon: String::new(),
+            urgency: Urgency::Normal,
+            progress: None,
+            tag: None,
+            actions: vec![],
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// An icon name from the icon theme (e.g. `audio-volume-high`), or an absolute path
+    pub fn icon(mut self, icon: impl Into<String>) -> Self {
+        self.icon = icon.into();
+        self
+    }
+
+    pub fn urgency(mut self, urgency: Urgency) -> Self {
+        self.urgency = urgency;
+        self
+    }
+
+    pub fn progress(mut self, percent: u32) -> Self {
+        self.progress = Some(percent.min(100));
+        self
+    }
+
+    /// Replace the previous notification with the same tag
+    pub fn tag(mut self, tag: impl Into<String>) -> Self {
+        self.tag = Some(tag.into());
+        self
+    }
+
+    /// Add a button, whose key is returned by `send_and_wait` when it's clicked. The key
+    /// `default` is invoked by clicking the notification itself.
+    pub fn action(mut self, key: impl Into<String>, label: impl Into<String>) -> Self {
+        self.actions.push((key.into(), label.into()));
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Show the notification. A notification which can't be shown (e.g. because no notification
+    /// daemon is running) is not worth failing the command for, so the error is only printed.
+    pub fn send(&self) {
+        // Without the `dbus` feature, notifications are turned off rather than broken
+        if !cfg!(feature = "dbus") {
+            return;
+        }
+        if let Err(err) = self.try_send() {
+            log::warn!(summary = self.summary, error = format!("{err:#}"); "Failed to show notification");
+        }
+    }
+
+    /// Show the notification, and wait until one of its actions is invoked, or until it is
+    /// closed or expires. Returns the key of the invoked action.
+    pub fn send_and_wait(&self) -> Option<String> {
+        if !cfg!(feature = "dbus") {
+            return None;
+        }
+        self.try_send_and_wait().unwrap_or_else(|err| {
+            log::warn!(summary = self.summary, error = format!("{err:#}"); "Failed to show notification");
+            None
+        })
+    }
+}
+
+#[cfg(feature = "dbus")]
+mod daemon {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+        time::Instant,
+    };
+
+    use dbus::{
+        arg::{PropMap, RefArg, Variant},
+        blocking::{BlockingSender, Connection, Proxy},
+        message::MatchRule,
+    };
+
+    use super::*;
+    use crate::{system_atlas::SYSTEM_ATLAS, util::session_bus};
+
+    const DESTINATION: &str = "org.freedesktop.Notifications";
+    const PATH: &str = "/org/freedesktop/Notifications";
+    const INTERFACE: &str = "org.freedesktop.Notifications";
+
+    fn variant(value: impl RefArg + 'static) -> Variant<Box<dyn RefArg>> {
+        Variant(Box::new(value))
+    }
+
+    /// The id of the notification which was last sent with the tag, or 0 (which the daemon reads
+    /// as "nothing to replace")
+    fn replaced_id(ids: &Path, tag: &str) -> u32 {
+        fs::read_to_string(ids.join(tag))
+            .ok()
+            .and_then(|id| id.trim().parse().ok())
+            .unwrap_or(0)
+    }
+
+    fn remember_id(ids: &Path, tag: &str, id: u32) -> anyhow::Result<()> {
+        fs::create_dir_all(ids)?;
+        fs::write(ids.join(tag), id.to_string())?;
+        Ok(())
+    }
+
+    impl Notification {
+        pub(super) fn hints(&self) -> PropMap {
+            let mut hints: PropMap = HashMap::new();
+            let urgency: u8 = match self.urgency {
+                Urgency::Low => 0,
+                Urgency::Normal => 1,
+                Urgency::Critical => 2,
+            };
+            hints.insert("urgency".to_owned(), variant(urgency));
+
+            if let Some(progress) = self.progress {
+                hints.insert("value".to_owned(), variant(progress as i32));
+            }
+            if let Some(tag) = &self.tag {
+                // Daemons which implement these replace by tag on their own, which also works
+                // after the remembered id went stale
+                hints.insert("x-dunst-stack-tag".to_owned(), variant(tag.clone()));
+                hints.insert(
+                    "x-canonical-private-synchronous".to_owned(),
+                    variant(tag.clone()),
+                );
+            }
+
+            hints
+        }
+
+        fn notify(&self, connection: &impl BlockingSender) -> anyhow::Result<u32> {
+            let ids = Path::new(&SYSTEM_ATLAS.notification_ids);
+            let replaces_id = self.tag.as_deref().map_or(0, |tag| replaced_id(ids, tag));
+            let actions = self
+                .actions
+                .iter()
+                .flat_map(|(key, label)| [key.as_str(), label.as_str()])
+                .collect::<Vec<_>>();
+            let timeout = i32::try_from(self.timeout.as_millis()).unwrap_or(i32::MAX);
+
+            let proxy = Proxy::new(DESTINATION, PATH, Duration::from_secs(2), connection);
+            let (id,): (u32,) = proxy.method_call(
+                INTERFACE,
+                "Notify",
+                (
+                    "plsdo",
+                    replaces_id,
+                    self.icon.as_str(),
+                    self.summary.as_str(),
+                    self.body.as_str(),
+                    actions,
+                    self.hints(),
+                    timeout,
+                ),
+            )?;
+
+            if let Some(tag) = &self.tag {
+                remember_id(ids, tag, id)?;
+            }
+            Ok(id)
+        }
+
+        pub fn try_send(&self) -> anyhow::Result<u32> {
+            self.notify(&*session_bus()?)
+        }
+
+        pub fn try_send_and_wait(&self) -> anyhow::Result<Option<String>> {
+            // A connection of its own, so that its signals are not handled by other threads
+            let connection = Connection::new_session()?;
+
+            // Listen before sending, so that a quick click can't be missed
+            let invoked = Arc::new(Mutex::new(None::<(u32, String)>));
+            let closed = Arc::new(Mutex::new(Vec::<u32>::new()));
+            {
+                let invoked = invoked.clone();
+                let rule = MatchRule::new_signal(INTERFACE, "ActionInvoked");
+                connection.add_match(rule, move |(id, key): (u32, String), _, _| {
+                    invoked.lock().unwrap().get_or_insert((id, key));
+                    true
+                })?;
+            }
+            {
+                let closed = closed.clone();
+                let rule = MatchRule::new_signal(INTERFACE, "NotificationClosed");
+                connection.add_match(rule, move |(id, _reason): (u32, u32), _, _| {
+                    closed.lock().unwrap().push(id);
+                    true
+                })?;
+            }
+
+            let id = self.notify(&connection)?;
+
+            // Some daemons never report expired notifications as closed
+            let deadline = Instant::now() + self.timeout + Duration::from_secs(1);
+            while Instant::now() < deadline {
+                connection.process(Duration::from_millis(200))?;
+
+                if let Some((_, key)) = invoked.lock().unwrap().as_ref().filter(|(i, _)| *i == id) {
+                    return Ok(Some(key.clone()));
+                }
+                if closed.lock().unwrap().contains(&id) {
+                    return Ok(None);
+                }
+            }
+
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(not(feature = "dbus"))]
+impl Notification {
+    pub fn try_send(&self) -> anyhow::Result<u32> {
+        anyhow::bail!("plsdo was built without the `dbus` feature, so it can't show notifications")
+    }
+
+    pub fn try_send_and_wait(&self) -> anyhow::Result<Option<String>> {
+        self.try_send().map(|_| None)
+    }
+}
+
+#[cfg(all(test, feature = "dbus"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hints_carry_urgency_progress_and_tag() {
+        let hints = Notification::new("Volume")
+            .progress(140)
+            .tag("volume")
+            .urgency(Urgency::Critical)
+            .hints();
+
+        assert_eq!(hints["urgency"].0.as_u64(), Some(2));
+        assert_eq!(hints["value"].0.as_i64(), Some(100));
+        assert_eq!(hints["x-dunst-stack-tag"].0.as_str(), Some("volume"));
+
+        let hints = Notification::new("Download finished").hints();
+        assert_eq!(hints["urgency"].0.as_u64(), Some(1));
+        assert!(!hints.contains_key("value"));
+    }
+}
@@ -0,0 +1,354 @@
+//! Running external programs. Subcommands go through a `CommandRunner` instead of spawning
+//! processes themselves, so that their logic can be tested against a `FakeRunner`, which records
+//! the invocations and answers them with canned output.
+//!
+//! The real implementation is the one for `xshell::Shell`, so a `&Shell` can be passed wherever a
+//! `&dyn CommandRunner` is expected.
+
+use std::{
+    fmt::Display,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use anyhow::Context;
+use xshell::Shell;
+
+/// A program, the arguments to call it with, and what to feed it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invocation {
+    pub program: String,
+    pub args: Vec<String>,
+    pub stdin: Option<Vec<u8>>,
+    pub current_dir: Option<PathBuf>,
+    /// Discard the stderr of the program, instead of passing it through
+    pub ignore_stderr: bool,
+    /// Capture the stderr of the program, so that it's part of the error when the program fails.
+    /// Only `output` and `read` capture it; `run` always passes it through.
+    pub capture_stderr: bool,
+}
+
+impl Invocation {
+    pub fn new(program: impl Into<String>) -> Self {
+        Self {
+            program: program.into(),
+            args: vec![],
+            stdin: None,
+            current_dir: None,
+            ignore_stderr: false,
+            capture_stderr: false,
+        }
+    }
+
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn args(mut self, args: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn stdin(mut self, stdin: impl Into<Vec<u8>>) -> Self {
+        self.stdin = Some(stdin.into());
+        self
+    }
+
+    pub fn current_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.current_dir = Some(dir.into());
+        self
+    }
+
+    pub fn ignore_stderr(mut self) -> Self {
+        self.ignore_stderr = true;
+        self
+    }
+
+    pub fn capture_stderr(mut self) -> Self {
+        self.capture_stderr = true;
+        self
+    }
+}
+
+/// The command line, e.g. `pactl set-default-sink speakers`
+impl Display for Invocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.program)?;
+        for arg in &self.args {
+            write!(f, " {}", arg)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Output {
+    /// `None` if the program was killed by a signal
+    pub code: Option<i32>,
+    pub stdout: Vec<u8>,
+    /// Empty unless the stderr of the program was captured
+    pub stderr: Vec<u8>,
+}
+
+impl Output {
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+
+    /// Fail unless the program exited successfully, with what it printed to stderr if that was
+    /// captured
+    pub fn check(self, invocation: &Invocation) -> anyhow::Result<Self> {
+        let message = match self.code {
+            Some(0) => return Ok(self),
+            Some(code) => format!("`{}` exited with code {}", invocation, code),
+            None => format!("`{}` was killed by a signal", invocation),
+        };
+
+        let stderr = String::from_utf8_lossy(&self.stderr);
+        match stderr.trim() {
+            "" => Err(anyhow::anyhow!(message)),
+            stderr => Err(anyhow::anyhow!(stderr.to_owned()).context(message)),
+        }
+    }
+}
+
+pub trait CommandRunner {
+    /// Run the program with its stdout captured, and wait for it to exit. A non-zero exit code is
+    /// not an error; it's up to the caller to check the output.
+    fn output(&self, invocation: &Invocation) -> anyhow::Result<Output>;
+
+    /// Run the program with its stdout passed through, and fail if it doesn't exit successfully.
+    /// Unlike `output`, this doesn't wait for processes which the program leaves running in the
+    /// background, such as a game started by lutris.
+    fn run(&self, invocation: &Invocation) -> anyhow::Result<()>;
+
+    /// Run the program, fail if it doesn't exit successfully, and return its stdout without the
+    /// trailing newline
+    fn read(&self, invocation: &Invocation) -> anyhow::Result<String> {
+        let output = self.output(invocation)?.check(invocation)?;
+        let mut stdout = String::from_utf8(output.stdout)
+            .with_context(|| format!("`{}` printed invalid UTF-8", invocation))?;
+        if stdout.ends_with('\n') {
+            stdout.pop();
+            if stdout.ends_with('\r') {
+                stdout.pop();
+            }
+        }
+        Ok(stdout)
+    }
+}
+
+impl CommandRunner for Shell {
+    fn output(&self, invocation: &Invocation) -> anyhow::Result<Output> {
+        let mut command = prepare(self, invocation);
+        command.stdout(Stdio::piped());
+        if invocation.capture_stderr {
+            command.stderr(Stdio::piped());
+        }
+        let mut child = command
+            .spawn()
+            .with_context(|| format!("Failed to run `{}`", invocation))?;
+
+        // Write from another thread, so that a program which prints before it has read all of its
+        // input can't deadlock us
+        let writer = child.stdin.take().map(|mut child_stdin| {
+            let stdin = invocation.stdin.clone().unwrap_or_default();
+            std::thread::spawn(move || child_stdin.write_all(&stdin))
+        });
+
+        let output = child.wait_with_output()?;
+        if let Some(writer) = writer {
+            let _ = writer.join();
+        }
+
+        Ok(Output {
+            code: output.status.code(),
+            stdout: output.stdout,
+            stderr: output.stderr,
+        })
+    }
+
+    fn run(&self, invocation: &Invocation) -> anyhow::Result<()> {
+        let mut child = prepare(self, invocation)
+            .spawn()
+            .with_context(|| format!("Failed to run `{}`", invocation))?;
+
+        if let Some(mut child_stdin) = child.stdin.take() {
+            let stdin = invocation.stdin.as_deref().unwrap_or_default();
+            // The program may exit without reading all of it
+            let _ = child_stdin.write_all(stdin);
+        }
+
+        let status = child.wait()?;
+        Output {
+            code: status.code(),
+            ..Output::default()
+        }
+        .check(invocation)?;
+        Ok(())
+    }
+}
+
+/// Prepare the process, in the current directory of the shell unless another one is given
+fn prepare(sh: &Shell, invocation: &Invocation) -> Command {
+    let mut command = Command::new(&invocation.program);
+    command
+        .args(&invocation.args)
+        .current_dir(
+            invocation
+                .current_dir
+                .clone()
+                .unwrap_or_else(|| sh.current_dir()),
+        )
+        .stdin(if invocation.stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::inherit()
+        });
+    if invocation.ignore_stderr {
+        command.stderr(Stdio::null());
+    }
+    command
+}
+
+/// A runner which doesn't run anything. Each invocation is recorded, and answered with the output
+/// scripted for its command line, or with empty output and exit code 0 if nothing was scripted.
+#[cfg(test)]
+#[derive(Default)]
+pub struct FakeRunner {
+    responses: std::cell::RefCell<Vec<(String, Output)>>,
+    invocations: std::cell::RefCell<Vec<Invocation>>,
+}
+
+#[cfg(test)]
+impl FakeRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer the command line with the given stdout and exit code. When the same command line is
+    /// scripted several times, the responses are given out in order, and the last one is repeated.
+    pub fn respond(self, command_line: &str, stdout: &str, code: i32) -> Self {
+        self.respond_with_stderr(command_line, stdout, "", code)
+    }
+
+    /// Like `respond`, with something printed to stderr too
+    pub fn respond_with_stderr(
+        self,
+        command_line: &str,
+        stdout: &str,
+        stderr: &str,
+        code: i32,
+    ) -> Self {
+        self.responses.borrow_mut().push((
+            command_line.to_owned(),
+            Output {
+                code: Some(code),
+                stdout: stdout.as_bytes().to_vec(),
+                stderr: stderr.as_bytes().to_vec(),
+            },
+        ));
+        self
+    }
+
+    /// The invocations so far, in order
+    pub fn invocations(&self) -> Vec<Invocation> {
+        self.invocations.borrow().clone()
+    }
+
+    /// The command lines of the invocations so far, in order
+    pub fn command_lines(&self) -> Vec<String> {
+        self.invocations
+            .borrow()
+            .iter()
+            .map(|invocation| invocation.to_string())
+            .collect()
+    }
+
+    fn answer(&self, invocation: &Invocation) -> Output {
+        self.invocations.borrow_mut().push(invocation.clone());
+
+        let command_line = invocation.to_string();
+        let mut responses = self.responses.borrow_mut();
+        let matching = responses
+            .iter()
+            .enumerate()
+            .filter(|(_, (line, _))| *line == command_line)
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+
+        match matching.as_slice() {
+            [] => Output {
+                code: Some(0),
+                ..Output::default()
+            },
+            [only] => responses[*only].1.clone(),
+            [first, ..] => responses.remove(*first).1,
+        }
+    }
+}
+
+#[cfg(test)]
+impl CommandRunner for FakeRunner {
+    fn output(&self, invocation: &Invocation) -> anyhow::Result<Output> {
+        Ok(self.answer(invocation))
+    }
+
+    fn run(&self, invocation: &Invocation) -> anyhow::Result<()> {
+        self.answer(invocation).check(invocation)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shell_runner_feeds_stdin_and_reports_exit_codes() {
+        let sh = Shell::new().unwrap();
+
+        let upper = sh
+            .read(&Invocation::new("tr").args(["a-z", "A-Z"]).stdin("hello\n"))
+            .unwrap();
+        assert_eq!(upper, "HELLO");
+
+        let output = sh
+            .output(&Invocation::new("sh").args(["-c", "echo out; exit 3"]))
+            .unwrap();
+        assert_eq!(output.code, Some(3));
+        assert_eq!(output.stdout, b"out\n");
+        assert!(sh.run(&Invocation::new("false")).is_err());
+
+        let err = sh
+            .read(
+                &Invocation::new("sh")
+                    .args(["-c", "echo 'no such output' >&2; exit 1"])
+                    .capture_stderr(),
+            )
+            .unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "`sh -c echo 'no such output' >&2; exit 1` exited with code 1: no such output"
+        );
+    }
+
+    #[test]
+    fn fake_runner_gives_out_scripted_responses_in_order() {
+        let fake = FakeRunner::new()
+            .respond("pactl get-default-sink", "speakers\n", 0)
+            .respond("pactl get-default-sink", "headphones\n", 0)
+            .respond("lutris -l", "", 1);
+
+        let sink = Invocation::new("pactl").arg("get-default-sink");
+        assert_eq!(fake.read(&sink).unwrap(), "speakers");
+        assert_eq!(fake.read(&sink).unwrap(), "headphones");
+        assert_eq!(fake.read(&sink).unwrap(), "headphones");
+        assert!(fake.read(&Invocation::new("lutris").arg("-l")).is_err());
+        assert!(fake.run(&Invocation::new("unscripted")).is_ok());
+
+        assert_eq!(fake.command_lines()[3..], ["lutris -l", "unscripted"]);
+    }
+}
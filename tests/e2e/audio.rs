@@ -0,0 +1,62 @@
+use crate::harness::Harness;
+
+fn audio_harness(name: &str) -> Harness {
+    let harness = Harness::new(name);
+    harness.write_config(
+        "[devices]\nheadphones_controller_name = \"Headphones\"\ntv_controller_name = \"TV\"\n",
+    );
+    harness.stub_pactl("alsa_output.hdmi");
+    harness
+}
+
+#[test]
+fn chosen_output_becomes_the_default_sink() {
+    let harness = audio_harness("audio-choose");
+    // Pick the headphones from the numbered choices
+    harness.stub("bemenu", "grep Headphones");
+
+    harness.run(&["audio", "output", "choose"]);
+
+    let log = harness.argv_log();
+    assert!(
+        log.contains(&"bemenu -l 2 --prompt Choose audio output [AS] --auto-select -F_".to_owned())
+    );
+    assert!(log.contains(&"pactl set-default-sink bluez_output.1".to_owned()));
+
+    let state = harness.read_atlas("eww_audio");
+    assert_eq!(
+        state.lines().last(),
+        Some(r#"{"volume":70,"is_muted":false,"output":"Headphones"}"#)
+    );
+}
+
+#[test]
+fn aborted_menu_leaves_the_output_alone() {
+    let harness = audio_harness("audio-abort");
+    harness.stub("bemenu", "exit 1");
+
+    let output = harness
+        .command(&["audio", "output", "choose"])
+        .output()
+        .unwrap();
+
+    // Closing the menu is not worth an error message
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+    assert!(!harness
+        .argv_log()
+        .iter()
+        .any(|line| line.starts_with("pactl set-default-sink")));
+}
+
+#[test]
+fn relative_volume_change_updates_the_backing_file() {
+    let harness = audio_harness("audio-volume");
+
+    harness.run(&["audio", "volume", "set", "-r", "-5"]);
+
+    assert!(harness
+        .argv_log()
+        .contains(&"pactl set-sink-volume @DEFAULT_SINK@ -5%".to_owned()));
+    assert!(harness.read_atlas("eww_audio").contains(r#""output":"TV""#));
+}
@@ -0,0 +1,35 @@
+use crate::harness::Harness;
+
+const LUTRIS: &str = r#"
+if [ "$1" = "-l" ]; then
+    echo "12 | Celeste | celeste | linux"
+    echo "7 | Hades | hades | wine"
+fi
+"#;
+
+#[test]
+fn only_match_is_launched_without_a_menu() {
+    let harness = Harness::new("game-match");
+    harness.stub("lutris", LUTRIS).stub("bemenu", "exit 1");
+
+    harness.run(&["game", "Hades"]);
+
+    assert_eq!(
+        harness.argv_log(),
+        ["lutris -l", "lutris lutris:rungameid/7"]
+    );
+}
+
+#[test]
+fn ambiguous_search_shows_the_menu() {
+    let harness = Harness::new("game-menu");
+    harness
+        .stub("lutris", LUTRIS)
+        .stub("bemenu", "grep Celeste");
+
+    harness.run(&["game", "e"]);
+
+    let log = harness.argv_log();
+    assert!(log[1].starts_with("bemenu "));
+    assert_eq!(log[2], "lutris lutris:rungameid/12");
+}
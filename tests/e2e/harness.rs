@@ -0,0 +1,220 @@
+use std::{
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    time::{Duration, Instant},
+};
+
+/// The keys of the `paths` section of the config, which are all pointed into the atlas directory
+const ATLAS_KEYS: &[&str] = &[
+    "alacritty",
+    "fontconfig",
+    "eww_brightness",
+    "eww_colortemp",
+    "eww_audio",
+    "eww_workspaces",
+    "keyboard_layout",
+    "ytdl_aggregator_socket",
+    "state_bus_socket",
+    "server_socket",
+    "daemon_pidfile",
+    "audio_listener_pidfile",
+    "workspace_listener_pidfile",
+    "ytdl_aggregator_pidfile",
+    "server_pidfile",
+    "daemon_socket",
+    "hypr_submap",
+    "main_dotfiles",
+    "canary_dotfiles",
+    "dotfiles_id",
+    "clipboard_history",
+    "config_backups",
+    "notification_ids",
+    "undo_history",
+    "menu_frecency",
+    "hyprland_keybinds",
+    "sxhkd_keybinds",
+    "logs",
+];
+
+/// A sandbox for running the real `plsdo` binary. It has its own home, config and `SystemAtlas`
+/// directory, and a directory of stub programs which is put in front of `$PATH`. Every stub appends
+/// its command line to the argv log before doing anything else.
+///
+/// The directory is removed when the harness is dropped, unless the test failed, so that the logs
+/// can be inspected.
+pub struct Harness {
+    dir: PathBuf,
+}
+
+impl Harness {
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("plsdo-e2e-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for sub_dir in [
+            "bin",
+            "home/.cache",
+            "config/plsdo",
+            "data",
+            "state",
+            "atlas",
+        ] {
+            fs::create_dir_all(dir.join(sub_dir)).unwrap();
+        }
+
+        let harness = Self { dir };
+        harness.write_config("");
+        harness
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The path which `paths.<key>` of the config points to
+    pub fn atlas(&self, key: &str) -> PathBuf {
+        // Config edits tell the format of the alacritty config by its extension
+        let file_name = match key {
+            "alacritty" => "alacritty.toml",
+            key => key,
+        };
+        self.dir.join("atlas").join(file_name)
+    }
+
+    pub fn read_atlas(&self, key: &str) -> String {
+        fs::read_to_string(self.atlas(key)).unwrap_or_default()
+    }
+
+    /// Write the config file, with the given sections after the `paths` and `menu` sections
+    pub fn write_config(&self, extra: &str) {
+        let paths = ATLAS_KEYS
+            .iter()
+            .map(|key| format!("{} = {:?}", key, self.atlas(key)))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let config = format!(
+            "[paths]\n{}\n\n[menu]\nbackend = \"bemenu\"\nargs = []\n\n{}",
+            paths, extra
+        );
+        fs::write(self.dir.join("config/plsdo/config.toml"), config).unwrap();
+    }
+
+    /// Put a stub program on the path, which runs the given shell script after logging its
+    /// command line
+    pub fn stub(&self, program: &str, script: &str) -> &Self {
+        let path = self.dir.join("bin").join(program);
+        let log = self.dir.join("argv.log");
+        let contents = format!(
+            "#!/bin/sh\n\
+             {{ printf '%s' {program}; for arg; do printf ' %s' \"$arg\"; done; echo; }} >> {log:?}\n\
+             {script}\n"
+        );
+
+        fs::write(&path, contents).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        self
+    }
+
+    /// Put a `pactl` stub on the path, which knows a TV and a pair of headphones. It keeps the
+    /// volume and the default sink in files, so that changes show up when they are read back.
+    pub fn stub_pactl(&self, default_sink: &str) -> &Self {
+        const PACTL: &str = r#"
+volume_file="$E2E_DIR/volume"
+volume=$(cat "$volume_file" 2>/dev/null || echo 70)
+case "$*" in
+    "get-sink-mute @DEFAULT_SINK@") echo "Mute: no" ;;
+    "get-sink-volume @DEFAULT_SINK@") echo "Volume: front-left: 0 /  $volume% / 0 dB" ;;
+    "set-sink-volume @DEFAULT_SINK@ "[+-]*) echo $((volume + ${3%\%})) > "$volume_file" ;;
+    "set-sink-volume @DEFAULT_SINK@ "*) echo "${3%\%}" > "$volume_file" ;;
+    "-f json list sinks")
+        echo '[{"name": "alsa_output.hdmi", "description": "TV Speakers"},'
+        echo ' {"name": "bluez_output.1", "description": "Headphones WH-1000"}]'
+        ;;
+    "get-default-sink") cat "$E2E_DIR/default-sink" 2>/dev/null || echo "$default_sink" ;;
+    "set-default-sink "*) echo "$2" > "$E2E_DIR/default-sink" ;;
+esac
+"#;
+        self.stub(
+            "pactl",
+            &format!("default_sink={}\n{}", default_sink, PACTL),
+        )
+    }
+
+    /// The command lines which the stubs were called with, in order
+    pub fn argv_log(&self) -> Vec<String> {
+        fs::read_to_string(self.dir.join("argv.log"))
+            .unwrap_or_default()
+            .lines()
+            .map(|line| line.to_owned())
+            .collect()
+    }
+
+    /// A `plsdo` command which runs inside the sandbox
+    pub fn command(&self, args: &[&str]) -> Command {
+        let path = std::env::var_os("PATH").unwrap_or_default();
+        let path = std::env::join_paths(
+            std::iter::once(self.dir.join("bin")).chain(std::env::split_paths(&path)),
+        )
+        .unwrap();
+
+        let mut command = Command::new(env!("CARGO_BIN_EXE_plsdo"));
+        command
+            .args(args)
+            .env("PATH", path)
+            .env("HOME", self.dir.join("home"))
+            .env("XDG_CONFIG_HOME", self.dir.join("config"))
+            .env("XDG_DATA_HOME", self.dir.join("data"))
+            .env("XDG_STATE_HOME", self.dir.join("state"))
+            .env("XDG_CURRENT_DESKTOP", "Hyprland")
+            .env("E2E_DIR", &self.dir)
+            // Keep notifications off the desktop of whoever runs the tests
+            .env(
+                "DBUS_SESSION_BUS_ADDRESS",
+                format!("unix:path={}", self.dir.join("no-bus").display()),
+            )
+            .env(
+                "DBUS_SYSTEM_BUS_ADDRESS",
+                format!("unix:path={}", self.dir.join("no-bus").display()),
+            )
+            .env_remove("COMPLETE")
+            .env_remove("PLSDO_FORMAT")
+            .env_remove("PLSDO_LOG");
+        command
+    }
+
+    /// Run `plsdo` to completion, and fail the test if it doesn't succeed
+    pub fn run(&self, args: &[&str]) -> String {
+        let output = self.command(args).output().unwrap();
+        assert_success(args, &output);
+        String::from_utf8(output.stdout).unwrap()
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        if !std::thread::panicking() {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+}
+
+pub fn assert_success(args: &[&str], output: &Output) {
+    assert!(
+        output.status.success(),
+        "`plsdo {}` failed with {}\nstdout:\n{}\nstderr:\n{}",
+        args.join(" "),
+        output.status,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// Poll the condition until it holds, and fail the test if it doesn't within a few seconds
+pub fn wait_until(what: &str, mut condition: impl FnMut() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !condition() {
+        assert!(Instant::now() < deadline, "Timed out waiting for {}", what);
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
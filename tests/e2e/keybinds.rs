@@ -0,0 +1,38 @@
+use crate::harness::Harness;
+
+const KEYMAP: &str = r#"
+[keybinds]
+"SUPER+Z" = ["undo"]
+"SUPER+SHIFT+T" = ["scene", "apply", "movie night"]
+"#;
+
+#[test]
+fn keymap_is_written_for_hyprland_and_sxhkd() {
+    let harness = Harness::new("keybinds-generate");
+    harness.write_config(KEYMAP);
+
+    let stdout = harness.run(&["keybinds", "generate"]);
+
+    assert!(stdout.starts_with("Wrote 2 keybinds"));
+    let hyprland = harness.read_atlas("hyprland_keybinds");
+    assert!(hyprland.contains("bind = SUPER SHIFT, T, exec, plsdo scene apply 'movie night'\n"));
+    assert!(hyprland.contains("bind = SUPER, Z, exec, plsdo undo\n"));
+    let sxhkd = harness.read_atlas("sxhkd_keybinds");
+    assert!(sxhkd.contains("\nsuper + z\n\tplsdo undo\n"));
+}
+
+#[test]
+fn invalid_keymap_writes_nothing() {
+    let harness = Harness::new("keybinds-invalid");
+    harness.write_config(&format!(
+        "{}\"SUPER+V\" = [\"audio\", \"louder\"]\n",
+        KEYMAP
+    ));
+
+    let output = harness.command(&["keybinds", "generate"]).output().unwrap();
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("SUPER+V: unrecognized subcommand 'louder'"));
+    assert!(!harness.atlas("hyprland_keybinds").exists());
+}
@@ -0,0 +1,38 @@
+use std::path::Path;
+
+use crate::harness::Harness;
+
+const HYPRCTL: &str = r#"
+case "$2" in
+    input:kb_layout) echo '{"option": "input:kb_layout", "str": "us,gb", "set": true}' ;;
+    input:kb_variant) echo '{"option": "input:kb_variant", "str": "intl,extd", "set": true}' ;;
+    input:kb_options) echo '{"option": "input:kb_options", "str": "", "set": false}' ;;
+esac
+"#;
+
+#[test]
+fn persisted_layout_is_reported() {
+    // The names of the layouts are looked up in the xkb rules
+    if !Path::new("/usr/share/X11/xkb/rules/base.lst").exists() {
+        eprintln!("Skipping: the xkb rules are not installed");
+        return;
+    }
+
+    let harness = Harness::new("keyboard-get");
+    harness.stub("hyprctl", HYPRCTL);
+    std::fs::write(harness.atlas("keyboard_layout"), "{\"layout_id\":\"gb\"}\n").unwrap();
+
+    let output = harness.run(&["--output-format", "json", "keyboard", "get"]);
+
+    let summary: serde_json::Value = serde_json::from_str(&output).unwrap();
+    assert_eq!(summary["index"], 1);
+    assert_eq!(summary["name"], "English (UK, extended, Windows)");
+    assert_eq!(
+        harness.argv_log(),
+        [
+            "hyprctl getoption input:kb_layout -j",
+            "hyprctl getoption input:kb_variant -j",
+            "hyprctl getoption input:kb_options -j"
+        ]
+    );
+}
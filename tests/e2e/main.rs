@@ -0,0 +1,19 @@
+//! End-to-end tests, which run the `plsdo` binary against stub programs instead of the desktop.
+//! See `harness::Harness` for what the sandbox looks like.
+
+mod audio;
+mod game;
+mod harness;
+mod keybinds;
+#[cfg(feature = "hyprland")]
+mod keyboard;
+mod menu;
+mod playerctl;
+#[cfg(feature = "dbus")]
+mod power;
+mod scene;
+#[cfg(feature = "hyprland")]
+mod screenshot;
+mod server;
+mod undo;
+mod ytdl;
@@ -0,0 +1,28 @@
+use crate::harness::Harness;
+
+/// Picks the volume action from the palette, and types a value into the prompt for it
+const BEMENU: &str = r#"
+case "$*" in
+    *"--prompt Run") grep "^audio › volume › set " ;;
+    *"audio › volume › set: VALUE"*) echo 30 ;;
+    *) exit 1 ;;
+esac
+"#;
+
+#[test]
+fn chosen_action_runs_with_the_prompted_arguments() {
+    let harness = Harness::new("menu");
+    harness.write_config("[devices]\ntv_controller_name = \"TV\"\n");
+    harness
+        .stub_pactl("alsa_output.hdmi")
+        .stub("bemenu", BEMENU);
+
+    harness.run(&["menu"]);
+
+    let log = harness.argv_log();
+    assert!(log.contains(&"pactl set-sink-volume @DEFAULT_SINK@ 30%".to_owned()));
+
+    let frecency: serde_json::Value =
+        serde_json::from_str(&harness.read_atlas("menu_frecency")).unwrap();
+    assert_eq!(frecency["audio volume set"]["count"], 1);
+}
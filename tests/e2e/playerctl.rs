@@ -0,0 +1,23 @@
+use crate::harness::Harness;
+
+#[test]
+fn commands_go_to_the_selected_player() {
+    let harness = Harness::new("playerctl");
+    harness.stub(
+        "playerctl",
+        r#"if [ "$1" = "-l" ]; then printf 'spotify\nfirefox.instance_1_23\n'; fi"#,
+    );
+
+    harness.run(&["playerctl", "select-player", "spotify"]);
+    harness.run(&["playerctl", "toggle"]);
+    harness.run(&["playerctl", "skip", "--", "-10"]);
+
+    assert_eq!(
+        harness.argv_log(),
+        [
+            "playerctl -l",
+            "playerctl play-pause -p spotify",
+            "playerctl position -p spotify -10"
+        ]
+    );
+}
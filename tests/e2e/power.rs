@@ -0,0 +1,206 @@
+use std::{
+    io::{BufRead, BufReader},
+    process::{Child, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use dbus::{
+    blocking::Connection,
+    channel::{Channel, MatchingReceiver, Sender},
+    message::MatchRule,
+    strings::ErrorName,
+    Message,
+};
+
+use crate::harness::Harness;
+
+/// A private bus with a stand-in for logind on it, which answers like a machine without swap
+/// (so it can't hibernate), where a video player blocks sleep. The methods which perform an
+/// action are only recorded, and suspending is refused like logind does for a blocked action.
+struct FakeLogind {
+    bus: Child,
+    address: String,
+    calls: Arc<Mutex<Vec<String>>>,
+    stop: Arc<AtomicBool>,
+    service: Option<JoinHandle<()>>,
+}
+
+impl FakeLogind {
+    /// Start the stand-in, or return `None` if `dbus-daemon` is not installed
+    fn start(harness: &Harness) -> Option<Self> {
+        let socket = harness.dir().join("system-bus");
+        let mut bus = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .arg(format!("--address=unix:path={}", socket.display()))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+
+        // The address is printed once the bus accepts connections
+        let mut address = String::new();
+        BufReader::new(bus.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        let address = address.trim().to_owned();
+
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let service = {
+            let (address, calls, stop) = (address.clone(), calls.clone(), stop.clone());
+            std::thread::spawn(move || serve(&address, calls, stop, ready_tx))
+        };
+        ready_rx.recv().unwrap();
+
+        Some(Self {
+            bus,
+            address,
+            calls,
+            stop,
+            service: Some(service),
+        })
+    }
+
+    fn calls(&self) -> Vec<String> {
+        self.calls.lock().unwrap().clone()
+    }
+}
+
+impl Drop for FakeLogind {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(service) = self.service.take() {
+            let _ = service.join();
+        }
+        let _ = self.bus.kill();
+        let _ = self.bus.wait();
+    }
+}
+
+fn serve(
+    address: &str,
+    calls: Arc<Mutex<Vec<String>>>,
+    stop: Arc<AtomicBool>,
+    ready: mpsc::Sender<()>,
+) {
+    let mut channel = Channel::open_private(address).unwrap();
+    channel.register().unwrap();
+    let connection = Connection::from(channel);
+    connection
+        .request_name("org.freedesktop.login1", false, true, false)
+        .unwrap();
+
+    connection.start_receive(
+        MatchRule::new_method_call(),
+        Box::new(move |call: Message, connection| {
+            let reply = answer(&call, &calls);
+            let _ = connection.send(reply);
+            true
+        }),
+    );
+    ready.send(()).unwrap();
+
+    while !stop.load(Ordering::Relaxed) {
+        connection.process(Duration::from_millis(50)).unwrap();
+    }
+}
+
+fn answer(call: &Message, calls: &Mutex<Vec<String>>) -> Message {
+    let member = call
+        .member()
+        .map(|member| member.to_string())
+        .unwrap_or_default();
+    match member.as_str() {
+        "CanHibernate" => call.method_return().append1("na"),
+        "CanPowerOff" | "CanReboot" | "CanSuspend" | "CanHybridSleep" => {
+            call.method_return().append1("yes")
+        }
+        "ListInhibitors" => call.method_return().append1(vec![(
+            "sleep:idle",
+            "mpv",
+            "Playing video",
+            "block",
+            1000u32,
+            42u32,
+        )]),
+        "Suspend" => call.error(
+            &ErrorName::from("org.freedesktop.login1.OperationInProgress"),
+            c"Operation inhibited by \"mpv\" (PID 42), reason is \"Playing video\"",
+        ),
+        _ => {
+            let args = call
+                .iter_init()
+                .map(|arg| format!("{:?}", arg))
+                .collect::<Vec<_>>();
+            calls
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", member, args.join(" ")));
+            call.method_return()
+        }
+    }
+}
+
+/// Remembers the menu it's shown, and picks the given action from it
+fn bemenu(harness: &Harness, action: &str) -> String {
+    let menu = harness.dir().join("menu");
+    format!("tee {menu:?} | grep -m1 ': {action}'")
+}
+
+#[test]
+fn actions_are_offered_with_their_inhibitors() {
+    let harness = Harness::new("power-menu");
+    let Some(logind) = FakeLogind::start(&harness) else {
+        eprintln!("dbus-daemon is not installed; skipping");
+        return;
+    };
+    harness.stub("bemenu", &bemenu(&harness, "reboot"));
+
+    let output = harness
+        .command(&["power"])
+        .env("DBUS_SYSTEM_BUS_ADDRESS", &logind.address)
+        .output()
+        .unwrap();
+
+    crate::harness::assert_success(&["power"], &output);
+    let menu = std::fs::read_to_string(harness.dir().join("menu")).unwrap();
+    assert_eq!(
+        menu.lines().collect::<Vec<_>>(),
+        [
+            "_1: shutdown",
+            "_2: reboot",
+            "_3: suspend  [inhibited by mpv: Playing video (block)]",
+            "_4: hybrid-sleep  [inhibited by mpv: Playing video (block)]",
+            "_5: lock",
+            "_6: logout",
+        ]
+    );
+    assert_eq!(logind.calls(), ["Reboot true"]);
+}
+
+#[test]
+fn refused_action_is_reported() {
+    let harness = Harness::new("power-refused");
+    let Some(logind) = FakeLogind::start(&harness) else {
+        eprintln!("dbus-daemon is not installed; skipping");
+        return;
+    };
+    harness.stub("bemenu", &bemenu(&harness, "suspend"));
+
+    let output = harness
+        .command(&["power"])
+        .env("DBUS_SYSTEM_BUS_ADDRESS", &logind.address)
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Failed to suspend"));
+    assert!(stderr.contains("Operation inhibited by \"mpv\""));
+}
@@ -0,0 +1,68 @@
+use std::fs;
+
+use crate::harness::Harness;
+
+const DEVICES: &str =
+    "[devices]\nheadphones_controller_name = \"Headphones\"\ntv_controller_name = \"TV\"\n";
+
+fn scene_harness(name: &str, scenes: &str) -> Harness {
+    let harness = Harness::new(name);
+    harness.write_config(&format!("{}\n{}", DEVICES, scenes));
+    harness.stub_pactl("bluez_output.1");
+    fs::write(harness.atlas("alacritty"), "[font]\nsize = 11\n").unwrap();
+    harness
+}
+
+#[test]
+fn failed_settings_dont_stop_the_others() {
+    let harness = scene_harness(
+        "scene-apply",
+        "[scenes.movie]\naudio_output = \"tv\"\nbrightness = 100\nfont_size = 14\n",
+    );
+
+    let output = harness
+        .command(&["scene", "apply", "movie"])
+        .output()
+        .unwrap();
+
+    // The sandbox has no session bus, so there is no gammarelay to set the brightness with
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(
+            "Error: 1 of 3 settings of scene 'movie' could not be applied:\n  brightness: "
+        ),
+        "{}",
+        stderr
+    );
+
+    assert!(harness
+        .argv_log()
+        .contains(&"pactl set-default-sink alsa_output.hdmi".to_owned()));
+    assert!(harness.read_atlas("eww_audio").contains(r#""output":"TV""#));
+    assert_eq!(harness.read_atlas("alacritty"), "[font]\nsize = 14\n");
+}
+
+#[test]
+fn saved_scene_is_listed() {
+    let harness = scene_harness("scene-save", "");
+
+    harness.run(&[
+        "scene",
+        "save",
+        "night",
+        "--only",
+        "audio_output",
+        "--only",
+        "font_size",
+    ]);
+
+    let config = fs::read_to_string(harness.dir().join("config/plsdo/config.toml")).unwrap();
+    assert!(config.contains("[scenes.night]\naudio_output = \"headphones\"\nfont_size = 11.0\n"));
+
+    let scenes = harness.run(&["--output-format", "json", "scene", "list"]);
+    assert_eq!(
+        scenes.trim(),
+        r#"{"night":{"audio_output":"headphones","font_size":11.0}}"#
+    );
+}
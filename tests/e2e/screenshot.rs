@@ -0,0 +1,29 @@
+use crate::harness::Harness;
+
+#[test]
+fn selected_area_is_saved_to_a_file() {
+    let harness = Harness::new("screenshot-area");
+    harness
+        .stub("slurp", "echo '10,20 300x200'")
+        .stub("grim", "");
+
+    harness.run(&["screenshot", "--output", "file", "area"]);
+
+    assert_eq!(harness.argv_log(), ["slurp", "grim -g 10,20 300x200"]);
+}
+
+#[test]
+fn clipboard_screenshot_is_piped_into_wl_copy() {
+    let harness = Harness::new("screenshot-clipboard");
+    harness
+        .stub("grim", "printf 'png bytes'")
+        .stub("wl-copy", "cat > \"$E2E_DIR/clipboard\"");
+
+    harness.run(&["screenshot", "--output", "clipboard", "area", "0,0,64,32"]);
+
+    assert_eq!(harness.argv_log()[..2], ["grim -g 0,0 64x32 -", "wl-copy"]);
+    assert_eq!(
+        std::fs::read_to_string(harness.dir().join("clipboard")).unwrap(),
+        "png bytes"
+    );
+}
@@ -0,0 +1,100 @@
+use std::{
+    fs,
+    process::{Child, Stdio},
+};
+
+use crate::harness::{assert_success, wait_until, Harness};
+
+/// Kills the server at the end of the test, even if it fails
+struct Server(Child);
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// Start a server in the sandbox, and fail the test if it doesn't come up
+fn start_server(harness: &Harness) -> Server {
+    let mut server = Server(
+        harness
+            .command(&["--log-level", "debug", "serve"])
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap(),
+    );
+    let socket = harness.atlas("server_socket");
+    wait_until("the server to start", || {
+        socket.exists() || server.0.try_wait().unwrap().is_some()
+    });
+
+    if let Some(status) = server.0.try_wait().unwrap() {
+        panic!(
+            "The server exited with {}\n{}",
+            status,
+            fs::read_to_string(harness.atlas("logs").join("server.log")).unwrap_or_default()
+        );
+    }
+    server
+}
+
+#[test]
+fn commands_without_a_terminal_run_in_the_server() {
+    let harness = Harness::new("server-forward");
+    harness.stub_pactl("alsa_output.hdmi");
+    harness.write_config("[devices]\ntv_controller_name = \"TV\"\n");
+    let _server = start_server(&harness);
+
+    harness.run(&["audio", "volume", "set", "-r", "-5"]);
+
+    assert!(harness
+        .argv_log()
+        .contains(&"pactl set-sink-volume @DEFAULT_SINK@ -5%".to_owned()));
+    let log = fs::read_to_string(harness.atlas("logs").join("server.log")).unwrap();
+    assert!(log.contains("Running command args=\""));
+    assert!(log.contains("audio volume set -r -5"));
+}
+
+#[test]
+fn errors_in_the_server_are_reported_by_the_client() {
+    let harness = Harness::new("server-error");
+    harness.stub("lutris", "exit 3");
+    let _server = start_server(&harness);
+
+    let output = harness.command(&["game"]).output().unwrap();
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.starts_with("Error: `lutris -l` exited with code 3"),
+        "{}",
+        stderr
+    );
+    let log = fs::read_to_string(harness.atlas("logs").join("server.log")).unwrap();
+    assert!(log.contains("WARN  serve: Command failed"));
+}
+
+#[test]
+fn commands_from_another_session_run_in_the_client() {
+    let harness = Harness::new("server-foreign-env");
+    harness.stub_pactl("alsa_output.hdmi");
+    harness.write_config("[devices]\ntv_controller_name = \"TV\"\n");
+    let _server = start_server(&harness);
+
+    let args = ["audio", "volume", "set", "-r", "-5"];
+    let output = harness
+        .command(&args)
+        .env("WAYLAND_DISPLAY", "wayland-e2e-other")
+        .output()
+        .unwrap();
+
+    // The server refuses before running anything, so the client ran pactl itself
+    assert_success(&args, &output);
+    assert!(harness
+        .argv_log()
+        .contains(&"pactl set-sink-volume @DEFAULT_SINK@ -5%".to_owned()));
+    let log = fs::read_to_string(harness.atlas("logs").join("server.log")).unwrap();
+    assert!(log.contains("The client runs in another environment"));
+    assert!(!log.contains("Command failed"));
+}
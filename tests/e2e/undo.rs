@@ -0,0 +1,33 @@
+use crate::harness::Harness;
+
+#[test]
+fn volume_change_is_undone_and_redone() {
+    let harness = Harness::new("undo-volume");
+    harness.write_config("[devices]\ntv_controller_name = \"TV\"\n");
+    harness.stub_pactl("alsa_output.hdmi");
+
+    harness.run(&["audio", "volume", "set", "-r", "-5"]);
+    let volume = || {
+        harness
+            .read_atlas("eww_audio")
+            .lines()
+            .last()
+            .unwrap()
+            .to_owned()
+    };
+    assert!(volume().contains(r#""volume":65"#));
+
+    let undone = harness.run(&["undo"]);
+    assert_eq!(undone.trim(), "Restored the volume 70%");
+    let log = harness.argv_log();
+    assert!(log.contains(&"pactl set-sink-volume @DEFAULT_SINK@ 70%".to_owned()));
+    assert!(log.contains(&"pactl set-sink-mute @DEFAULT_SINK@ 0".to_owned()));
+    assert!(volume().contains(r#""volume":70"#));
+
+    harness.run(&["redo"]);
+    assert!(volume().contains(r#""volume":65"#));
+
+    let output = harness.command(&["redo"]).output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Error: There is nothing to redo"));
+}
@@ -0,0 +1,98 @@
+use std::{
+    fs,
+    process::{Child, Stdio},
+};
+
+use crate::harness::{assert_success, wait_until, Harness};
+
+/// Prints the lines of a download which is in progress, then waits until the test lets it finish
+const YT_DLP: &str = r#"
+for url; do :; done
+echo "[youtube] Extracting URL: $url"
+echo "[download] Destination: /videos/Some Video.webm"
+echo "[download]  42.0% of   10.00MiB at    1.00MiB/s ETA 00:06"
+while [ ! -e "$E2E_DIR/finish" ]; do sleep 0.05; done
+echo "[download] 100% of   10.00MiB in 00:00:10 at 1.00MiB/s"
+"#;
+
+/// Kills the aggregator at the end of the test, even if it fails
+struct Aggregator(Child);
+
+impl Drop for Aggregator {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn progress(harness: &Harness) -> serde_json::Value {
+    let output = harness.run(&["--output-format", "json", "ytdl", "get_download_progress"]);
+    serde_json::from_str(&output).unwrap()
+}
+
+#[test]
+fn download_progress_is_aggregated() {
+    let harness = Harness::new("ytdl");
+    harness.stub("yt-dlp", YT_DLP);
+
+    let mut aggregator = Aggregator(
+        harness
+            .command(&["--log-level", "debug", "ytdl", "run_aggregator"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap(),
+    );
+    let socket = harness.atlas("ytdl_aggregator_socket");
+    wait_until("the aggregator to start", || {
+        socket.exists() || aggregator.0.try_wait().unwrap().is_some()
+    });
+    if let Some(status) = aggregator.0.try_wait().unwrap() {
+        panic!("The aggregator exited ({})", status);
+    }
+
+    let download_args = [
+        "ytdl",
+        "download",
+        "-f",
+        "720p",
+        "url",
+        "https://youtu.be/abc",
+    ];
+    let download = harness
+        .command(&download_args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    wait_until("the download to show up", || {
+        progress(&harness).to_string().contains("Some Video")
+    });
+    let all_progress = progress(&harness);
+    let downloads = all_progress.as_object().unwrap();
+    assert_eq!(downloads.len(), 1);
+    let (_pid, download_info) = downloads.iter().next().unwrap();
+    let info = &download_info["Full"];
+    assert_eq!(info["metadata"]["url"], "https://youtu.be/abc");
+    assert_eq!(info["metadata"]["path"], "/videos/Some Video.webm");
+    assert_eq!(info["progress"]["Downloading"]["percent"], 42);
+
+    fs::write(harness.dir().join("finish"), "").unwrap();
+    let output = download.wait_with_output().unwrap();
+    assert_success(&download_args, &output);
+
+    assert_eq!(
+        harness.argv_log(),
+        ["yt-dlp -f bestvideo[height<=720]+bestaudio/best[height<=720] --progress --newline -r 16384 https://youtu.be/abc"]
+    );
+    wait_until("the finished download to be dropped", || {
+        progress(&harness).as_object().unwrap().is_empty()
+    });
+
+    let log = fs::read_to_string(harness.atlas("logs").join("ytdl.log")).unwrap();
+    assert!(log.contains("INFO  ytdl::aggregator: Download started"));
+    assert!(log.contains(r#"DEBUG ytdl::aggregator: Download destination is known"#));
+    assert!(log.contains(r#"path="/videos/Some Video.webm""#));
+    assert!(log.contains("Download process exited"));
+}